@@ -0,0 +1,277 @@
+use image::{Rgba, RgbaImage};
+use std::convert::TryFrom;
+use std::fmt;
+use std::net::{IpAddr, Ipv6Addr};
+use std::str::FromStr;
+
+use crate::error::Error;
+
+/// The upper 64 bits of the addresses a screen listens on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Prefix(pub [u16; 4]);
+
+impl Prefix {
+    /// The prefix used by the original Jinglepings screen.
+    pub const DEFAULT: Prefix = Prefix([0x2001, 0x610, 0x1908, 0xa000]);
+}
+
+impl Default for Prefix {
+    fn default() -> Self {
+        Prefix::DEFAULT
+    }
+}
+
+impl fmt::Display for Prefix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let [a, b, c, d] = self.0;
+        write!(f, "{:x}:{:x}:{:x}:{:x}", a, b, c, d)
+    }
+}
+
+impl FromStr for Prefix {
+    type Err = Error;
+
+    /// Parse a /64 prefix. This accepts the four groups on their own
+    /// (`2001:610:1908:a000`) as well as regular IPv6 notation with the lower
+    /// 64 bits left empty (`2001:610:1908:a000::` or `2001:610:1908:a000::/64`).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| Error::InvalidPrefix(format!("'{}' {}", s, reason));
+
+        let address = match s.find('/') {
+            Some(idx) if &s[idx + 1..] == "64" => &s[..idx],
+            Some(_) => return Err(invalid("is not a /64 prefix")),
+            None => s,
+        };
+
+        // The four group form is not a valid IPv6 address on its own, so we'll
+        // have to fill in the missing groups ourselves
+        let parsed = address
+            .parse::<Ipv6Addr>()
+            .or_else(|_| format!("{}::", address).parse::<Ipv6Addr>())
+            .map_err(|_| invalid("is not a valid IPv6 prefix"))?;
+
+        let segments = parsed.segments();
+        if segments[4..].iter().any(|&segment| segment != 0) {
+            return Err(invalid(
+                "contains more than 64 bits, only the first four groups should be specified",
+            ));
+        }
+
+        Ok(Prefix([segments[0], segments[1], segments[2], segments[3]]))
+    }
+}
+
+/// How a pixel's color is packed into the last 32 bits of an address. The name
+/// lists the channels in the order they appear in the address, from the most
+/// significant byte of the seventh group to the least significant byte of the
+/// eighth group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PixelLayout {
+    /// `(b << 8) | g` followed by `(r << 8) | a`. This is what the original
+    /// Jinglepings screen uses.
+    #[default]
+    Bgra16,
+    /// `(r << 8) | g` followed by `(b << 8) | a`.
+    Rgba16,
+    /// `(a << 8) | r` followed by `(g << 8) | b`.
+    Argb16,
+    /// `(a << 8) | b` followed by `(g << 8) | r`.
+    Abgr16,
+}
+
+impl PixelLayout {
+    /// The two address groups for a pixel in this layout.
+    fn pack(self, pixel: &Rgba<u8>) -> (u16, u16) {
+        let group = |high: u8, low: u8| (u16::from(high) << 8) | u16::from(low);
+        let &Rgba([r, g, b, a]) = pixel;
+        match self {
+            PixelLayout::Bgra16 => (group(b, g), group(r, a)),
+            PixelLayout::Rgba16 => (group(r, g), group(b, a)),
+            PixelLayout::Argb16 => (group(a, r), group(g, b)),
+            PixelLayout::Abgr16 => (group(a, b), group(g, r)),
+        }
+    }
+}
+
+/// Build an IPv6 address for writing a pixel. `x` and `y` should correspond to
+/// some pixel on the screen listening on `prefix`, and the pixel's color gets
+/// packed according to `layout`. Every channel always takes up a full byte, so
+/// colors reduced with [`reduce_color_depth()`](crate::reduce_color_depth) are
+/// sent scaled back up to 8 bits, while the alpha byte is sent as is.
+pub fn build_address(
+    prefix: Prefix,
+    layout: PixelLayout,
+    x: u16,
+    y: u16,
+    pixel: &Rgba<u8>,
+) -> IpAddr {
+    IpAddr::V6(ipv6_address(prefix, layout, x, y, pixel))
+}
+
+/// The screen coordinates and the address for every pixel in `image` when it's
+/// drawn with its top left corner at `origin`, row by row. This only computes
+/// what would be sent, so it can be used to send the pings some other way or
+/// to analyze them. Completely transparent pixels are left out if
+/// `skip_transparent` is set, just like when drawing. Pixels that would end up
+/// past the largest possible coordinates are always left out.
+pub fn image_addresses(
+    image: &RgbaImage,
+    origin: (u16, u16),
+    prefix: Prefix,
+    layout: PixelLayout,
+    skip_transparent: bool,
+) -> impl Iterator<Item = (u16, u16, Ipv6Addr)> + '_ {
+    image
+        .enumerate_pixels()
+        .filter(move |(_, _, pixel)| !skip_transparent || pixel[3] > 0)
+        .filter_map(move |(x, y, pixel)| {
+            let x = u16::try_from(u32::from(origin.0) + x).ok()?;
+            let y = u16::try_from(u32::from(origin.1) + y).ok()?;
+
+            Some((x, y, ipv6_address(prefix, layout, x, y, pixel)))
+        })
+}
+
+fn ipv6_address(prefix: Prefix, layout: PixelLayout, x: u16, y: u16, pixel: &Rgba<u8>) -> Ipv6Addr {
+    let Prefix([p1, p2, p3, p4]) = prefix;
+    let (color_high, color_low) = layout.pack(pixel);
+
+    Ipv6Addr::new(p1, p2, p3, p4, x, y, color_high, color_low)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The last four bytes of the address for a pixel with distinct channels.
+    fn color_bytes(layout: PixelLayout) -> [u8; 4] {
+        let pixel = Rgba([0x11, 0x22, 0x33, 0x44]);
+        match build_address(Prefix::DEFAULT, layout, 0x0102, 0x0304, &pixel) {
+            IpAddr::V6(address) => {
+                let octets = address.octets();
+                assert_eq!(
+                    octets[..8],
+                    [0x20, 0x01, 0x06, 0x10, 0x19, 0x08, 0xa0, 0x00]
+                );
+                assert_eq!(octets[8..12], [0x01, 0x02, 0x03, 0x04]);
+                [octets[12], octets[13], octets[14], octets[15]]
+            }
+            IpAddr::V4(_) => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn packs_bgra16() {
+        assert_eq!(color_bytes(PixelLayout::Bgra16), [0x33, 0x22, 0x11, 0x44]);
+    }
+
+    #[test]
+    fn packs_rgba16() {
+        assert_eq!(color_bytes(PixelLayout::Rgba16), [0x11, 0x22, 0x33, 0x44]);
+    }
+
+    #[test]
+    fn packs_argb16() {
+        assert_eq!(color_bytes(PixelLayout::Argb16), [0x44, 0x11, 0x22, 0x33]);
+    }
+
+    #[test]
+    fn packs_abgr16() {
+        assert_eq!(color_bytes(PixelLayout::Abgr16), [0x44, 0x33, 0x22, 0x11]);
+    }
+
+    #[test]
+    fn builds_addresses_at_the_edges() {
+        let address =
+            |x, y, pixel| build_address(Prefix::DEFAULT, PixelLayout::Bgra16, x, y, &pixel);
+
+        assert_eq!(
+            address(0, 0, Rgba([0, 0, 0, 0])),
+            "2001:610:1908:a000::".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(
+            address(u16::MAX, u16::MAX, Rgba([255, 255, 255, 255])),
+            "2001:610:1908:a000:ffff:ffff:ffff:ffff"
+                .parse::<IpAddr>()
+                .unwrap()
+        );
+        assert_eq!(
+            address(1919, 1079, Rgba([255, 0, 0, 255])),
+            "2001:610:1908:a000:77f:437:0:ffff"
+                .parse::<IpAddr>()
+                .unwrap()
+        );
+        assert_eq!(
+            address(42, 7, Rgba([0, 0, 255, 128])),
+            "2001:610:1908:a000:2a:7:ff00:80".parse::<IpAddr>().unwrap()
+        );
+    }
+
+    #[test]
+    fn uses_the_prefix() {
+        let prefix: Prefix = "fd00:1:2:3".parse().unwrap();
+        assert_eq!(
+            build_address(prefix, PixelLayout::Bgra16, 1, 2, &Rgba([1, 2, 3, 4])),
+            "fd00:1:2:3:1:2:302:104".parse::<IpAddr>().unwrap()
+        );
+    }
+
+    #[test]
+    fn packs_channels_without_overlap() {
+        // Every channel should end up in its own byte, so a single set channel
+        // only ever shows up in one place
+        for channel in 0..4 {
+            for &value in &[1, 0x80, 0xff] {
+                let mut pixel = Rgba([0; 4]);
+                pixel[channel] = value;
+                let (high, low) = PixelLayout::Bgra16.pack(&pixel);
+                let bytes = [(high >> 8) as u8, high as u8, (low >> 8) as u8, low as u8];
+                assert_eq!(bytes.iter().filter(|&&byte| byte != 0).count(), 1);
+                assert_eq!(bytes[[2, 1, 0, 3][channel]], value);
+            }
+        }
+    }
+
+    #[test]
+    fn lists_image_addresses() {
+        let image = RgbaImage::from_fn(2, 1, |x, _| match x {
+            0 => Rgba([0, 0, 0, 0]),
+            _ => Rgba([0x11, 0x22, 0x33, 0x44]),
+        });
+        let address = |x| Ipv6Addr::new(0x2001, 0x610, 0x1908, 0xa000, x, 5, 0x3322, 0x1144);
+        let addresses = |skip_transparent| {
+            image_addresses(
+                &image,
+                (10, 5),
+                Prefix::DEFAULT,
+                PixelLayout::Bgra16,
+                skip_transparent,
+            )
+            .collect::<Vec<_>>()
+        };
+
+        assert_eq!(addresses(true), vec![(11, 5, address(11))]);
+        assert_eq!(
+            addresses(false),
+            vec![
+                (
+                    10,
+                    5,
+                    Ipv6Addr::new(0x2001, 0x610, 0x1908, 0xa000, 10, 5, 0, 0)
+                ),
+                (11, 5, address(11)),
+            ]
+        );
+
+        // Everything past the last possible column gets dropped
+        let clipped: Vec<_> = image_addresses(
+            &image,
+            (u16::MAX, 0),
+            Prefix::DEFAULT,
+            PixelLayout::Bgra16,
+            false,
+        )
+        .collect();
+        assert_eq!(clipped.len(), 1);
+    }
+}
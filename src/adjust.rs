@@ -0,0 +1,100 @@
+use image::{imageops, RgbaImage};
+
+/// The standard deviation of the blur [`sharpen()`] subtracts from the image.
+const SHARPEN_SIGMA: f32 = 1.0;
+/// The smallest difference [`sharpen()`] will enhance, so flat areas don't get
+/// noisy.
+const SHARPEN_THRESHOLD: i32 = 2;
+
+/// Color corrections for images that would otherwise look too bright or washed
+/// out on the screen. The default values leave the image untouched.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Adjustments {
+    /// The amount added to every color channel, between -255 and 255.
+    pub brightness: i32,
+    /// The contrast adjustment in percent. Negative values decrease the
+    /// contrast and positive values increase it.
+    pub contrast: f32,
+    /// The gamma correction factor. Values above 1 brighten the midtones and
+    /// values below 1 darken them.
+    pub gamma: f32,
+}
+
+impl Default for Adjustments {
+    fn default() -> Self {
+        Adjustments {
+            brightness: 0,
+            contrast: 0.0,
+            gamma: 1.0,
+        }
+    }
+}
+
+/// Apply `adjustments` to `image`. The brightness is changed first, then the
+/// contrast, and the gamma correction is applied last. Every step clamps its
+/// results to the valid range, and the alpha channel is left alone.
+pub fn adjust_colors(image: &mut RgbaImage, adjustments: Adjustments) {
+    if adjustments.brightness != 0 {
+        *image = imageops::brighten(image, adjustments.brightness);
+    }
+
+    if adjustments.contrast != 0.0 {
+        // `imageops::contrast()` also changes the alpha channel, so we have to
+        // restore that afterwards
+        let adjusted = imageops::contrast(image, adjustments.contrast);
+        for (pixel, adjusted) in image.pixels_mut().zip(adjusted.pixels()) {
+            pixel[0] = adjusted[0];
+            pixel[1] = adjusted[1];
+            pixel[2] = adjusted[2];
+        }
+    }
+
+    if adjustments.gamma != 1.0 {
+        let mut table = [0u8; 256];
+        for (value, entry) in table.iter_mut().enumerate() {
+            let corrected = (value as f32 / 255.0).powf(1.0 / adjustments.gamma) * 255.0;
+            *entry = corrected.round().clamp(0.0, 255.0) as u8;
+        }
+
+        for pixel in image.pixels_mut() {
+            for channel in &mut pixel.0[..3] {
+                *channel = table[*channel as usize];
+            }
+        }
+    }
+}
+
+/// Apply a Gaussian blur with a standard deviation of `sigma` pixels to
+/// `image`. The alpha channel is blurred as well, so hard edges get softened.
+pub fn blur(image: &mut RgbaImage, sigma: f32) {
+    *image = imageops::blur(image, sigma);
+}
+
+/// Sharpen `image` with an unsharp mask while keeping its alpha channel.
+pub fn sharpen(image: &mut RgbaImage) {
+    let sharpened = imageops::unsharpen(image, SHARPEN_SIGMA, SHARPEN_THRESHOLD);
+    for (pixel, sharpened) in image.pixels_mut().zip(sharpened.pixels()) {
+        pixel[0] = sharpened[0];
+        pixel[1] = sharpened[1];
+        pixel[2] = sharpened[2];
+    }
+}
+
+/// Convert `image` to grayscale while keeping its alpha channel.
+pub fn to_grayscale(image: &mut RgbaImage) {
+    let gray = imageops::grayscale(image);
+    for (pixel, gray) in image.pixels_mut().zip(gray.pixels()) {
+        pixel[0] = gray[0];
+        pixel[1] = gray[0];
+        pixel[2] = gray[0];
+    }
+}
+
+/// Invert the colors in `image` while keeping its alpha channel.
+pub fn invert_colors(image: &mut RgbaImage) {
+    for pixel in image.pixels_mut() {
+        for channel in &mut pixel.0[..3] {
+            *channel = 255 - *channel;
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use image::{Rgba, RgbaImage};
+
+/// How semitransparent pixels get sent to the screen. The alpha channel ends up
+/// in the address built by [`build_address()`](crate::build_address), and
+/// completely transparent pixels are never sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AlphaMode {
+    /// Send every pixel with its alpha value as is, leaving the blending up to
+    /// the screen.
+    #[default]
+    SkipZero,
+    /// Treat pixels with an alpha value below the threshold as completely
+    /// transparent, and send all other pixels with an alpha value of 255.
+    Threshold(u8),
+    /// Blend pixels with the background color and send them with an alpha
+    /// value of 255. Completely transparent pixels are still skipped.
+    Premultiply(Rgba<u8>),
+    /// Composite the entire image over the background color, so every pixel
+    /// gets sent with an alpha value of 255. Nothing is skipped, including
+    /// completely transparent pixels.
+    Flatten(Rgba<u8>),
+}
+
+/// Change the alpha values in `image` according to `mode`.
+pub fn apply_alpha_mode(image: &mut RgbaImage, mode: AlphaMode) {
+    match mode {
+        AlphaMode::SkipZero => (),
+        AlphaMode::Threshold(threshold) => {
+            for pixel in image.pixels_mut() {
+                pixel[3] = if pixel[3] < threshold { 0 } else { 255 };
+            }
+        }
+        AlphaMode::Premultiply(background) => {
+            for pixel in image.pixels_mut().filter(|pixel| pixel[3] > 0) {
+                *pixel = blend(*pixel, background);
+            }
+        }
+        AlphaMode::Flatten(background) => {
+            for pixel in image.pixels_mut() {
+                *pixel = blend(*pixel, background);
+            }
+        }
+    }
+}
+
+/// Multiply the alpha value of every pixel in `image` by `opacity`, which should
+/// be between 0 and 1. Pixels whose alpha value ends up at 0 become completely
+/// transparent, so they won't be sent unless the image gets flattened
+/// afterwards.
+pub fn scale_alpha(image: &mut RgbaImage, opacity: f32) {
+    for pixel in image.pixels_mut() {
+        pixel[3] = (f32::from(pixel[3]) * opacity).round() as u8;
+    }
+}
+
+/// Fade the alpha values in `image` out toward its edges, so it blends into
+/// the screen instead of ending in hard rectangular edges. `strength` should be
+/// between 0 and 1, and it's the fraction of the distance from the center to
+/// the edges the fade covers. Pixels outside of the ellipse touching the
+/// image's edges become completely transparent, so combined with
+/// [`AlphaMode::Threshold`] this leaves an oval cutout.
+pub fn vignette(image: &mut RgbaImage, strength: f32) {
+    let (half_width, half_height) = (image.width() as f32 / 2.0, image.height() as f32 / 2.0);
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        // The distance to the center relative to the ellipse, using the
+        // pixel's center
+        let dx = (x as f32 + 0.5 - half_width) / half_width;
+        let dy = (y as f32 + 0.5 - half_height) / half_height;
+        let distance = (dx * dx + dy * dy).sqrt();
+
+        // A smoothstep falloff doesn't leave a visible ring where the fade
+        // starts
+        let t = ((1.0 - distance) / strength.max(f32::EPSILON)).clamp(0.0, 1.0);
+        let factor = t * t * (3.0 - 2.0 * t);
+        pixel[3] = (f32::from(pixel[3]) * factor).round() as u8;
+    }
+}
+
+/// Blend `pixel` with an opaque `background`, returning an opaque pixel.
+fn blend(pixel: Rgba<u8>, background: Rgba<u8>) -> Rgba<u8> {
+    let alpha = u32::from(pixel[3]);
+    let channel = |idx: usize| {
+        ((u32::from(pixel[idx]) * alpha + u32::from(background[idx]) * (255 - alpha) + 127) / 255)
+            as u8
+    };
+
+    Rgba([channel(0), channel(1), channel(2), 255])
+}
+
+/// Make every pixel within `tolerance` of `key` completely transparent. The
+/// distance between two colors is the Euclidean distance between their red,
+/// green, and blue values, so a tolerance of 0 only matches the key color
+/// itself and the largest possible distance is about 441.
+pub fn chroma_key(image: &mut RgbaImage, key: Rgba<u8>, tolerance: f32) {
+    for pixel in image.pixels_mut() {
+        let distance = (0..3)
+            .map(|idx| (f32::from(pixel[idx]) - f32::from(key[idx])).powi(2))
+            .sum::<f32>()
+            .sqrt();
+        if distance <= tolerance {
+            pixel[3] = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fades_out_toward_the_edges() {
+        let opaque = RgbaImage::from_pixel(10, 10, Rgba([255, 0, 0, 255]));
+
+        let mut image = opaque.clone();
+        vignette(&mut image, 0.5);
+        // The pixels around the center stay opaque, and the fade gets stronger
+        // toward the edges until the corners are completely transparent
+        assert_eq!(image.get_pixel(5, 5)[3], 255);
+        assert_eq!(image.get_pixel(4, 4)[3], 255);
+        assert!(image.get_pixel(5, 1)[3] < image.get_pixel(5, 3)[3]);
+        assert!(image.get_pixel(5, 1)[3] > 0);
+        for &(x, y) in &[(0, 0), (9, 0), (0, 9), (9, 9)] {
+            assert_eq!(image.get_pixel(x, y)[3], 0);
+        }
+        // Only the alpha channel changes
+        assert!(image.pixels().all(|pixel| pixel[0] == 255));
+
+        // Without any fade only the pixels outside of the ellipse get cut off
+        let mut image = opaque;
+        vignette(&mut image, 0.0);
+        assert!(image.pixels().all(|pixel| pixel[3] == 0 || pixel[3] == 255));
+        assert_eq!(image.get_pixel(5, 0)[3], 255);
+        assert_eq!(image.get_pixel(0, 0)[3], 0);
+    }
+}
@@ -0,0 +1,673 @@
+use gif::SetParameter;
+use image::{DynamicImage, ImageError, ImageFormat, Rgba, RgbaImage};
+use std::cmp::Ordering;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::decode::decode_image;
+use crate::error::Error;
+
+/// A single frame of an animation. The image is shown for `delay` before
+/// moving on to the next frame.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub image: RgbaImage,
+    pub delay: Duration,
+}
+
+/// The color pixels that became transparent are cleared to by
+/// [`frame_difference()`].
+pub const CLEAR_COLOR: Rgba<u8> = Rgba([0, 0, 0, 255]);
+
+/// Compute the pixels that have to be drawn to turn `previous` into `current`.
+/// Pixels that didn't change are made completely transparent so they won't be
+/// sent, and pixels that became transparent are set to [`CLEAR_COLOR`]. The
+/// returned image is large enough to hold both frames.
+pub fn frame_difference(previous: &RgbaImage, current: &RgbaImage) -> RgbaImage {
+    let transparent = Rgba([0, 0, 0, 0]);
+    let pixel_at = |image: &RgbaImage, x: u32, y: u32| {
+        if x < image.width() && y < image.height() {
+            *image.get_pixel(x, y)
+        } else {
+            transparent
+        }
+    };
+
+    RgbaImage::from_fn(
+        previous.width().max(current.width()),
+        previous.height().max(current.height()),
+        |x, y| {
+            let old = pixel_at(previous, x, y);
+            let new = pixel_at(current, x, y);
+            match (old[3], new[3]) {
+                (0, 0) => transparent,
+                (_, 0) => CLEAR_COLOR,
+                _ if old == new => transparent,
+                _ => new,
+            }
+        },
+    )
+}
+
+/// Decode every frame in a GIF file. The GIF format allows frames to only
+/// update part of the image and to specify what should happen to those pixels
+/// afterwards, so we'll composite every frame on top of the previous ones. The
+/// returned frames are thus all full images of the same size.
+///
+/// We don't use `image`'s own GIF decoder for this since it does not handle the
+/// 'restore to previous' disposal method.
+pub fn decode_gif<R: Read>(reader: R) -> Result<Vec<Frame>, Error> {
+    let mut decoder = gif::Decoder::new(reader);
+    decoder.set(gif::ColorOutput::RGBA);
+    let mut reader = decoder.read_info().map_err(ImageError::from)?;
+
+    let mut canvas = RgbaImage::new(u32::from(reader.width()), u32::from(reader.height()));
+    let mut frames = Vec::new();
+    while let Some(frame) = reader.read_next_frame().map_err(ImageError::from)? {
+        let left = u32::from(frame.left);
+        let top = u32::from(frame.top);
+        let width = u32::from(frame.width);
+        let height = u32::from(frame.height);
+
+        let previous = match frame.dispose {
+            gif::DisposalMethod::Previous => Some(canvas.clone()),
+            _ => None,
+        };
+
+        // Transparent pixels in a frame leave the pixels of the frames below
+        // them intact. Frames are allowed to extend past the logical screen,
+        // so we have to clip them.
+        for (idx, pixel) in frame.buffer.chunks_exact(4).enumerate() {
+            let x = left + (idx as u32 % width);
+            let y = top + (idx as u32 / width);
+            if pixel[3] > 0 && x < canvas.width() && y < canvas.height() {
+                canvas.put_pixel(x, y, Rgba([pixel[0], pixel[1], pixel[2], pixel[3]]));
+            }
+        }
+
+        // The delay is specified in hundredths of a second. Browsers treat very
+        // short delays as 100 milliseconds since a lot of GIFs in the wild
+        // rely on this, so we'll do the same.
+        let delay = match frame.delay {
+            0 | 1 => Duration::from_millis(100),
+            delay => Duration::from_millis(u64::from(delay) * 10),
+        };
+        frames.push(Frame {
+            image: canvas.clone(),
+            delay,
+        });
+
+        match frame.dispose {
+            gif::DisposalMethod::Any | gif::DisposalMethod::Keep => (),
+            gif::DisposalMethod::Background => {
+                for y in top..(top + height).min(canvas.height()) {
+                    for x in left..(left + width).min(canvas.width()) {
+                        canvas.put_pixel(x, y, Rgba([0, 0, 0, 0]));
+                    }
+                }
+            }
+            gif::DisposalMethod::Previous => canvas = previous.unwrap(),
+        }
+    }
+
+    Ok(frames)
+}
+
+/// Decode every frame in an animated PNG file. Like with GIFs, frames can
+/// update only part of the image, so they're composited on top of each other
+/// the same way [`decode_gif()`] does it. A regular PNG file without an
+/// animation is returned as a single frame.
+///
+/// The `png` crate parses the animation chunks but can't decode frames smaller
+/// than the image, so every frame gets decoded as a standalone PNG file made
+/// out of the frame's size, the original image's other header chunks, and the
+/// frame's image data.
+pub fn decode_apng(data: &[u8]) -> Result<Vec<Frame>, Error> {
+    let chunks = png_chunks(data)?;
+    if !chunks.iter().any(|(kind, _)| kind == b"acTL") {
+        let image = decode_image(data, ImageFormat::PNG)?;
+        return Ok(vec![Frame {
+            image: image.to_rgba(),
+            delay: Duration::from_millis(100),
+        }]);
+    }
+
+    // The header contains everything up to the first frame's image data,
+    // except for the chunks that only matter for the animation. If there's no
+    // frame control chunk before the `IDAT` chunks then the default image is
+    // not part of the animation.
+    let mut ihdr: &[u8] = &[];
+    let mut header = Vec::new();
+    let mut frames: Vec<(ApngFrameControl, Vec<u8>)> = Vec::new();
+    let mut seen_data = false;
+    for (kind, body) in &chunks {
+        match kind {
+            b"IHDR" => ihdr = body,
+            b"acTL" => (),
+            b"fcTL" => frames.push((ApngFrameControl::parse(body)?, Vec::new())),
+            b"IDAT" => {
+                seen_data = true;
+                if let Some((_, frame_data)) = frames.last_mut() {
+                    frame_data.extend_from_slice(body);
+                }
+            }
+            b"fdAT" => match (frames.last_mut(), body.get(4..)) {
+                (Some((_, frame_data)), Some(body)) => frame_data.extend_from_slice(body),
+                _ => return Err(malformed_apng("frame data without a frame control chunk")),
+            },
+            _ if !seen_data => header.push((*kind, *body)),
+            _ => (),
+        }
+    }
+    if ihdr.len() != 13 {
+        return Err(malformed_apng("missing image header"));
+    }
+
+    let width = u32::from_be_bytes([ihdr[0], ihdr[1], ihdr[2], ihdr[3]]);
+    let height = u32::from_be_bytes([ihdr[4], ihdr[5], ihdr[6], ihdr[7]]);
+    let mut canvas = RgbaImage::new(width, height);
+    let mut result = Vec::new();
+    for (control, frame_data) in frames {
+        let mut frame_ihdr = ihdr.to_vec();
+        frame_ihdr[0..4].copy_from_slice(&control.width.to_be_bytes());
+        frame_ihdr[4..8].copy_from_slice(&control.height.to_be_bytes());
+
+        let mut png = PNG_SIGNATURE.to_vec();
+        write_png_chunk(&mut png, b"IHDR", &frame_ihdr);
+        for (kind, body) in &header {
+            write_png_chunk(&mut png, kind, body);
+        }
+        write_png_chunk(&mut png, b"IDAT", &frame_data);
+        write_png_chunk(&mut png, b"IEND", &[]);
+        let image = decode_image(&png, ImageFormat::PNG)?.to_rgba();
+
+        let previous = match control.dispose_op {
+            APNG_DISPOSE_OP_PREVIOUS => Some(canvas.clone()),
+            _ => None,
+        };
+
+        // Frames have to lie within the image, but we'll clip them anyway
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let (x, y) = (control.x_offset + x, control.y_offset + y);
+            if x < width && y < height {
+                let pixel = match control.blend_op {
+                    APNG_BLEND_OP_SOURCE => *pixel,
+                    _ => blend_over(*canvas.get_pixel(x, y), *pixel),
+                };
+                canvas.put_pixel(x, y, pixel);
+            }
+        }
+
+        // The delay is a fraction of a second, and a denominator of zero means
+        // hundredths of a second. Very short delays are treated the same way
+        // as they are for GIFs.
+        let denominator = match control.delay_den {
+            0 => 100,
+            denominator => u64::from(denominator),
+        };
+        let delay = match u64::from(control.delay_num) * 1000 / denominator {
+            0..=10 => Duration::from_millis(100),
+            millis => Duration::from_millis(millis),
+        };
+        result.push(Frame {
+            image: canvas.clone(),
+            delay,
+        });
+
+        match (control.dispose_op, previous) {
+            (APNG_DISPOSE_OP_PREVIOUS, Some(previous)) => canvas = previous,
+            (APNG_DISPOSE_OP_BACKGROUND, _) => {
+                for y in control.y_offset..(control.y_offset + control.height).min(height) {
+                    for x in control.x_offset..(control.x_offset + control.width).min(width) {
+                        canvas.put_pixel(x, y, Rgba([0, 0, 0, 0]));
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    if result.is_empty() {
+        return Err(malformed_apng("the animation does not contain any frames"));
+    }
+
+    Ok(result)
+}
+
+/// The number of times an animated GIF or PNG file asks to be played, or
+/// `None` if it should loop forever. GIFs store this in the NETSCAPE2.0
+/// application extension as the number of times the animation repeats after
+/// it has been played once, which is how browsers interpret it. GIFs without
+/// that extension are played once. Animated PNGs store the total number of
+/// plays in the `acTL` chunk. Anything else loops forever.
+pub fn play_count(data: &[u8]) -> Option<usize> {
+    if data.starts_with(PNG_SIGNATURE) {
+        let chunks = png_chunks(data).ok()?;
+        let (_, animation_control) = chunks.iter().find(|(kind, _)| kind == b"acTL")?;
+        match animation_control.get(4..8)? {
+            [0, 0, 0, 0] => None,
+            plays => Some(u32::from_be_bytes([plays[0], plays[1], plays[2], plays[3]]) as usize),
+        }
+    } else if data.starts_with(b"GIF") {
+        match gif_loop_count(data) {
+            None => Some(1),
+            Some(0) => None,
+            Some(loops) => Some(usize::from(loops) + 1),
+        }
+    } else {
+        None
+    }
+}
+
+/// Find the loop count in a GIF file's NETSCAPE2.0 application extension. The
+/// `gif` crate skips over extensions it doesn't know, so this walks over the
+/// file's blocks until it finds one.
+fn gif_loop_count(data: &[u8]) -> Option<u16> {
+    // The header and the logical screen descriptor are followed by the global
+    // color table, if there is one
+    let flags = *data.get(10)?;
+    let mut position = 13;
+    if flags & 0x80 != 0 {
+        position += 3 << ((flags & 0x07) + 1);
+    }
+
+    loop {
+        match *data.get(position)? {
+            // An extension's label is followed by its data sub-blocks
+            0x21 => {
+                let label = *data.get(position + 1)?;
+                let (blocks, end) = gif_sub_blocks(data, position + 2)?;
+                if label == 0xff {
+                    if let [b"NETSCAPE2.0" | b"ANIMEXTS1.0", [1, low, high], ..] = blocks[..] {
+                        return Some(u16::from_le_bytes([*low, *high]));
+                    }
+                }
+                position = end;
+            }
+            // An image descriptor is followed by an optional local color
+            // table, the minimum LZW code size, and the image data sub-blocks
+            0x2c => {
+                let flags = *data.get(position + 9)?;
+                position += 10;
+                if flags & 0x80 != 0 {
+                    position += 3 << ((flags & 0x07) + 1);
+                }
+                let (_, end) = gif_sub_blocks(data, position + 1)?;
+                position = end;
+            }
+            _ => return None,
+        }
+    }
+}
+
+/// The data sub-blocks starting at `position` in a GIF file, along with the
+/// position right after the block terminator.
+fn gif_sub_blocks(data: &[u8], mut position: usize) -> Option<(Vec<&[u8]>, usize)> {
+    let mut blocks = Vec::new();
+    loop {
+        let length = usize::from(*data.get(position)?);
+        if length == 0 {
+            return Some((blocks, position + 1));
+        }
+        blocks.push(data.get(position + 1..position + 1 + length)?);
+        position += 1 + length;
+    }
+}
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+const APNG_DISPOSE_OP_BACKGROUND: u8 = 1;
+const APNG_DISPOSE_OP_PREVIOUS: u8 = 2;
+const APNG_BLEND_OP_SOURCE: u8 = 0;
+
+/// The contents of an APNG `fcTL` chunk, without the sequence number.
+#[derive(Debug, Clone, Copy)]
+struct ApngFrameControl {
+    width: u32,
+    height: u32,
+    x_offset: u32,
+    y_offset: u32,
+    delay_num: u16,
+    delay_den: u16,
+    dispose_op: u8,
+    blend_op: u8,
+}
+
+impl ApngFrameControl {
+    fn parse(body: &[u8]) -> Result<Self, Error> {
+        if body.len() != 26 {
+            return Err(malformed_apng("invalid frame control chunk"));
+        }
+
+        let u32_at = |idx: usize| {
+            u32::from_be_bytes([body[idx], body[idx + 1], body[idx + 2], body[idx + 3]])
+        };
+        let u16_at = |idx: usize| u16::from_be_bytes([body[idx], body[idx + 1]]);
+        Ok(ApngFrameControl {
+            width: u32_at(4),
+            height: u32_at(8),
+            x_offset: u32_at(12),
+            y_offset: u32_at(16),
+            delay_num: u16_at(20),
+            delay_den: u16_at(22),
+            dispose_op: body[24],
+            blend_op: body[25],
+        })
+    }
+}
+
+/// A PNG chunk's type and contents.
+type PngChunk<'a> = ([u8; 4], &'a [u8]);
+
+/// Split a PNG file into its chunks. The checksums are left for the PNG decoder
+/// to verify.
+fn png_chunks(data: &[u8]) -> Result<Vec<PngChunk<'_>>, Error> {
+    let mut rest = data
+        .strip_prefix(PNG_SIGNATURE)
+        .ok_or_else(|| malformed_apng("missing PNG signature"))?;
+    let mut chunks = Vec::new();
+    while rest.len() >= 12 {
+        let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        let kind = [rest[4], rest[5], rest[6], rest[7]];
+        let body = rest
+            .get(8..8 + length)
+            .ok_or_else(|| malformed_apng("truncated chunk"))?;
+        chunks.push((kind, body));
+        rest = rest.get(12 + length..).unwrap_or(&[]);
+        if &kind == b"IEND" {
+            break;
+        }
+    }
+
+    Ok(chunks)
+}
+
+fn write_png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], body: &[u8]) {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(kind);
+    hasher.update(body);
+    png.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    png.extend_from_slice(kind);
+    png.extend_from_slice(body);
+    png.extend_from_slice(&hasher.finalize().to_be_bytes());
+}
+
+fn malformed_apng(reason: &str) -> Error {
+    Error::Image(ImageError::FormatError(format!(
+        "Malformed animated PNG: {}",
+        reason
+    )))
+}
+
+/// Draw `source` over `backdrop` using regular alpha compositing, like the
+/// APNG `APNG_BLEND_OP_OVER` blend operation.
+fn blend_over(backdrop: Rgba<u8>, source: Rgba<u8>) -> Rgba<u8> {
+    match (backdrop[3], source[3]) {
+        (_, 255) | (0, _) => source,
+        (_, 0) => backdrop,
+        _ => {
+            let source_alpha = f32::from(source[3]) / 255.0;
+            let backdrop_alpha = f32::from(backdrop[3]) / 255.0 * (1.0 - source_alpha);
+            let alpha = source_alpha + backdrop_alpha;
+            let mut result = Rgba([0, 0, 0, (alpha * 255.0).round() as u8]);
+            for channel in 0..3 {
+                let color = (f32::from(source[channel]) * source_alpha
+                    + f32::from(backdrop[channel]) * backdrop_alpha)
+                    / alpha;
+                result[channel] = color.round() as u8;
+            }
+
+            result
+        }
+    }
+}
+
+/// Load every file in `dir` as a frame of an animation, showing each frame for
+/// `delay`. Files are sorted by name with numbers compared by their value, so
+/// `frame_2.png` comes before `frame_10.png`. Hidden files are ignored. The
+/// frames can have different dimensions.
+pub fn load_sequence(dir: &Path, delay: Duration) -> Result<Vec<Frame>, Error> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir).map_err(ImageError::from)? {
+        let entry = entry.map_err(ImageError::from)?;
+        let hidden = entry.file_name().to_string_lossy().starts_with('.');
+        if !hidden && entry.path().is_file() {
+            paths.push(entry.path());
+        }
+    }
+    if paths.is_empty() {
+        return Err(Error::EmptySequence(dir.to_owned()));
+    }
+
+    paths.sort_by(|a, b| {
+        natural_cmp(
+            &a.file_name().unwrap().to_string_lossy(),
+            &b.file_name().unwrap().to_string_lossy(),
+        )
+    });
+    paths
+        .into_iter()
+        .map(|path| match open_image(&path) {
+            Ok(image) => Ok(Frame {
+                image: image.to_rgba(),
+                delay,
+            }),
+            Err(err) => Err(Error::Frame(path, err)),
+        })
+        .collect()
+}
+
+/// Open an image file, detecting its format from its contents.
+fn open_image(path: &Path) -> Result<DynamicImage, ImageError> {
+    let data = fs::read(path)?;
+    decode_image(&data, image::guess_format(&data)?)
+}
+
+/// Compare two strings while treating runs of digits as numbers.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        match (a.chars().next(), b.chars().next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let (x_digits, x_rest) = split_digits(a);
+                let (y_digits, y_rest) = split_digits(b);
+
+                // Comparing the lengths first lets us compare numbers of any
+                // length without parsing them
+                let x_number = x_digits.trim_start_matches('0');
+                let y_number = y_digits.trim_start_matches('0');
+                let ordering = x_number
+                    .len()
+                    .cmp(&y_number.len())
+                    .then_with(|| x_number.cmp(y_number))
+                    .then_with(|| x_digits.len().cmp(&y_digits.len()));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+
+                a = x_rest;
+                b = y_rest;
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(&y);
+                }
+
+                a = &a[x.len_utf8()..];
+                b = &b[y.len_utf8()..];
+            }
+        }
+    }
+}
+
+/// Split a string into its leading digits and the rest.
+fn split_digits(s: &str) -> (&str, &str) {
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    s.split_at(end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::png::PNGEncoder;
+    use image::ColorType;
+
+    /// The `IHDR` and `IDAT` chunks of a PNG file containing `image`.
+    fn encode_chunks(image: &RgbaImage) -> (Vec<u8>, Vec<u8>) {
+        let mut png = Vec::new();
+        PNGEncoder::new(&mut png)
+            .encode(image, image.width(), image.height(), ColorType::RGBA(8))
+            .unwrap();
+
+        let chunks = png_chunks(&png).unwrap();
+        let find = |kind: &[u8; 4]| {
+            chunks
+                .iter()
+                .filter(|(chunk_kind, _)| chunk_kind == kind)
+                .flat_map(|(_, body)| body.to_vec())
+                .collect()
+        };
+        (find(b"IHDR"), find(b"IDAT"))
+    }
+
+    fn frame_control(
+        sequence: u32,
+        size: (u32, u32),
+        offset: (u32, u32),
+        delay: (u16, u16),
+        dispose_op: u8,
+        blend_op: u8,
+    ) -> Vec<u8> {
+        let mut body = Vec::new();
+        for value in &[sequence, size.0, size.1, offset.0, offset.1] {
+            body.extend_from_slice(&value.to_be_bytes());
+        }
+        body.extend_from_slice(&delay.0.to_be_bytes());
+        body.extend_from_slice(&delay.1.to_be_bytes());
+        body.extend_from_slice(&[dispose_op, blend_op]);
+        body
+    }
+
+    /// A 2x2 animation with three frames. The first frame is the default image,
+    /// the second frame draws a translucent pixel over it and then gets cleared,
+    /// and the third frame replaces a pixel with a transparent one.
+    fn test_apng() -> Vec<u8> {
+        let red = Rgba([255, 0, 0, 255]);
+        let (ihdr, first) = encode_chunks(&RgbaImage::from_pixel(2, 2, red));
+        let (_, second) = encode_chunks(&RgbaImage::from_pixel(1, 1, Rgba([0, 0, 255, 128])));
+        let (_, third) = encode_chunks(&RgbaImage::from_pixel(1, 1, Rgba([0, 0, 0, 0])));
+
+        let mut png = PNG_SIGNATURE.to_vec();
+        write_png_chunk(&mut png, b"IHDR", &ihdr);
+        write_png_chunk(&mut png, b"acTL", &[0, 0, 0, 3, 0, 0, 0, 0]);
+        write_png_chunk(
+            &mut png,
+            b"fcTL",
+            &frame_control(0, (2, 2), (0, 0), (1, 2), 0, 0),
+        );
+        write_png_chunk(&mut png, b"IDAT", &first);
+        write_png_chunk(
+            &mut png,
+            b"fcTL",
+            &frame_control(1, (1, 1), (1, 1), (25, 0), 1, 1),
+        );
+        write_png_chunk(
+            &mut png,
+            b"fdAT",
+            &[&2u32.to_be_bytes()[..], &second].concat(),
+        );
+        write_png_chunk(
+            &mut png,
+            b"fcTL",
+            &frame_control(3, (1, 1), (0, 0), (0, 0), 0, 0),
+        );
+        write_png_chunk(
+            &mut png,
+            b"fdAT",
+            &[&4u32.to_be_bytes()[..], &third].concat(),
+        );
+        write_png_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+
+    #[test]
+    fn decodes_apng_frames() {
+        let frames = decode_apng(&test_apng()).unwrap();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(
+            frames.iter().map(|frame| frame.delay).collect::<Vec<_>>(),
+            vec![
+                Duration::from_millis(500),
+                Duration::from_millis(250),
+                Duration::from_millis(100),
+            ]
+        );
+
+        let red = Rgba([255, 0, 0, 255]);
+        assert!(frames[0].image.pixels().all(|pixel| *pixel == red));
+        assert_eq!(*frames[1].image.get_pixel(0, 0), red);
+        assert_eq!(*frames[1].image.get_pixel(1, 1), Rgba([127, 0, 128, 255]));
+        // The second frame's region gets cleared before the third frame
+        assert_eq!(*frames[2].image.get_pixel(1, 1), Rgba([0, 0, 0, 0]));
+        assert_eq!(*frames[2].image.get_pixel(0, 0), Rgba([0, 0, 0, 0]));
+        assert_eq!(*frames[2].image.get_pixel(1, 0), red);
+    }
+
+    /// A two frame 1x1 GIF, with `repeat` written to the NETSCAPE2.0
+    /// extension if set.
+    fn test_gif(repeat: Option<gif::Repeat>) -> Vec<u8> {
+        let mut data = Vec::new();
+        {
+            let mut encoder =
+                gif::Encoder::new(&mut data, 1, 1, &[0, 0, 0, 255, 255, 255]).unwrap();
+            if let Some(repeat) = repeat {
+                encoder.set(repeat).unwrap();
+            }
+            for &color in &[0, 1] {
+                encoder
+                    .write_frame(&gif::Frame::from_indexed_pixels(1, 1, &[color], None))
+                    .unwrap();
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn reads_play_counts() {
+        assert_eq!(play_count(&test_gif(None)), Some(1));
+        assert_eq!(play_count(&test_gif(Some(gif::Repeat::Infinite))), None);
+        assert_eq!(play_count(&test_gif(Some(gif::Repeat::Finite(2)))), Some(3));
+        assert_eq!(
+            decode_gif(&test_gif(Some(gif::Repeat::Finite(2)))[..])
+                .unwrap()
+                .len(),
+            2
+        );
+
+        // The test APNG loops forever
+        let mut png = test_apng();
+        assert_eq!(play_count(&png), None);
+        let position = png.windows(4).position(|window| window == b"acTL").unwrap();
+        png[position + 11] = 4;
+        assert_eq!(play_count(&png), Some(4));
+    }
+
+    #[test]
+    fn decodes_plain_png_as_single_frame() {
+        let image = RgbaImage::from_pixel(3, 2, Rgba([1, 2, 3, 4]));
+        let mut png = Vec::new();
+        PNGEncoder::new(&mut png)
+            .encode(&image, 3, 2, ColorType::RGBA(8))
+            .unwrap();
+
+        let frames = decode_apng(&png).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].image.dimensions(), (3, 2));
+        assert_eq!(frames[0].image.as_ref(), image.as_ref());
+    }
+}
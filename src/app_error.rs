@@ -0,0 +1,186 @@
+//! The errors that can make the `pingas` binary exit, and the exit codes they
+//! map to. Invalid command line arguments that clap catches while parsing them
+//! make clap exit with code 1. Arguments that can only be checked afterwards,
+//! like coordinates that depend on the screen's size, end up as
+//! [`AppError::Usage`] and exit with the same code.
+
+use clap::ErrorKind;
+use image::ImageError;
+use pingas::Error;
+use std::fmt;
+use std::io;
+
+/// Something went wrong, but it doesn't fit in any of the other categories.
+pub const EXIT_OTHER: i32 = 1;
+/// A file could not be opened or read.
+pub const EXIT_READ: i32 = 2;
+/// An image or an address list could not be decoded.
+pub const EXIT_DECODE: i32 = 3;
+/// The image does not fit on the screen.
+pub const EXIT_BOUNDS: i32 = 4;
+/// The pinger could not be set up, usually because of missing privileges, or
+/// its sockets could not be bound to the requested address or interface.
+pub const EXIT_PINGER: i32 = 5;
+/// An output file could not be written.
+pub const EXIT_WRITE: i32 = 6;
+/// Nothing would be drawn, because the image is completely transparent or
+/// because it lies entirely outside of the screen.
+pub const EXIT_EMPTY: i32 = 7;
+
+/// Everything that can go wrong in the `pingas` binary.
+#[derive(Debug)]
+pub enum AppError {
+    /// An error from the library.
+    Pingas(Error),
+    /// The command line arguments are invalid or conflict with each other.
+    Usage(clap::Error),
+    /// The config file could not be loaded.
+    Config(String),
+    /// The anchored image would end up outside of the screen.
+    Placement(String),
+    /// The addresses to replay could not be read.
+    ReadAddresses(String),
+    /// The addresses to replay were not in a format we understand.
+    DecodeAddresses(String),
+    /// The list of addresses to replay did not contain any valid addresses.
+    NoAddresses(String),
+    /// The `--positions` file could not be read.
+    ReadPositions(io::Error),
+    /// The `--positions` file did not contain any valid positions on the
+    /// screen.
+    NoPositions(String),
+    /// None of the image's pixels would be drawn.
+    NothingToDraw(String),
+    /// The preview image could not be saved.
+    WritePreview(io::Error),
+    /// The JSON output could not be written.
+    WriteJson(String),
+    /// The `--resume` file could not be loaded.
+    ReadState(String),
+    /// The `--state` file could not be written.
+    WriteState(String),
+    /// The Ctrl-C handler could not be installed.
+    Signal(ctrlc::Error),
+    /// The `SIGUSR1` and `SIGUSR2` handlers for pausing could not be installed.
+    PauseSignal(io::Error),
+    /// The image could not be watched for changes.
+    Watch(notify::Error),
+    /// The metrics server could not be started.
+    Metrics(io::Error),
+    /// The server for the `serve` subcommand could not be started.
+    Serve(io::Error),
+    /// The `--manifest` file or one of the images listed in it could not be
+    /// loaded.
+    Manifest(String),
+    /// The `--camera` device could not be opened.
+    Camera(io::Error),
+    /// The `--stream` could not be opened, or it ended before the first frame.
+    Stream(io::Error),
+}
+
+impl AppError {
+    /// An error for command line arguments that clap could not check on its
+    /// own, formatted the same way clap formats its errors.
+    pub fn usage(description: &str, kind: ErrorKind) -> Self {
+        AppError::Usage(clap::Error::with_description(description, kind))
+    }
+
+    /// The code the process should exit with after this error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            AppError::Pingas(err) => match err {
+                Error::Image(ImageError::IoError(_)) | Error::Frame(_, ImageError::IoError(_)) => {
+                    EXIT_READ
+                }
+                Error::EmptySequence(_) | Error::Font(_) | Error::Palette(_) => EXIT_READ,
+                Error::Image(_)
+                | Error::Frame(..)
+                | Error::UnrecognizedFormat
+                | Error::UnsupportedFormat(_)
+                | Error::MissingFeature { .. }
+                | Error::Svg(_)
+                | Error::EmptyImage => EXIT_DECODE,
+                Error::InvalidCrop { .. } | Error::OutOfBounds { .. } => EXIT_BOUNDS,
+                Error::Pinger(_) | Error::Bind(_) => EXIT_PINGER,
+                Error::InvalidColor(_) | Error::InvalidColorDepth(_) | Error::InvalidPrefix(_) => {
+                    EXIT_OTHER
+                }
+            },
+            AppError::Usage(_)
+            | AppError::Config(_)
+            | AppError::Signal(_)
+            | AppError::PauseSignal(_)
+            | AppError::Metrics(_)
+            | AppError::Serve(_) => EXIT_OTHER,
+            AppError::Placement(_) => EXIT_BOUNDS,
+            AppError::ReadAddresses(_)
+            | AppError::NoAddresses(_)
+            | AppError::ReadPositions(_)
+            | AppError::NoPositions(_)
+            | AppError::Watch(_)
+            | AppError::Manifest(_)
+            | AppError::Camera(_)
+            | AppError::Stream(_)
+            | AppError::ReadState(_) => EXIT_READ,
+            AppError::DecodeAddresses(_) => EXIT_DECODE,
+            AppError::NothingToDraw(_) => EXIT_EMPTY,
+            AppError::WritePreview(_) | AppError::WriteJson(_) | AppError::WriteState(_) => {
+                EXIT_WRITE
+            }
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AppError::Pingas(err) => write!(f, "{}", err),
+            AppError::Usage(err) => write!(f, "{}", err),
+            AppError::Config(err)
+            | AppError::Placement(err)
+            | AppError::Manifest(err)
+            | AppError::ReadState(err)
+            | AppError::WriteState(err) => write!(f, "{}", err),
+            AppError::ReadAddresses(err) => write!(f, "Can't read addresses:\n{}", err),
+            AppError::DecodeAddresses(err) => write!(f, "Can't parse addresses:\n{}", err),
+            AppError::NoAddresses(path) => {
+                write!(f, "'{}' does not contain any valid addresses", path)
+            }
+            AppError::ReadPositions(err) => write!(f, "Can't read positions:\n{}", err),
+            AppError::NoPositions(path) => {
+                write!(f, "'{}' does not contain any positions on the screen", path)
+            }
+            AppError::NothingToDraw(name) => write!(
+                f,
+                "None of the pixels of '{}' would be drawn, it's either completely transparent \
+                 or it lies entirely outside of the screen",
+                name
+            ),
+            AppError::WritePreview(err) => write!(f, "Can't save preview:\n{}", err),
+            AppError::WriteJson(err) => write!(f, "Can't write JSON output:\n{}", err),
+            AppError::Signal(err) => write!(f, "Can't install the Ctrl-C handler:\n{}", err),
+            AppError::PauseSignal(err) => {
+                write!(f, "Can't install the signal handlers for pausing:\n{}", err)
+            }
+            AppError::Watch(err) => write!(f, "Can't watch the image for changes:\n{}", err),
+            AppError::Metrics(err) => write!(f, "Can't start the metrics server:\n{}", err),
+            AppError::Serve(err) => write!(f, "Can't start the server:\n{}", err),
+            AppError::Camera(err) => write!(f, "Can't open the camera:\n{}", err),
+            AppError::Stream(err) => write!(f, "Can't read the stream:\n{}", err),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl From<Error> for AppError {
+    fn from(err: Error) -> Self {
+        AppError::Pingas(err)
+    }
+}
+
+impl From<clap::Error> for AppError {
+    fn from(err: clap::Error) -> Self {
+        AppError::Usage(err)
+    }
+}
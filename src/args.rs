@@ -0,0 +1,1893 @@
+//! The command line interface. This defines every option and subcommand, and
+//! contains the helpers for validating and parsing the arguments clap can't
+//! fully check on its own.
+
+use clap::{App, AppSettings, Arg, ErrorKind, SubCommand};
+use pingas::{parse_color, ColorDepth, Prefix, DEFAULT_SCREEN_SIZE, MAX_PAYLOAD_SIZE};
+use std::convert::TryFrom;
+use std::fmt::Display;
+use std::net::{Ipv6Addr, SocketAddr};
+use std::str::FromStr;
+
+use crate::app_error::AppError;
+use crate::clock;
+use crate::config::{Config, FILTERS};
+use crate::pattern;
+use crate::placement::{self, parse_lengths, Length, ANCHORS};
+use crate::stream::MAX_FRAME_SIZE;
+
+/// The default values for the options that can also be set in the config file,
+/// formatted the way clap expects them.
+pub struct Defaults {
+    repeat: String,
+    threads: String,
+    rate: Option<String>,
+    prefix: String,
+    filter: String,
+    screen_width: String,
+    screen_height: String,
+}
+
+impl Defaults {
+    /// Fill in the defaults from the config file, falling back to the built-in
+    /// defaults for anything that isn't set there.
+    pub fn new(config: &Config) -> Self {
+        let (default_width, default_height) = DEFAULT_SCREEN_SIZE;
+        Defaults {
+            repeat: config.repeat.unwrap_or(1).to_string(),
+            threads: num_cpus::get().to_string(),
+            rate: config.rate.map(|rate| rate.to_string()),
+            prefix: config
+                .prefix
+                .as_deref()
+                .unwrap_or("2001:610:1908:a000")
+                .to_owned(),
+            // Nearest neighbour is the default because the system does not
+            // actually blend semitransparent pixels. All other filtering
+            // algorithms will cause there to be a border of semitransparent
+            // pixels resulting in weird looking artifacts.
+            filter: config.filter.as_deref().unwrap_or("nearest").to_owned(),
+            screen_width: config.screen_width.unwrap_or(default_width).to_string(),
+            screen_height: config.screen_height.unwrap_or(default_height).to_string(),
+        }
+    }
+}
+
+/// The definition of pingas' command line interface, using `defaults` for the
+/// options that can also be set in the config file.
+pub fn app(defaults: &Defaults) -> App<'_, '_> {
+    let mut rate_arg = Arg::with_name("rate")
+        .long("rate")
+        .help("Limit the number of packets sent per second.")
+        .long_help(
+            "Limit the combined number of packets sent per second. \
+             There is no limit by default. For the public screen \
+             a few thousand packets per second is a reasonable \
+             starting point, increase it until errors start \
+             showing up.",
+        )
+        .takes_value(true)
+        .validator(|rate| validate_rate(&rate, "The rate"));
+    if let Some(rate) = &defaults.rate {
+        rate_arg = rate_arg.default_value(rate);
+    }
+
+    App::new("pingas")
+        .version(clap::crate_version!())
+        .author(clap::crate_authors!())
+        .set_term_width(80)
+        .about("A Jinglepings pinger")
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .after_help(
+            "Defaults for -r, --rate, --filter, --prefix, --screen-width, and \
+             --screen-height can be set in a pingas.toml config file. Options passed \
+             on the command line take precedence over the config file.\n\n\
+             While drawing, sending SIGUSR1 to pingas pauses drawing and sending \
+             SIGUSR2 resumes it again.",
+        )
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .help(
+                    "Load defaults from this config file instead of ./pingas.toml or \
+                     ~/.config/pingas/pingas.toml.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("print_config")
+                .long("print-config")
+                .help("Print the options that can be set in the config file as TOML or JSON, then exit.")
+                .long_help(
+                    "Print the values pingas uses for the options that can be \
+                     set in the config file, after combining the command line, \
+                     the config file, and the built-in defaults, as TOML or \
+                     JSON. Then exit without drawing anything. This uses the \
+                     config file's keys, except that the prefixes are printed \
+                     as a list.",
+                )
+                .takes_value(true)
+                .possible_values(&["toml", "json"]),
+        )
+        .arg(
+            Arg::with_name("list_filters")
+                .long("list-filters")
+                .help("List the scaling filters that can be passed to --filter, then exit."),
+        )
+        .arg(
+            Arg::with_name("state")
+                .long("state")
+                .help("Save the arguments to this file so pingas can be restarted with --resume.")
+                .long_help(
+                    "Save the arguments pingas was started with and the current \
+                     directory to this JSON file, so the same drawing can be \
+                     started again after a restart with --resume. Resuming \
+                     keeps this option, so the state file stays up to date.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("resume")
+                .long("resume")
+                .help("Start drawing whatever the --state file was saved for.")
+                .long_help(
+                    "Start drawing whatever the --state file was saved for, \
+                     using the same arguments and the same working directory. \
+                     This can't be combined with any other arguments.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .long("verbose")
+                .help("Log more details, pass this twice to log everything.")
+                .long_help(
+                    "Log more details to STDERR. With -v the number of addresses \
+                     per row and the time it took to compute them are logged, \
+                     and with -vv every failed send gets logged as well. The \
+                     RUST_LOG environment variable can be used for more fine \
+                     grained filtering.",
+                )
+                .multiple(true),
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .short("q")
+                .long("quiet")
+                .help("Only log warnings and errors.")
+                .conflicts_with("verbose"),
+        )
+        .arg(
+            Arg::with_name("repeat")
+                .short("r")
+                .help("The number of repetitions when pinging rows.")
+                .long_help(
+                    "The number of repetitions when pinging rows. \
+                     This might be useful when drawing small images \
+                     that don't quite saturate the packet queue.",
+                )
+                .takes_value(true)
+                .validator(|repeat| match repeat.parse::<usize>() {
+                    Ok(repeat) if repeat > 0 => Ok(()),
+                    _ => Err(String::from(
+                        "The number of repetitions should be a positive integer",
+                    )),
+                })
+                .default_value(&defaults.repeat),
+        )
+        .arg(
+            Arg::with_name("threads")
+                .long("threads")
+                .help("The number of threads to spread the pixels over. Defaults to the number of CPUs.")
+                .long_help(
+                    "The number of threads to spread the pixels over. Every \
+                     thread pings the same number of pixels, regardless of how \
+                     the rows are laid out, and -r is applied on top of this. \
+                     Defaults to the number of CPUs.",
+                )
+                .takes_value(true)
+                .validator(|threads| match threads.parse::<usize>() {
+                    Ok(threads) if threads > 0 => Ok(()),
+                    _ => Err(String::from("The number of threads should be a positive integer")),
+                })
+                .default_value(&defaults.threads),
+        )
+        .arg(rate_arg)
+        .arg(
+            Arg::with_name("adaptive")
+                .long("adaptive")
+                .help("Lower the send rate automatically when pings fail or go unanswered.")
+                .long_help(
+                    "Treat --rate as the maximum rate, and automatically halve \
+                     the send rate when more than 1% of the pings sent during \
+                     the last second failed or went unanswered. The rate then \
+                     slowly increases again while there are no errors. The rate \
+                     that's currently allowed is shown in the statistics. Screens \
+                     that never reply to pings should be used with --backend raw, \
+                     since every ping sent through fastping would otherwise count \
+                     as unanswered.",
+                )
+                .requires("rate"),
+        )
+        .arg(
+            Arg::with_name("ramp")
+                .long("ramp")
+                .help("Gradually increase the send rate to --rate over this amount of time, e.g. 5s.")
+                .long_help(
+                    "Start out at a fraction of --rate and linearly increase the \
+                     send rate to the full rate over this amount of time, e.g. \
+                     5s. Starting all threads at full speed at once can flood \
+                     the queues. This does nothing without a --rate.",
+                )
+                .takes_value(true)
+                .validator(|ramp| {
+                    humantime::parse_duration(&ramp)
+                        .map(|_| ())
+                        .map_err(|err| err.to_string())
+                }),
+        )
+        .arg(
+            Arg::with_name("keepalive_rate")
+                .long("keepalive-rate")
+                .help("After drawing the image once, keep sending it at this many packets per second.")
+                .long_help(
+                    "Draw the image once at the normal rate and then drop down \
+                     to sending it again at this many packets per second. This \
+                     keeps the image on the screen when it occasionally gets \
+                     drawn over, without flooding the screen with packets. New \
+                     images and frames are drawn at the normal rate again, so \
+                     this is mostly useful for still images.",
+                )
+                .takes_value(true)
+                .validator(|rate| validate_rate(&rate, "The keepalive rate")),
+        )
+        .arg(
+            Arg::with_name("jitter")
+                .long("jitter")
+                .help("Wait a random amount of time up to this long before every pass, e.g. 5ms.")
+                .long_help(
+                    "Have every thread wait a random amount of time up to this \
+                     long before every pass, e.g. 5ms. Threads that send at the \
+                     same moment cause periodic bursts of traffic on the screen, \
+                     and this spreads those out. Use --seed to get the same \
+                     delays every time.",
+                )
+                .takes_value(true)
+                .validator(|jitter| {
+                    humantime::parse_duration(&jitter)
+                        .map(|_| ())
+                        .map_err(|err| err.to_string())
+                }),
+        )
+        .arg(
+            Arg::with_name("backend")
+                .long("backend")
+                .help("How to send the pings.")
+                .long_help(
+                    "How to send the pings. 'fastping' uses the fastping_rs \
+                     library, which also keeps track of unanswered pings. 'raw' \
+                     writes the packets to a raw socket directly and can send \
+                     them a lot faster. Both need permission to open raw \
+                     sockets, so on Linux pingas either has to run as root or \
+                     it needs the CAP_NET_RAW capability.",
+                )
+                .possible_values(&["fastping", "raw"])
+                .default_value("fastping"),
+        )
+        .arg(
+            Arg::with_name("interface")
+                .long("interface")
+                .help("Send the pings through this network interface. Requires --backend raw.")
+                .long_help(
+                    "Send the pings through this network interface, from one of \
+                     its global IPv6 addresses. This is useful when the host \
+                     has multiple network interfaces and the pings would \
+                     otherwise leave through the wrong one. Requires --backend \
+                     raw.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("source_address")
+                .long("source")
+                .help("Send the pings from this local IPv6 address. Requires --backend raw.")
+                .takes_value(true)
+                .conflicts_with("interface")
+                .validator(|address| {
+                    address
+                        .parse::<Ipv6Addr>()
+                        .map(|_| ())
+                        .map_err(|_| format!("'{}' is not a valid IPv6 address", address))
+                }),
+        )
+        .arg(
+            Arg::with_name("filter")
+                .short("f")
+                .long("filter")
+                .help("Choose kind of filtering used when scaling the image.")
+                .long_help(
+                    "Choose kind of filtering used when scaling the image. \
+                     'nearest' keeps hard edges, 'linear' is a triangle filter \
+                     that interpolates between neighbouring pixels, 'cubic' is \
+                     the sharper Catmull-Rom filter, 'gaussian' is a soft \
+                     Gaussian blur, and 'lanczos3' is the sharpest filter. \
+                     Every filter other than 'nearest' creates semitransparent \
+                     pixels along transparent edges. When --upscale-filter is \
+                     set this is only used for shrinking images.",
+                )
+                .possible_values(FILTERS)
+                .default_value(&defaults.filter),
+        )
+        .arg(
+            Arg::with_name("upscale_filter")
+                .long("upscale-filter")
+                .help("Use this filter instead of --filter when the image gets enlarged.")
+                .long_help(
+                    "Use this filter instead of --filter when the image gets \
+                     enlarged, so photos can be shrunk with a smooth filter \
+                     while pixel art gets enlarged with 'nearest', or the other \
+                     way around. When stretching an image this is used if \
+                     either of its dimensions grows.",
+                )
+                .takes_value(true)
+                .possible_values(FILTERS),
+        )
+        .arg(
+            Arg::with_name("resize_mode")
+                .long("resize-mode")
+                .help("How to resize the image when both <width> and <height> are set.")
+                .long_help(
+                    "How to resize the image when both <width> and <height> are \
+                     set. 'fit' scales the image to fit within that box while \
+                     keeping its aspect ratio, 'fill' scales it to cover the \
+                     entire box and then crops the center, and 'stretch' scales \
+                     it to exactly that size regardless of the aspect ratio. \
+                     All modes scale using the --filter.",
+                )
+                .possible_values(&["fit", "fill", "stretch"])
+                .default_value("fit"),
+        )
+        .arg(
+            Arg::with_name("scale")
+                .long("scale")
+                .help("Scale the image up by a whole number instead of passing <width>, e.g. 8x.")
+                .long_help(
+                    "Scale the image up by a whole number instead of passing \
+                     <width> and <height>, e.g. 8x. Every pixel becomes a square \
+                     block of pixels of the same color, so pixel art stays crisp. \
+                     Unlike resizing with --filter nearest this never gets \
+                     rounded, so every block is exactly the same size.",
+                )
+                .takes_value(true)
+                .validator(|scale| parse_scale(&scale).map(|_| ()))
+                .conflicts_with("max_pixels"),
+        )
+        .arg(
+            Arg::with_name("max_pixels")
+                .long("max-pixels")
+                .help("Shrink the image until it contains at most this many visible pixels.")
+                .long_help(
+                    "Shrink the image until it contains at most this many pixels \
+                     that aren't completely transparent, so it doesn't take up \
+                     more of a shared screen than intended. The image keeps its \
+                     aspect ratio, and a warning with the new size is printed if \
+                     it had to be shrunk. For animations this applies to the \
+                     largest frame.",
+                )
+                .takes_value(true)
+                .validator(|pixels| match pixels.parse::<u64>() {
+                    Ok(pixels) if pixels > 0 => Ok(()),
+                    _ => Err(String::from("The number of pixels should be a positive integer")),
+                }),
+        )
+        .arg(
+            Arg::with_name("crop")
+                .long("crop")
+                .help("Only draw this part of the image, in the form x,y,width,height.")
+                .long_help(
+                    "Only draw this part of the image. This takes the form \
+                     x,y,width,height in pixels of the original image. The \
+                     image is cropped before it gets scaled.",
+                )
+                .takes_value(true)
+                .validator(|crop| parse_crop(&crop).map(|_| ())),
+        )
+        .arg(
+            Arg::with_name("no_auto_orient")
+                .long("no-auto-orient")
+                .help("Don't rotate JPEG images according to their EXIF orientation."),
+        )
+        .arg(
+            Arg::with_name("svg_dpi")
+                .long("svg-dpi")
+                .help("The resolution to rasterize SVG images at when no <width> is given.")
+                .long_help(
+                    "The resolution to rasterize SVG images at when no <width> \
+                     is given, like when using --scale, or when the image gets \
+                     cropped or rotated. SVG images are otherwise rasterized at \
+                     exactly the size they get drawn at, so they stay sharp \
+                     instead of being scaled after rasterizing them. SVG images \
+                     are only supported when pingas is built with the `svg` \
+                     feature.",
+                )
+                .takes_value(true)
+                .default_value("96")
+                .validator(|dpi| match dpi.parse::<f32>() {
+                    Ok(dpi) if dpi > 0.0 && dpi.is_finite() => Ok(()),
+                    _ => Err(String::from("The DPI should be a positive number")),
+                }),
+        )
+        .arg(
+            Arg::with_name("rotate")
+                .long("rotate")
+                .help("Rotate the image clockwise by this many degrees before scaling it.")
+                .takes_value(true)
+                .possible_values(&["90", "180", "270"]),
+        )
+        .arg(
+            Arg::with_name("flip_h")
+                .long("flip-h")
+                .help("Flip the image horizontally. This is done after rotating."),
+        )
+        .arg(
+            Arg::with_name("flip_v")
+                .long("flip-v")
+                .help("Flip the image vertically. This is done after rotating."),
+        )
+        .arg(
+            Arg::with_name("mirror")
+                .long("mirror")
+                .help("Mirror the scaled image into a larger block, like a kaleidoscope.")
+                .long_help(
+                    "Mirror the scaled image into a larger block, like a \
+                     kaleidoscope. With 'quad' the image is drawn twice as wide \
+                     and twice as high, with the original image in the top left, \
+                     a horizontally flipped copy to its right, and vertically \
+                     flipped copies of both below them.",
+                )
+                .takes_value(true)
+                .possible_values(&["quad"]),
+        )
+        .arg(
+            Arg::with_name("chroma_key")
+                .long("chroma-key")
+                .help("Treat pixels of this color as transparent, in RRGGBB format.")
+                .takes_value(true)
+                .validator(|color| {
+                    parse_color(&color)
+                        .map(|_| ())
+                        .map_err(|err| err.to_string())
+                }),
+        )
+        .arg(
+            Arg::with_name("tolerance")
+                .long("tolerance")
+                .help("How far colors can be from --chroma-key and still be made transparent.")
+                .long_help(
+                    "How far colors can be from --chroma-key and still be made \
+                     transparent. This is the Euclidean distance between the \
+                     colors' red, green, and blue values, so 0 only matches the \
+                     exact color and 441 matches every color.",
+                )
+                .takes_value(true)
+                .requires("chroma_key")
+                .validator(|tolerance| match tolerance.parse::<f32>() {
+                    Ok(tolerance) if tolerance >= 0.0 => Ok(()),
+                    _ => Err(String::from("The tolerance should be a non-negative number")),
+                }),
+        )
+        .arg(
+            Arg::with_name("blur")
+                .long("blur")
+                .help("Blur the image by this many pixels, e.g. 0.5.")
+                .long_help(
+                    "Blur the image with a Gaussian blur with this standard \
+                     deviation in pixels, e.g. 0.5. A small amount of blur can \
+                     make downscaled photos look less noisy. This is done right \
+                     after resizing, before any of the other filters and color \
+                     adjustments.",
+                )
+                .takes_value(true)
+                .conflicts_with("sharpen")
+                .validator(|sigma| match sigma.parse::<f32>() {
+                    Ok(sigma) if sigma > 0.0 && sigma.is_finite() => Ok(()),
+                    _ => Err(String::from("The amount of blur should be a positive number")),
+                }),
+        )
+        .arg(
+            Arg::with_name("sharpen")
+                .long("sharpen")
+                .help("Sharpen the image.")
+                .long_help(
+                    "Sharpen the image with an unsharp mask. This can help line \
+                     art stay crisp after downscaling. This is done right after \
+                     resizing, before any of the other filters and color \
+                     adjustments.",
+                ),
+        )
+        .arg(
+            Arg::with_name("brightness")
+                .long("brightness")
+                .help("Add this value to every color channel, between -255 and 255.")
+                .takes_value(true)
+                .allow_hyphen_values(true)
+                .validator(|brightness| match brightness.parse::<i32>() {
+                    Ok(brightness) if (-255..=255).contains(&brightness) => Ok(()),
+                    _ => Err(String::from(
+                        "The brightness should be a whole number between -255 and 255",
+                    )),
+                })
+                .default_value("0"),
+        )
+        .arg(
+            Arg::with_name("contrast")
+                .long("contrast")
+                .help("Change the contrast by this percentage, e.g. 20 or -20.")
+                .takes_value(true)
+                .allow_hyphen_values(true)
+                .validator(|contrast| match contrast.parse::<f32>() {
+                    Ok(contrast) if contrast.is_finite() => Ok(()),
+                    _ => Err(String::from("The contrast should be a number")),
+                })
+                .default_value("0"),
+        )
+        .arg(
+            Arg::with_name("gamma")
+                .long("gamma")
+                .help("Apply gamma correction. Values above 1 brighten the image.")
+                .long_help(
+                    "Apply gamma correction. Values above 1 brighten the image's \
+                     midtones and values below 1 darken them. The brightness is \
+                     changed first, then the contrast, and the gamma correction \
+                     is applied last.",
+                )
+                .takes_value(true)
+                .validator(|gamma| match gamma.parse::<f32>() {
+                    Ok(gamma) if gamma > 0.0 && gamma.is_finite() => Ok(()),
+                    _ => Err(String::from("The gamma should be a positive number")),
+                })
+                .default_value("1"),
+        )
+        .arg(
+            Arg::with_name("grayscale")
+                .long("grayscale")
+                .help("Convert the image to grayscale."),
+        )
+        .arg(
+            Arg::with_name("invert")
+                .long("invert")
+                .help("Invert the image's colors. This is done after --grayscale."),
+        )
+        .arg(
+            Arg::with_name("outline")
+                .long("outline")
+                .help("Only draw the edges in the image.")
+                .long_help(
+                    "Only draw the edges in the image. Edges are detected in \
+                     the image's brightness, and every other pixel is skipped. \
+                     This greatly reduces the number of pixels that have to be \
+                     sent for line art and logos. This is done after the other \
+                     color adjustments and after --alpha-mode and --background, \
+                     but before --palette and --dither.",
+                ),
+        )
+        .arg(
+            Arg::with_name("threshold")
+                .long("threshold")
+                .help("How strong an edge has to be to get drawn by --outline, between 0 and 1.")
+                .long_help(
+                    "How strong an edge has to be to get drawn by --outline, \
+                     between 0 and 1. Lower values draw more of the image's \
+                     finer details, and 1 corresponds to a jump from black to \
+                     white.",
+                )
+                .takes_value(true)
+                .validator(|threshold| match threshold.parse::<f32>() {
+                    Ok(threshold) if (0.0..=1.0).contains(&threshold) => Ok(()),
+                    _ => Err(String::from("The threshold should be between 0 and 1")),
+                })
+                .default_value("0.25"),
+        )
+        .arg(
+            Arg::with_name("opacity")
+                .long("opacity")
+                .help("Multiply every pixel's alpha value by this number between 0 and 1.")
+                .long_help(
+                    "Multiply every pixel's alpha value by this number between 0 \
+                     and 1, to draw the image as a faint overlay. This is done \
+                     right before --vignette, --alpha-mode, and --background \
+                     are applied. Pixels that end up completely transparent are \
+                     skipped like any other transparent pixel, unless \
+                     --background is used. With --background or --alpha-mode \
+                     premultiply this controls how strongly the image shows up \
+                     over the background color.",
+                )
+                .takes_value(true)
+                .validator(|opacity| match opacity.parse::<f32>() {
+                    Ok(opacity) if (0.0..=1.0).contains(&opacity) => Ok(()),
+                    _ => Err(String::from("The opacity should be between 0 and 1")),
+                }),
+        )
+        .arg(
+            Arg::with_name("vignette")
+                .long("vignette")
+                .help("Fade the image out toward its edges, with a strength between 0 and 1.")
+                .long_help(
+                    "Fade the image's alpha values out toward its edges with a \
+                     radial falloff, so it blends softly into the screen. The \
+                     strength between 0 and 1 is how far the fade reaches from \
+                     the edges toward the center. Everything outside of the \
+                     oval touching the edges becomes completely transparent. \
+                     This is applied after --opacity and before --alpha-mode \
+                     and --background, so with '--alpha-mode threshold' this \
+                     leaves a clean oval.",
+                )
+                .takes_value(true)
+                .validator(|strength| match strength.parse::<f32>() {
+                    Ok(strength) if (0.0..=1.0).contains(&strength) => Ok(()),
+                    _ => Err(String::from("The strength should be between 0 and 1")),
+                }),
+        )
+        .arg(
+            Arg::with_name("alpha_mode")
+                .long("alpha-mode")
+                .help("How to send semitransparent pixels.")
+                .long_help(
+                    "How to send semitransparent pixels. The alpha value is sent \
+                     as the lowest byte of every pixel's address, and completely \
+                     transparent pixels are never sent. 'skip-zero' sends all \
+                     other pixels with their alpha value as is. 'threshold' \
+                     treats pixels with an alpha value below --alpha-threshold \
+                     as completely transparent and sends all other pixels with \
+                     an alpha value of 255. 'premultiply' blends the pixels with \
+                     --background, or black if that's not set, and sends them \
+                     with an alpha value of 255.",
+                )
+                .possible_values(&["skip-zero", "threshold", "premultiply"])
+                .default_value("skip-zero"),
+        )
+        .arg(
+            Arg::with_name("alpha_threshold")
+                .long("alpha-threshold")
+                .help("The alpha value used for --alpha-mode threshold.")
+                .takes_value(true)
+                .default_value("128"),
+        )
+        .arg(
+            Arg::with_name("background")
+                .long("background")
+                .help("Draw the image on top of this color, in RRGGBB format.")
+                .long_help(
+                    "Draw the image on top of this color, in RRGGBB format. \
+                     Every pixel, including completely transparent ones, then \
+                     gets sent as an opaque pixel. This takes precedence over \
+                     --alpha-mode unless that's set to 'premultiply', in which \
+                     case completely transparent pixels are still skipped.",
+                )
+                .takes_value(true)
+                .validator(|color| {
+                    parse_color(&color)
+                        .map(|_| ())
+                        .map_err(|err| err.to_string())
+                }),
+        )
+        .arg(
+            Arg::with_name("no_skip_transparent")
+                .long("no-skip-transparent")
+                .help("Also send completely transparent pixels.")
+                .long_help(
+                    "Also send completely transparent pixels, with an alpha \
+                     value of 0. These are normally skipped, so anything \
+                     someone else drew in the image's transparent areas stays \
+                     visible. With this option those areas get blanked out \
+                     instead. Use --background to blank them out with a color. \
+                     Pixels that stay the same between frames of an animation \
+                     are still skipped.",
+                ),
+        )
+        .arg(
+            Arg::with_name("dither")
+                .long("dither")
+                .help("Dither the scaled image to reduce banding on screens with poor colors.")
+                .possible_values(&["none", "floyd-steinberg"])
+                .default_value("none"),
+        )
+        .arg(
+            Arg::with_name("color_depth")
+                .long("color-depth")
+                .help("The color depth to dither to, e.g. rgb332 or rgb565.")
+                .long_help(
+                    "The color depth to dither to when using --dither. This takes \
+                     the form of rgbRGB where R, G, and B are the number of bits \
+                     for that channel, so rgb332 uses 3 bits for red and green \
+                     and 2 bits for blue.",
+                )
+                .takes_value(true)
+                .validator(|depth| {
+                    depth
+                        .parse::<ColorDepth>()
+                        .map(|_| ())
+                        .map_err(|err| err.to_string())
+                })
+                .default_value("rgb565"),
+        )
+        .arg(
+            Arg::with_name("quantize")
+                .long("quantize")
+                .help("Reduce every color channel to this bit depth, e.g. rgb565 or rgb332.")
+                .long_help(
+                    "Reduce every color channel to the number of bits in this \
+                     color depth, e.g. rgb565 or rgb332, by dropping the least \
+                     significant bits. This shows the banding a screen with a \
+                     limited color resolution would produce. The alpha channel \
+                     keeps all of its 8 bits. When combined with --dither the \
+                     image gets dithered to this color depth instead, like \
+                     with --color-depth.",
+                )
+                .takes_value(true)
+                .conflicts_with_all(&["color_depth", "palette"])
+                .validator(|depth| {
+                    depth
+                        .parse::<ColorDepth>()
+                        .map(|_| ())
+                        .map_err(|err| err.to_string())
+                }),
+        )
+        .arg(
+            Arg::with_name("palette")
+                .long("palette")
+                .help("Reduce the image to the colors in this GIMP palette or list of hex colors.")
+                .long_help(
+                    "Replace every pixel with the nearest color from this palette. \
+                     This can be either a GIMP palette (.gpl) or a text file \
+                     with one RRGGBB color per line. When combined with --dither \
+                     the image gets dithered toward the palette instead of \
+                     toward --color-depth.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("order")
+                .long("order")
+                .help("The order to send the pixels in.")
+                .long_help(
+                    "The order to send the pixels in. With 'rows' every row is \
+                     pinged on its own, so on a congested screen the image fills \
+                     in from top to bottom. 'columns' does the same for every \
+                     column, which spreads the load differently and can help \
+                     with tall and narrow images. With 'random' the pixels are \
+                     shuffled so the image fades in uniformly instead. \
+                     'center-out' and 'spiral' start with the pixels at the \
+                     center of the image so its subject appears first.",
+                )
+                .possible_values(&["rows", "columns", "random", "center-out", "spiral"])
+                .default_value("rows"),
+        )
+        .arg(
+            Arg::with_name("dispatch")
+                .long("dispatch")
+                .help("Whether the workers get rows or columns of pixels. Same as --order rows or columns.")
+                .long_help(
+                    "Whether the workers get rows or columns of pixels to send. \
+                     This is the same as --order rows or --order columns, since \
+                     those orders are how the pixels get split up over the \
+                     workers.",
+                )
+                .takes_value(true)
+                .possible_values(&["rows", "columns"])
+                .conflicts_with("order"),
+        )
+        .arg(
+            Arg::with_name("pixel_layout")
+                .long("pixel-layout")
+                .help("How colors are packed into the addresses.")
+                .long_help(
+                    "How colors are packed into the last two groups of the \
+                     addresses. The channels are listed in the order they appear \
+                     in the address, so with the default 'bgra16' a pixel with \
+                     the color RRGGBBAA gets sent to an address ending in \
+                     BBGG:RRAA. The other layouts are for screens that expect the \
+                     channels in a different order.",
+                )
+                .possible_values(&["bgra16", "rgba16", "argb16", "abgr16"])
+                .default_value("bgra16"),
+        )
+        .arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .help("The seed to use with --order random and --jitter, for reproducible results.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("prefix")
+                .short("p")
+                .long("prefix")
+                .help("The /64 prefix of the screen's IPv6 addresses.")
+                .long_help(
+                    "The /64 prefix of the screen's IPv6 addresses. This can be \
+                     passed multiple times when the screen listens on multiple \
+                     prefixes, in which case the pixels are spread over all of \
+                     them round-robin.",
+                )
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .validator(|prefix| {
+                    prefix
+                        .parse::<Prefix>()
+                        .map(|_| ())
+                        .map_err(|err| err.to_string())
+                })
+                .default_value(&defaults.prefix),
+        )
+        .arg(
+            Arg::with_name("screen_width")
+                .long("screen-width")
+                .help("The width of the screen in pixels.")
+                .takes_value(true)
+                .validator(validate_dimension)
+                .default_value(&defaults.screen_width),
+        )
+        .arg(
+            Arg::with_name("screen_height")
+                .long("screen-height")
+                .help("The height of the screen in pixels.")
+                .takes_value(true)
+                .validator(validate_dimension)
+                .default_value(&defaults.screen_height),
+        )
+        .arg(
+            Arg::with_name("anchor")
+                .long("anchor")
+                .help("Align the image to this point on the screen instead of passing <x> and <y>.")
+                .long_help(
+                    "Align the image to this point on the screen. The <x> and <y> \
+                     arguments should be omitted when using this option.",
+                )
+                .takes_value(true)
+                .possible_values(ANCHORS),
+        )
+        .arg(
+            Arg::with_name("at")
+                .long("at")
+                .help("Draw at these coordinates instead of passing <x> and <y>, in the form x,y.")
+                .long_help(
+                    "Draw at these coordinates instead of passing <x> and <y>, \
+                     in the form x,y. Both coordinates can also be percentages of \
+                     the screen's size, e.g. 50%,50% for the center of the \
+                     screen.",
+                )
+                .takes_value(true)
+                .conflicts_with("anchor")
+                .validator(|position| parse_lengths(&position).map(|_| ())),
+        )
+        .arg(
+            Arg::with_name("positions")
+                .long("positions")
+                .help("Draw the image at every position listed in this file instead of at <x> and <y>.")
+                .long_help(
+                    "Draw copies of the image at every position listed in this \
+                     file instead of passing <x> and <y>. Every line contains a \
+                     position in the form 'x y' or 'x,y', and like with --at \
+                     these can also be percentages of the screen's size. Empty \
+                     lines and lines starting with # are ignored. The image is \
+                     only loaded once, and positions that are invalid or where \
+                     the image would not fit on the screen are reported and \
+                     skipped. This only works with still images.",
+                )
+                .takes_value(true)
+                .conflicts_with_all(&[
+                    "at",
+                    "anchor",
+                    "scroll",
+                    "watch",
+                    "blink",
+                    "sequence",
+                    "camera",
+                    "stream",
+                    "output_json",
+                ]),
+        )
+        .arg(
+            Arg::with_name("offset")
+                .long("offset")
+                .help("Move the anchored image by this many pixels, in the form x,y.")
+                .long_help(
+                    "Move the anchored image by this many pixels, in the form \
+                     x,y. The values can also be percentages of the screen's \
+                     size, e.g. -10%,0 to move the image left by a tenth of \
+                     the screen's width.",
+                )
+                .takes_value(true)
+                .allow_hyphen_values(true)
+                .requires("anchor")
+                .validator(|offset| parse_lengths(&offset).map(|_| ())),
+        )
+        .arg(
+            Arg::with_name("tile")
+                .long("tile")
+                .help("Repeat the image in a grid of this many columns and rows.")
+                .long_help(
+                    "Repeat the image in a grid of this many columns and rows, \
+                     starting at the given coordinates. The grid is drawn as if \
+                     it were a single image, so the entire grid has to fit on \
+                     the screen unless --clip is used.",
+                )
+                .takes_value(true)
+                .number_of_values(2)
+                .value_names(&["cols", "rows"])
+                .validator(|count| match count.parse::<u32>() {
+                    Ok(count) if count > 0 => Ok(()),
+                    _ => Err(String::from("The number of tiles should be a positive number")),
+                }),
+        )
+        .arg(
+            Arg::with_name("border")
+                .long("border")
+                .help("Draw a solid outline this many pixels thick around the image.")
+                .long_help(
+                    "Draw a solid outline this many pixels thick around the \
+                     image. The outline is drawn outside of the image, so the \
+                     image and its outline together have to fit on the screen \
+                     unless --clip is used. When tiling, the outline goes \
+                     around the entire grid.",
+                )
+                .takes_value(true)
+                .validator(|thickness| {
+                    thickness
+                        .parse::<u32>()
+                        .map(|_| ())
+                        .map_err(|_| String::from("The thickness should be a number of pixels"))
+                }),
+        )
+        .arg(
+            Arg::with_name("border_color")
+                .long("border-color")
+                .help("The color of the outline drawn by --border, in RRGGBB format.")
+                .takes_value(true)
+                .validator(|color| {
+                    parse_color(&color)
+                        .map(|_| ())
+                        .map_err(|err| err.to_string())
+                })
+                .default_value("ffffff"),
+        )
+        .arg(
+            Arg::with_name("loop_count")
+                .long("loop-count")
+                .help(
+                    "The number of times to play animations and sequences. Use 0 to loop \
+                     forever.",
+                )
+                .long_help(
+                    "The number of times to play animations and sequences. Use 0 \
+                     to loop forever. Animated GIFs and PNGs are played as many \
+                     times as the file asks for by default, and everything else \
+                     loops forever.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("full_frames")
+                .long("full-frames")
+                .help("Send every pixel of every frame of an animation.")
+                .long_help(
+                    "Send every pixel of every frame of an animation. By default \
+                     only the first frame is sent in full, and after that only \
+                     the pixels that changed since the previous frame are sent. \
+                     Use this when other people might be drawing over the \
+                     animation.",
+                ),
+        )
+        .arg(
+            Arg::with_name("transition")
+                .long("transition")
+                .help("Fade between the frames of animations and between reloaded images.")
+                .long_help(
+                    "Fade between the frames of animations and sequences, and \
+                     between the old and the new image when using --watch or the \
+                     serve subcommand. The new image gets blended over the old \
+                     one in several steps that are shown after the old image's \
+                     own delay, so this does make animations play slower.",
+                )
+                .takes_value(true)
+                .possible_values(&["fade"]),
+        )
+        .arg(
+            Arg::with_name("transition_ms")
+                .long("transition-ms")
+                .help("How long a transition takes in milliseconds.")
+                .takes_value(true)
+                .validator(|duration| match duration.parse::<u64>() {
+                    Ok(duration) if duration > 0 => Ok(()),
+                    _ => Err(String::from(
+                        "The duration should be a positive number of milliseconds",
+                    )),
+                })
+                .default_value("500"),
+        )
+        .arg(
+            Arg::with_name("easing")
+                .long("easing")
+                .help("How the blending speeds up and slows down during a transition.")
+                .long_help(
+                    "How the blending speeds up and slows down during a \
+                     transition. 'linear' blends at a constant speed, and \
+                     'ease-in-out' starts and ends slowly.",
+                )
+                .takes_value(true)
+                .possible_values(&["linear", "ease-in-out"])
+                .default_value("linear"),
+        )
+        .arg(
+            Arg::with_name("duration")
+                .long("duration")
+                .help("Stop drawing after this amount of time, e.g. 30s or 1h 30m.")
+                .takes_value(true)
+                .validator(|duration| {
+                    humantime::parse_duration(&duration)
+                        .map(|_| ())
+                        .map_err(|err| err.to_string())
+                }),
+        )
+        .arg(
+            Arg::with_name("start_at")
+                .long("start-at")
+                .help("Wait until this time before drawing, e.g. 20:00 or '2019-12-27 20:00:00'.")
+                .long_help(
+                    "Wait until this time before drawing, e.g. 20:00, 20:00:00, or \
+                     '2019-12-27 20:00:00'. A time that has already passed today \
+                     refers to that time tomorrow. This can also be a duration \
+                     like 5m. The image is loaded before waiting, and --duration \
+                     starts counting once drawing starts, so the two can be \
+                     combined to draw during a fixed time slot.",
+                )
+                .takes_value(true)
+                .validator(|start_at| clock::parse_target(&start_at).map(|_| ())),
+        )
+        .arg(
+            Arg::with_name("metrics_addr")
+                .long("metrics-addr")
+                .help("Serve Prometheus metrics on this address while drawing, e.g. 127.0.0.1:9100.")
+                .long_help(
+                    "Serve Prometheus metrics on this address while drawing, e.g. \
+                     127.0.0.1:9100. This exposes the number of packets sent, \
+                     the number of errors and unanswered pings, the number of \
+                     frames drawn, and the current rate limit.",
+                )
+                .takes_value(true)
+                .validator(|address| {
+                    address
+                        .parse::<SocketAddr>()
+                        .map(|_| ())
+                        .map_err(|_| format!("'{}' is not a valid address and port", address))
+                }),
+        )
+        .arg(
+            Arg::with_name("count")
+                .long("count")
+                .help("Stop after every row has been pinged this many times. Use 0 to never stop.")
+                .long_help(
+                    "Stop after every thread has pinged its pixels this many times. \
+                     Use 0 to keep pinging until pingas gets stopped.",
+                )
+                .takes_value(true)
+                .default_value("0"),
+        )
+        .arg(
+            Arg::with_name("max_packets")
+                .long("max-packets")
+                .help("Stop after sending this many packets in total.")
+                .long_help(
+                    "Stop after sending this many packets in total, counted over \
+                     all threads. This is a hard limit, so a pass over the image \
+                     that would go over it is not sent at all. This can be \
+                     combined with --duration and --count, in which case pingas \
+                     stops as soon as any of them is reached.",
+                )
+                .takes_value(true)
+                .validator(|max_packets| match max_packets.parse::<u64>() {
+                    Ok(max_packets) if max_packets > 0 => Ok(()),
+                    _ => Err(String::from(
+                        "The number of packets should be a positive integer",
+                    )),
+                }),
+        )
+        .arg(
+            Arg::with_name("retry")
+                .long("retry")
+                .help("Send pings that failed to send again up to this many times. Requires --backend raw.")
+                .long_help(
+                    "Send pings that could not be sent again up to this many \
+                     times before giving up on them until the next pass. Sends \
+                     usually fail because the send queues are full, so this \
+                     helps to get every pixel onto a busy screen. Only the \
+                     failed pings get sent again. fastping_rs does not report \
+                     which sends failed, so this requires --backend raw.",
+                )
+                .takes_value(true)
+                .validator(|retries| match retries.parse::<usize>() {
+                    Ok(_) => Ok(()),
+                    Err(_) => Err(String::from("The number of retries should be a whole number")),
+                }),
+        )
+        .arg(
+            Arg::with_name("payload_size")
+                .long("payload-size")
+                .help("Pad every echo request with this many bytes. Requires --backend raw.")
+                .long_help(
+                    "Pad every echo request with this many zero bytes, from 0 \
+                     up to 65527. Some screens treat larger packets \
+                     differently, and this can be used to measure throughput at \
+                     different packet sizes. Payloads larger than 1232 bytes \
+                     may get fragmented. fastping_rs always sends the same \
+                     packets, so this requires --backend raw.",
+                )
+                .takes_value(true)
+                .validator(|size| match size.parse::<usize>() {
+                    Ok(size) if size <= MAX_PAYLOAD_SIZE => Ok(()),
+                    _ => Err(format!(
+                        "The payload size should be a whole number no larger than {}",
+                        MAX_PAYLOAD_SIZE
+                    )),
+                }),
+        )
+        .arg(
+            Arg::with_name("clip").long("clip").help(
+                "Drop pixels that fall outside of the screen instead of exiting with an error.",
+            ),
+        )
+        .arg(
+            Arg::with_name("dry_run")
+                .long("dry-run")
+                .help("Print the addresses that would be pinged instead of pinging them.")
+                .long_help(
+                    "Print the addresses that would be pinged to STDOUT \
+                     instead of pinging them. Every address is printed once \
+                     for every ping it would receive during a single pass \
+                     over the image, so repetitions are included. For \
+                     animations only the pixels that change between frames \
+                     are printed, unless --full-frames is used.",
+                ),
+        )
+        .arg(
+            Arg::with_name("preview")
+                .long("preview")
+                .help("Save the image as it would be sent to this file instead of pinging it.")
+                .long_help(
+                    "Save the image to this file after it has been resized and \
+                     all filters have been applied, instead of pinging it. The \
+                     file format is based on the extension. For animations only \
+                     the first frame gets saved.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("stats_runs")
+                .long("stats-runs")
+                .help("Report how many runs of identical colors every row contains before drawing.")
+                .long_help(
+                    "Report how many horizontal runs of identical colors the rows \
+                     contain after the image has been resized and all filters \
+                     have been applied, along with the most common color. Every \
+                     pixel is still sent as its own packet, but an image with a \
+                     lot of long runs is mostly solid color, and a solid \
+                     background may not be worth sending. The number of runs in \
+                     every row is logged with -v. For animations only the first \
+                     frame is analyzed.",
+                ),
+        )
+        .arg(
+            Arg::with_name("output_json")
+                .long("output-json")
+                .help("Write the pings that would be sent to this file as JSON instead of pinging.")
+                .long_help(
+                    "Write the pings that would be sent to this file as JSON \
+                     instead of pinging them, or to STDOUT when this is set to \
+                     -. This is an array of objects containing the frame number, \
+                     the pixel's x and y coordinates on the screen, its r, g, b, \
+                     and a values, and the address to ping. Like with --dry-run \
+                     the pings are listed in order, repetitions are included, \
+                     and completely transparent pixels are left out.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("watch")
+                .long("watch")
+                .help("Reload and redraw the image whenever the file changes.")
+                .long_help(
+                    "Reload the image whenever the file gets modified, and start \
+                     drawing the new version in its place. The image gets resized \
+                     and filtered the same way as before, but it stays at the same \
+                     position. This only works with still images.",
+                )
+                .conflicts_with_all(&["text", "sequence", "manifest"]),
+        )
+        .arg(
+            Arg::with_name("manifest")
+                .long("manifest")
+                .help("Draw the images listed in this TOML file as a single scene.")
+                .long_help(
+                    "Draw the images listed in this TOML file as a single scene \
+                     instead of drawing a single image. Every image gets its own \
+                     [[image]] table with a 'file', and optionally an 'x' and 'y' \
+                     position on the screen, a 'width' and 'height' to resize \
+                     it to, a scaling 'filter', an 'opacity' between 0 and 1, and \
+                     a 'blend' mode. The blend mode can be 'normal', 'multiply', \
+                     'screen', or 'overlay'. Later images are drawn on top of \
+                     earlier ones. The scene can still be placed with --anchor \
+                     or --at, in which case the positions are relative to each \
+                     other.",
+                )
+                .takes_value(true)
+                .conflicts_with_all(&["filename", "text", "sequence"]),
+        )
+        .arg(
+            Arg::with_name("sequence")
+                .long("sequence")
+                .help("Play the images in this directory as an animation instead of an image.")
+                .long_help(
+                    "Play the images in this directory as an animation instead of \
+                     drawing a single image. The files are played in order of \
+                     their names, with numbers being sorted by their value. Every \
+                     frame gets resized to the specified width on its own.",
+                )
+                .takes_value(true)
+                .conflicts_with("text"),
+        )
+        .arg(
+            Arg::with_name("camera")
+                .long("camera")
+                .help("Keep drawing what this webcam sees instead of an image.")
+                .long_help(
+                    "Keep drawing what this webcam sees instead of an image. This \
+                     can be either the number of a video device, e.g. 0 for \
+                     /dev/video0, or the path to a video device. Every frame gets \
+                     resized and filtered like an image would, and only the pixels \
+                     that changed since the previous frame are sent. Use --fps to \
+                     limit the number of frames captured per second. The camera \
+                     should support either YUYV or Motion JPEG.",
+                )
+                .takes_value(true)
+                .conflicts_with_all(&["text", "sequence", "manifest", "watch", "blink"]),
+        )
+        .arg(
+            Arg::with_name("stream")
+                .long("stream")
+                .help("Keep drawing frames as they come in through this named pipe, or - for STDIN.")
+                .long_help(
+                    "Keep drawing frames as they come in through this named pipe, \
+                     or through STDIN when this is set to -. Every frame starts \
+                     with its length in bytes as a 32-bit big endian integer, \
+                     followed by an image in any supported format. Every frame \
+                     gets resized and filtered like an image would, and only the \
+                     pixels that changed since the previous frame are sent. When \
+                     frames come in faster than they can be drawn the older \
+                     frames are skipped. The last frame stays on screen after the \
+                     stream ends.",
+                )
+                .takes_value(true)
+                .conflicts_with_all(&[
+                    "text", "sequence", "manifest", "camera", "watch", "blink",
+                ]),
+        )
+        .arg(
+            Arg::with_name("raw_frames")
+                .long("raw-frames")
+                .help("Read --stream as raw RGBA frames of this size, e.g. 640x480.")
+                .long_help(
+                    "Read --stream as uncompressed RGBA frames of this size in \
+                     the WIDTHxHEIGHT format, e.g. 640x480, instead of as length \
+                     prefixed images. Every frame then consists of exactly \
+                     WIDTH * HEIGHT * 4 bytes. This matches the output of \
+                     `ffmpeg -f rawvideo -pix_fmt rgba`.",
+                )
+                .takes_value(true)
+                .validator(|size| parse_frame_size(&size).map(|_| ()))
+                .requires("stream"),
+        )
+        .arg(
+            Arg::with_name("fps")
+                .long("fps")
+                .help(
+                    "The number of frames per second to play --sequence at, or to \
+                     capture from --camera at.",
+                )
+                .takes_value(true)
+                .validator(|fps| validate_rate(&fps, "The frame rate"))
+                .default_value("10"),
+        )
+        .arg(
+            Arg::with_name("text")
+                .long("text")
+                .help("Draw this text instead of an image. Requires --font.")
+                .takes_value(true)
+                .requires("font"),
+        )
+        .arg(
+            Arg::with_name("font")
+                .long("font")
+                .help("The TrueType or OpenType font to use with --text.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("scroll")
+                .long("scroll")
+                .help("Scroll the text across the screen like a marquee.")
+                .long_help(
+                    "Scroll the text across the screen like a marquee. The text \
+                     scrolls between the x coordinate and the right edge of the \
+                     screen, and enters again from the other side once it has \
+                     fully scrolled out of view.",
+                )
+                .takes_value(true)
+                .possible_values(&["left", "right"])
+                .requires("text")
+                .conflicts_with_all(&["dry_run", "output_json"]),
+        )
+        .arg(
+            Arg::with_name("speed")
+                .long("speed")
+                .help("The speed to --scroll at, in pixels per second.")
+                .takes_value(true)
+                .validator(|speed| validate_rate(&speed, "The speed"))
+                .default_value("30"),
+        )
+        .arg(
+            Arg::with_name("blink")
+                .long("blink")
+                .help("Flash the image this many times per second.")
+                .long_help(
+                    "Flash the image this many times per second by alternating \
+                     between drawing the image and clearing it to --background, \
+                     or to black if that's not set. This only works with still \
+                     images. The rate limit still applies, so with a low --rate \
+                     and a high frequency the image may not be drawn completely \
+                     before it gets cleared again.",
+                )
+                .takes_value(true)
+                .validator(|frequency| validate_rate(&frequency, "The frequency"))
+                .conflicts_with_all(&["scroll", "watch", "sequence"]),
+        )
+        .arg(
+            Arg::with_name("size")
+                .long("size")
+                .help("The height of a line of text in pixels.")
+                .takes_value(true)
+                .validator(validate_text_size)
+                .default_value("24"),
+        )
+        .arg(
+            Arg::with_name("color")
+                .long("color")
+                .help("The color to draw text in, in RRGGBB or RRGGBBAA format.")
+                .takes_value(true)
+                .validator(|color| {
+                    parse_color(&color)
+                        .map(|_| ())
+                        .map_err(|err| err.to_string())
+                })
+                .default_value("ffffff"),
+        )
+        .arg(
+            Arg::with_name("filename")
+                .help(
+                    "A path to an image, or - to read the image from STDIN. \
+                     Most bitmap format are supported. This should be omitted \
+                     when using --text, --sequence, --camera, or --stream.",
+                )
+                .required_unless_one(&[
+                    "text",
+                    "sequence",
+                    "manifest",
+                    "camera",
+                    "stream",
+                    "print_config",
+                    "list_filters",
+                ])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("x")
+                .help(
+                    "The x coordinate to draw at, starting from 0 at the left of the screen. \
+                     This should be omitted when using --anchor or --at.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("y")
+                .help(
+                    "The y coordinate to draw at, starting from 0 at the top of the screen. \
+                     This should be omitted when using --anchor or --at.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("width")
+                .help(
+                    "The width of the scaled bitmap. Optional when using --text, \
+                     --camera, or --stream. This and the coordinates can also be \
+                     percentages of the screen's size, e.g. 25%.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("height")
+                .help(
+                    "The height of the scaled bitmap. If set, the bitmap will be resized to fit \
+                     within the specified width and height.",
+                )
+                .takes_value(true),
+        )
+        .subcommand(
+            SubCommand::with_name("fill")
+                .about("Fill a rectangle with a solid color")
+                .arg(
+                    Arg::with_name("color")
+                        .long("color")
+                        .help("The color to fill with, in RRGGBB or RRGGBBAA format.")
+                        .takes_value(true)
+                        .validator(|color| {
+                            parse_color(&color)
+                                .map(|_| ())
+                                .map_err(|err| err.to_string())
+                        })
+                        .default_value("000000"),
+                )
+                .arg(
+                    Arg::with_name("clear")
+                        .long("clear")
+                        .help("Fill with opaque black to blank out a region.")
+                        .conflicts_with("color"),
+                )
+                .arg(
+                    Arg::with_name("x")
+                        .help(
+                            "The x coordinate of the rectangle's top left corner. This should \
+                             be omitted when using --anchor or --at.",
+                        )
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("y")
+                        .help(
+                            "The y coordinate of the rectangle's top left corner. This should \
+                             be omitted when using --anchor or --at.",
+                        )
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("width")
+                        .help("The width of the rectangle.")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("height")
+                        .help("The height of the rectangle.")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("test-pattern")
+                .about("Draw a generated test pattern to check the position and the colors")
+                .long_about(
+                    "Draw a generated test pattern to check the position, the \
+                     colors, and the prefix before drawing real images. \
+                     'checkerboard' draws white and black squares, 'gradient' \
+                     goes from black to red from left to right and to green from \
+                     top to bottom, 'color-bars' draws eight vertical bars in \
+                     white, yellow, cyan, green, magenta, red, blue, and black, \
+                     and 'solid' fills the rectangle with --color.",
+                )
+                .arg(
+                    Arg::with_name("pattern")
+                        .help("The pattern to draw.")
+                        .possible_values(pattern::PATTERNS)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("cell_size")
+                        .long("cell-size")
+                        .help("The size of the squares in the checkerboard pattern.")
+                        .takes_value(true)
+                        .validator(|size| match size.parse::<u32>() {
+                            Ok(size) if size > 0 => Ok(()),
+                            _ => Err(String::from(
+                                "The cell size should be a positive integer",
+                            )),
+                        })
+                        .default_value("8"),
+                )
+                .arg(
+                    Arg::with_name("color")
+                        .long("color")
+                        .help("The color for the solid pattern, in RRGGBB or RRGGBBAA format.")
+                        .takes_value(true)
+                        .validator(|color| {
+                            parse_color(&color)
+                                .map(|_| ())
+                                .map_err(|err| err.to_string())
+                        })
+                        .default_value("ffffff"),
+                )
+                .arg(
+                    Arg::with_name("x")
+                        .help(
+                            "The x coordinate of the pattern's top left corner. This should \
+                             be omitted when using --anchor or --at.",
+                        )
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("y")
+                        .help(
+                            "The y coordinate of the pattern's top left corner. This should \
+                             be omitted when using --anchor or --at.",
+                        )
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("width")
+                        .help("The width of the pattern.")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("height")
+                        .help("The height of the pattern.")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("replay")
+                .about("Ping a list of addresses captured with --dry-run or --output-json")
+                .arg(
+                    Arg::with_name("file")
+                        .help(
+                            "A file containing one IPv6 address per line or the output \
+                             of --output-json, or - to read from STDIN. Invalid \
+                             addresses are reported and skipped. Repetitions captured \
+                             in the file are replayed as well, on top of -r.",
+                        )
+                        .required(true)
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("serve")
+                .about("Wait for images to be uploaded over HTTP and draw the latest one")
+                .long_about(
+                    "Wait for images to be uploaded over HTTP and draw the latest \
+                     one. Images are uploaded by POSTing the image data, and the \
+                     position and size can be passed as query parameters, e.g. \
+                     'curl --data-binary @image.png \
+                     \"http://127.0.0.1:8080/?x=10&y=10&width=100\"'. Like the \
+                     positional arguments, these can also be percentages of the \
+                     screen's size. The options for resizing and filtering \
+                     images apply to uploaded images as well.",
+                )
+                .arg(
+                    Arg::with_name("address")
+                        .help("The address and port to listen on.")
+                        .takes_value(true)
+                        .validator(|address| {
+                            address
+                                .parse::<SocketAddr>()
+                                .map(|_| ())
+                                .map_err(|_| format!("'{}' is not a valid address and port", address))
+                        })
+                        .default_value("127.0.0.1:8080"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("clock")
+                .about("Draw the current time and update it every second")
+                .arg(
+                    Arg::with_name("font")
+                        .long("font")
+                        .help("The TrueType or OpenType font to draw the time with.")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .help("The strftime-style format to draw the time in, e.g. %H:%M.")
+                        .takes_value(true)
+                        .validator(clock::validate_format)
+                        .default_value(clock::DEFAULT_FORMAT),
+                )
+                .arg(
+                    Arg::with_name("size")
+                        .long("size")
+                        .help("The height of the text in pixels.")
+                        .takes_value(true)
+                        .validator(validate_text_size)
+                        .default_value("24"),
+                )
+                .arg(
+                    Arg::with_name("color")
+                        .long("color")
+                        .help("The color to draw the time in, in RRGGBB or RRGGBBAA format.")
+                        .takes_value(true)
+                        .validator(|color| {
+                            parse_color(&color)
+                                .map(|_| ())
+                                .map_err(|err| err.to_string())
+                        })
+                        .default_value("ffffff"),
+                )
+                .arg(
+                    Arg::with_name("x")
+                        .help(
+                            "The x coordinate of the clock's top left corner. This should \
+                             be omitted when using --anchor or --at.",
+                        )
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("y")
+                        .help(
+                            "The y coordinate of the clock's top left corner. This should \
+                             be omitted when using --anchor or --at.",
+                        )
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("countdown")
+                .about("Draw the time left until a moment and update it every second")
+                .arg(
+                    Arg::with_name("target")
+                        .help(
+                            "When the countdown ends. This can be a duration like 5m or \
+                             '1h 30m', a time like 18:30, or a date and a time like \
+                             '2019-12-27 18:30'.",
+                        )
+                        .takes_value(true)
+                        .validator(|target| clock::parse_target(&target).map(|_| ()))
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("font")
+                        .long("font")
+                        .help("The TrueType or OpenType font to draw the countdown with.")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("size")
+                        .long("size")
+                        .help("The height of the text in pixels.")
+                        .takes_value(true)
+                        .validator(validate_text_size)
+                        .default_value("24"),
+                )
+                .arg(
+                    Arg::with_name("color")
+                        .long("color")
+                        .help("The color to draw the countdown in, in RRGGBB or RRGGBBAA format.")
+                        .takes_value(true)
+                        .validator(|color| {
+                            parse_color(&color)
+                                .map(|_| ())
+                                .map_err(|err| err.to_string())
+                        })
+                        .default_value("ffffff"),
+                )
+                .arg(
+                    Arg::with_name("done_text")
+                        .long("done-text")
+                        .help("The text to draw once the countdown has ended instead of zero.")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("flash")
+                        .long("flash")
+                        .help("Flash the text once the countdown has ended."),
+                )
+                .arg(
+                    Arg::with_name("x")
+                        .help(
+                            "The x coordinate of the countdown's top left corner. This \
+                             should be omitted when using --anchor or --at.",
+                        )
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("y")
+                        .help(
+                            "The y coordinate of the countdown's top left corner. This \
+                             should be omitted when using --anchor or --at.",
+                        )
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("bench")
+                .about("Measure how many packets per second can be sent")
+                .long_about(
+                    "Measure how many packets per second can be sent by drawing a \
+                     generated image in the top left corner of the screen, and \
+                     print the lowest, average, and highest rate afterwards along \
+                     with the number of errors. This can be used to compare \
+                     backends and thread counts. The other options for drawing, \
+                     like --backend, --threads, --rate, and -r, apply here as \
+                     well.",
+                )
+                .arg(
+                    Arg::with_name("size")
+                        .long("size")
+                        .help("The width and height of the generated image.")
+                        .takes_value(true)
+                        .validator(|size| match size.parse::<u32>() {
+                            Ok(size) if size > 0 => Ok(()),
+                            _ => Err(String::from(
+                                "The benchmark's image size should be a positive integer",
+                            )),
+                        })
+                        .default_value("500"),
+                )
+                .arg(
+                    Arg::with_name("duration")
+                        .long("duration")
+                        .help("How long to run the benchmark for, e.g. 10s or 1m.")
+                        .takes_value(true)
+                        .validator(|duration| {
+                            humantime::parse_duration(&duration)
+                                .map(|_| ())
+                                .map_err(|err| err.to_string())
+                        })
+                        .default_value("10s"),
+                ),
+        )
+}
+
+/// The lowest rate accepted by options that are measured in packets, frames, or
+/// pixels per second. The time between two of those has to fit in a
+/// [`Duration`](std::time::Duration), and anything this slow isn't useful
+/// anyway.
+pub const MIN_RATE: f64 = 0.001;
+
+/// Check that a rate is a finite number of at least [`MIN_RATE`]. `name`
+/// describes the rate in the error message.
+pub fn check_rate(rate: f64, name: &str) -> Result<(), String> {
+    if rate >= MIN_RATE && rate.is_finite() {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} should be a number of at least {}",
+            name, MIN_RATE
+        ))
+    }
+}
+
+/// Validate a rate passed on the command line, see [`check_rate()`].
+pub fn validate_rate(value: &str, name: &str) -> Result<(), String> {
+    check_rate(value.parse().unwrap_or(f64::NAN), name)
+}
+
+/// Validate the screen's width or height.
+pub fn validate_dimension(value: String) -> Result<(), String> {
+    match value.parse::<u32>() {
+        Ok(value) if value > 0 => Ok(()),
+        _ => Err(String::from(
+            "The screen's dimensions should be positive integers",
+        )),
+    }
+}
+
+/// Check that a `--size` for text is a positive, finite number of pixels.
+pub fn validate_text_size(value: String) -> Result<(), String> {
+    match value.parse::<f32>() {
+        Ok(size) if size > 0.0 && size.is_finite() => Ok(()),
+        _ => Err(String::from("The text size should be a positive number")),
+    }
+}
+
+/// Parse a positional coordinate or dimension that can also be a percentage of
+/// `screen_size`. This returns an error if it's missing or out of range.
+pub fn parse_length<T: TryFrom<i64>>(
+    name: &str,
+    value: Option<&str>,
+    screen_size: u32,
+) -> Result<T, AppError> {
+    let pixels = parse_positional::<Length>(name, value)?.to_pixels(screen_size);
+    T::try_from(pixels).map_err(|_| {
+        AppError::usage(
+            &format!("Invalid value for '<{}>': {} is out of range", name, pixels),
+            ErrorKind::ValueValidation,
+        )
+    })
+}
+
+/// Parse a positional coordinate like [`parse_length()`], but also return an
+/// error if it doesn't lie on the screen.
+pub fn parse_coordinate(
+    name: &str,
+    value: Option<&str>,
+    screen_size: u32,
+) -> Result<u16, AppError> {
+    let length = parse_positional::<Length>(name, value)?;
+    placement::to_coordinate(name, length, screen_size).map_err(|err| {
+        AppError::usage(
+            &format!("Invalid value for '<{}>': {}", name, err),
+            ErrorKind::ValueValidation,
+        )
+    })
+}
+
+/// Parse a positional argument. This returns an error if it's missing or if it
+/// could not be parsed.
+pub fn parse_positional<T>(name: &str, value: Option<&str>) -> Result<T, AppError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let value = value.ok_or_else(|| {
+        AppError::usage(
+            &format!("The argument '<{}>' was not provided", name),
+            ErrorKind::MissingRequiredArgument,
+        )
+    })?;
+
+    value.parse().map_err(|err| {
+        AppError::usage(
+            &format!("Invalid value for '<{}>': {}", name, err),
+            ErrorKind::ValueValidation,
+        )
+    })
+}
+
+/// Parse the factor for `--scale`, which can optionally end with an `x`.
+pub fn parse_scale(scale: &str) -> Result<u32, String> {
+    match scale.trim_end_matches('x').parse::<u32>() {
+        Ok(factor) if factor > 0 => Ok(factor),
+        _ => Err(format!(
+            "'{}' should be a positive whole number like 8x",
+            scale
+        )),
+    }
+}
+
+/// Parse the size of the frames for `--raw-frames` in the `WIDTHxHEIGHT`
+/// format.
+pub fn parse_frame_size(size: &str) -> Result<(u32, u32), String> {
+    let error = || format!("'{}' should be in the WIDTHxHEIGHT format", size);
+    let (width, height) = size.split_once('x').ok_or_else(error)?;
+    match (width.trim().parse(), height.trim().parse()) {
+        (Ok(0), _) | (_, Ok(0)) => Err(String::from("The frames can't be empty")),
+        (Ok(width), Ok(height))
+            if u64::from(width) * u64::from(height) * 4 > MAX_FRAME_SIZE as u64 =>
+        {
+            Err(format!(
+                "Frames of {}x{} pixels are too large, raw frames can be at most {} MiB",
+                width,
+                height,
+                MAX_FRAME_SIZE / 1024 / 1024
+            ))
+        }
+        (Ok(width), Ok(height)) => Ok((width, height)),
+        _ => Err(error()),
+    }
+}
+
+/// Parse a crop rectangle in the `x,y,width,height` format.
+pub fn parse_crop(crop: &str) -> Result<(u32, u32, u32, u32), String> {
+    let values: Vec<u32> = crop
+        .split(',')
+        .map(|value| value.trim().parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("'{}' should be in the x,y,width,height format", crop))?;
+
+    match values[..] {
+        [_, _, 0, _] | [_, _, _, 0] => Err(String::from("The crop can't be empty")),
+        [x, y, width, height] => Ok((x, y, width, height)),
+        _ => Err(format!(
+            "'{}' should be in the x,y,width,height format",
+            crop
+        )),
+    }
+}
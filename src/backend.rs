@@ -0,0 +1,337 @@
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+use std::io;
+use std::net::{IpAddr, Ipv6Addr, SocketAddrV6};
+use std::process;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::RwLock;
+use std::thread;
+use std::time::Duration;
+
+use crate::error::Error;
+
+/// The maximum number of packets passed to a single `sendmmsg()` call.
+#[cfg(target_os = "linux")]
+const BATCH_SIZE: usize = 1024;
+
+/// How long to wait before sending failed pings again. Sends usually fail
+/// because the send queue is full, so retrying right away would likely fail
+/// again.
+const RETRY_DELAY: Duration = Duration::from_millis(1);
+
+/// The largest payload an echo request can carry. This is the largest IPv6
+/// payload minus the 8 byte ICMPv6 header. Packets larger than the link's MTU
+/// get fragmented, which for the minimum IPv6 MTU of 1280 bytes happens for
+/// payloads larger than 1232 bytes.
+pub const MAX_PAYLOAD_SIZE: usize = 65535 - 8;
+
+/// How the ping packets get sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    /// Send pings through fastping_rs. This also listens for replies, which is
+    /// how unanswered pings get counted.
+    #[default]
+    Fastping,
+    /// Build the ICMPv6 echo requests ourselves and write them straight to a
+    /// raw socket. On Linux the packets are sent in batches using
+    /// `sendmmsg()`, which is a lot faster than sending them one by one. This
+    /// doesn't listen for replies.
+    ///
+    /// Like fastping_rs this needs to be able to open raw sockets, so on Linux
+    /// the process either has to run as root or it needs the `CAP_NET_RAW`
+    /// capability.
+    Raw,
+}
+
+/// Where the pings get sent from. On a host with multiple network interfaces
+/// this makes sure the pings leave through the interface connected to the
+/// screen. This is only supported by the raw backend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Binding {
+    /// Send the pings from this local address.
+    Address(Ipv6Addr),
+    /// Send the pings through this network interface, from one of its global
+    /// IPv6 addresses.
+    Interface(String),
+}
+
+impl Binding {
+    /// Find the address to bind to, along with the interface to bind to if
+    /// there is one. This returns an error if the interface does not exist or
+    /// if it doesn't have a global IPv6 address.
+    pub(crate) fn resolve(&self) -> Result<(Ipv6Addr, Option<&str>), Error> {
+        match self {
+            Binding::Address(address) => Ok((*address, None)),
+            Binding::Interface(name) => {
+                let addresses = interface_addresses(name)
+                    .map_err(|err| Error::Bind(err.to_string()))?
+                    .ok_or_else(|| {
+                        Error::Bind(format!("There is no interface named '{}'", name))
+                    })?;
+
+                // Unique local addresses are fine since the screen could be on
+                // a private network, but link-local addresses are not
+                addresses
+                    .into_iter()
+                    .find(|address| {
+                        !address.is_loopback()
+                            && !address.is_unspecified()
+                            && !address.is_multicast()
+                            && address.segments()[0] & 0xffc0 != 0xfe80
+                    })
+                    .map(|address| (address, Some(name.as_str())))
+                    .ok_or_else(|| {
+                        Error::Bind(format!(
+                            "The interface '{}' does not have a global IPv6 address",
+                            name
+                        ))
+                    })
+            }
+        }
+    }
+}
+
+/// All IPv6 addresses assigned to the interface called `name`, or `None` if
+/// there is no such interface.
+#[cfg(unix)]
+fn interface_addresses(name: &str) -> io::Result<Option<Vec<Ipv6Addr>>> {
+    use std::ffi::CStr;
+    use std::ptr;
+
+    let mut interfaces = ptr::null_mut();
+    if unsafe { libc::getifaddrs(&mut interfaces) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // Every address gets its own entry in this linked list, and interfaces
+    // without any addresses still get an entry with a null address
+    let mut found = false;
+    let mut addresses = Vec::new();
+    let mut current = interfaces;
+    while !current.is_null() {
+        let interface = unsafe { &*current };
+        let interface_name = unsafe { CStr::from_ptr(interface.ifa_name) };
+        if interface_name.to_bytes() == name.as_bytes() {
+            found = true;
+
+            let address = interface.ifa_addr;
+            if !address.is_null() && i32::from(unsafe { (*address).sa_family }) == libc::AF_INET6 {
+                let address = unsafe { &*(address as *const libc::sockaddr_in6) };
+                addresses.push(Ipv6Addr::from(address.sin6_addr.s6_addr));
+            }
+        }
+
+        current = interface.ifa_next;
+    }
+    unsafe { libc::freeifaddrs(interfaces) };
+
+    Ok(if found { Some(addresses) } else { None })
+}
+
+#[cfg(not(unix))]
+fn interface_addresses(_name: &str) -> io::Result<Option<Vec<Ipv6Addr>>> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "Binding to an interface is not supported on this platform",
+    ))
+}
+
+/// A raw ICMPv6 socket for sending echo requests to a fixed set of addresses.
+pub(crate) struct RawSocket {
+    socket: Socket,
+    /// The sequence number is incremented after every pass over the addresses.
+    sequence: AtomicU16,
+    /// The number of zero bytes appended to every echo request.
+    payload_size: usize,
+    targets: RwLock<Vec<SockAddr>>,
+}
+
+impl RawSocket {
+    /// Open a new socket. If `binding` is set, this is the address and
+    /// optionally the interface returned by [`Binding::resolve()`]. The echo
+    /// requests carry `payload_size` bytes of padding, which should be at most
+    /// [`MAX_PAYLOAD_SIZE`].
+    pub fn new(
+        binding: Option<(Ipv6Addr, Option<&str>)>,
+        payload_size: usize,
+    ) -> Result<Self, Error> {
+        let socket = Socket::new(Domain::ipv6(), Type::raw(), Some(Protocol::icmpv6()))
+            .map_err(|err| Error::Pinger(err.to_string()))?;
+        if let Some((address, interface)) = binding {
+            if let Some(interface) = interface {
+                bind_device(&socket, interface).map_err(|err| {
+                    Error::Bind(format!("Can't bind to interface '{}': {}", interface, err))
+                })?;
+            }
+
+            socket
+                .bind(&SockAddr::from(SocketAddrV6::new(address, 0, 0, 0)))
+                .map_err(|err| Error::Bind(format!("Can't bind to {}: {}", address, err)))?;
+        }
+
+        Ok(RawSocket {
+            socket,
+            sequence: AtomicU16::new(0),
+            payload_size,
+            targets: RwLock::new(Vec::new()),
+        })
+    }
+
+    /// Replace the addresses pings get sent to. IPv4 addresses are ignored.
+    pub fn set_targets(&self, addresses: &[IpAddr]) {
+        *self.targets.write().unwrap() = addresses
+            .iter()
+            .filter_map(|address| match address {
+                IpAddr::V6(address) => Some(SockAddr::from(SocketAddrV6::new(*address, 0, 0, 0))),
+                IpAddr::V4(_) => None,
+            })
+            .collect();
+    }
+
+    /// Send an echo request to every target. Pings that could not be sent are
+    /// sent again up to `retries` times. Sends that still failed after that
+    /// are logged through the `log` crate, just like fastping_rs does it.
+    /// Returns the number of packets that were sent again, and the number of
+    /// packets that could not be sent at all.
+    pub fn send(&self, retries: usize) -> (usize, usize) {
+        // The kernel fills in the checksum for ICMPv6 sockets, and since the
+        // packet doesn't contain the destination we can send the same packet
+        // to every address
+        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed);
+        let identifier = process::id() as u16;
+        let mut packet = vec![0; 8 + self.payload_size];
+        packet[0] = 128;
+        packet[4..6].copy_from_slice(&identifier.to_be_bytes());
+        packet[6..8].copy_from_slice(&sequence.to_be_bytes());
+
+        let mut failed = send_packets(&self.socket, &packet, &self.targets.read().unwrap());
+        let mut retried = 0;
+        for _ in 0..retries {
+            if failed.is_empty() {
+                break;
+            }
+
+            thread::sleep(RETRY_DELAY);
+            retried += failed.len();
+            let targets: Vec<SockAddr> = failed
+                .into_iter()
+                .map(|(target, _)| SockAddr::from(target))
+                .collect();
+            failed = send_packets(&self.socket, &packet, &targets);
+        }
+
+        for (target, err) in &failed {
+            log::error!("Failed to send ping to {}: {}", target.ip(), err);
+        }
+
+        (retried, failed.len())
+    }
+}
+
+/// Send `packet` to every address in `targets`, which should all be IPv6
+/// addresses. Returns the targets the packet could not be sent to, along with
+/// the reason why.
+#[cfg(target_os = "linux")]
+fn send_packets(
+    socket: &Socket,
+    packet: &[u8],
+    targets: &[SockAddr],
+) -> Vec<(SocketAddrV6, io::Error)> {
+    use std::mem;
+    use std::os::unix::io::AsRawFd;
+
+    let mut failed = Vec::new();
+    let mut iov = libc::iovec {
+        iov_base: packet.as_ptr() as *mut libc::c_void,
+        iov_len: packet.len(),
+    };
+
+    for targets in targets.chunks(BATCH_SIZE) {
+        let mut messages: Vec<libc::mmsghdr> = targets
+            .iter()
+            .map(|target| {
+                // Not all fields of `msghdr` are public on every libc
+                let mut header: libc::msghdr = unsafe { mem::zeroed() };
+                header.msg_name = target.as_ptr() as *mut libc::c_void;
+                header.msg_namelen = target.len();
+                header.msg_iov = &mut iov;
+                header.msg_iovlen = 1;
+
+                libc::mmsghdr {
+                    msg_hdr: header,
+                    msg_len: 0,
+                }
+            })
+            .collect();
+
+        // `sendmmsg()` stops at the first packet that could not be sent, so
+        // we'll skip over that packet and try again with the rest
+        let mut sent = 0;
+        while sent < messages.len() {
+            let result = unsafe {
+                libc::sendmmsg(
+                    socket.as_raw_fd(),
+                    messages[sent..].as_mut_ptr(),
+                    (messages.len() - sent) as libc::c_uint,
+                    0,
+                )
+            };
+
+            if result < 0 {
+                failed.push((
+                    targets[sent].as_inet6().unwrap(),
+                    io::Error::last_os_error(),
+                ));
+                sent += 1;
+            } else {
+                sent += result as usize;
+            }
+        }
+    }
+
+    failed
+}
+
+#[cfg(not(target_os = "linux"))]
+fn send_packets(
+    socket: &Socket,
+    packet: &[u8],
+    targets: &[SockAddr],
+) -> Vec<(SocketAddrV6, io::Error)> {
+    targets
+        .iter()
+        .filter_map(|target| match socket.send_to(packet, target) {
+            Ok(_) => None,
+            Err(err) => Some((target.as_inet6().unwrap(), err)),
+        })
+        .collect()
+}
+
+/// Make `socket` only send packets through `interface`. Binding to one of the
+/// interface's addresses is not enough on its own, since the routing table
+/// decides which interface the packets leave through.
+#[cfg(target_os = "linux")]
+fn bind_device(socket: &Socket, interface: &str) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_BINDTODEVICE,
+            interface.as_ptr() as *const libc::c_void,
+            interface.len() as libc::socklen_t,
+        )
+    };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Other platforms don't have `SO_BINDTODEVICE`, so there we only bind to the
+/// interface's address.
+#[cfg(not(target_os = "linux"))]
+fn bind_device(_socket: &Socket, _interface: &str) -> io::Result<()> {
+    Ok(())
+}
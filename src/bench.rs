@@ -0,0 +1,117 @@
+//! Measuring how many packets per second can be sent for the `bench`
+//! subcommand, for comparing backends and thread counts.
+
+use image::{Rgba, RgbaImage};
+use pingas::Stats;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::logging;
+
+/// A square image of `size` by `size` pixels where every pixel has a different
+/// color, so nothing gets skipped as being the same as a previous pixel.
+pub fn test_image(size: u32) -> RgbaImage {
+    RgbaImage::from_fn(size, size, |x, y| {
+        let index = y * size + x;
+        Rgba([(index >> 16) as u8, (index >> 8) as u8, index as u8, 255])
+    })
+}
+
+/// Records the number of packets sent every second in the background.
+pub struct Sampler {
+    stats: Stats,
+    /// The number of failed sends before the benchmark started, since fastping_rs
+    /// only reports these through a global counter.
+    initial_errors: u64,
+    done: Arc<AtomicBool>,
+    handle: JoinHandle<Vec<u64>>,
+}
+
+/// The results of a benchmark, printed at the end.
+#[derive(Debug)]
+pub struct Summary {
+    /// The number of packets sent during every whole second of the benchmark.
+    rates: Vec<u64>,
+    packets_sent: u64,
+    failed_sends: u64,
+    retried_sends: u64,
+    unanswered: u64,
+}
+
+impl Sampler {
+    /// Start sampling the packet rate from `stats` for every whole second in
+    /// `duration`.
+    pub fn start(stats: Stats, duration: Duration) -> Self {
+        let done = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let stats = stats.clone();
+            let done = done.clone();
+            thread::spawn(move || {
+                let mut rates = Vec::new();
+                let mut last_sent = stats.packets_sent();
+                for _ in 0..duration.as_secs() {
+                    thread::sleep(Duration::from_secs(1));
+                    // Drawing can also be stopped early with Ctrl-C, and that
+                    // second would only be partially filled
+                    if done.load(Ordering::Relaxed) {
+                        break;
+                    }
+
+                    let sent = stats.packets_sent();
+                    rates.push(sent - last_sent);
+                    last_sent = sent;
+                }
+
+                rates
+            })
+        };
+
+        Sampler {
+            stats,
+            initial_errors: logging::SEND_ERRORS.load(Ordering::Relaxed),
+            done,
+            handle,
+        }
+    }
+
+    /// Stop sampling and summarize the results.
+    pub fn finish(self) -> Summary {
+        self.done.store(true, Ordering::Relaxed);
+        let rates = self.handle.join().unwrap();
+
+        Summary {
+            rates,
+            packets_sent: self.stats.packets_sent(),
+            failed_sends: logging::SEND_ERRORS.load(Ordering::Relaxed) - self.initial_errors,
+            retried_sends: self.stats.retried_sends(),
+            unanswered: self.stats.unanswered(),
+        }
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.rates.iter().min(), self.rates.iter().max()) {
+            (Some(min), Some(max)) => writeln!(
+                f,
+                "Packets per second: {} min, {:.0} avg, {} max over {} seconds",
+                min,
+                self.rates.iter().sum::<u64>() as f64 / self.rates.len() as f64,
+                max,
+                self.rates.len()
+            )?,
+            _ => writeln!(
+                f,
+                "Packets per second: the benchmark did not run for a full second"
+            )?,
+        }
+        write!(
+            f,
+            "Packets: {} sent, {} failed to send, {} retried, {} unanswered",
+            self.packets_sent, self.failed_sends, self.retried_sends, self.unanswered
+        )
+    }
+}
@@ -0,0 +1,355 @@
+//! Capturing frames from a webcam for `--camera`. This talks to Video4Linux
+//! directly, so it only works on Linux.
+
+use image::{ImageError, ImageFormat, Rgba, RgbaImage};
+use std::ffi::CString;
+use std::io;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::ptr;
+use std::slice;
+
+/// The resolution we ask the camera for. The driver picks the closest
+/// resolution it supports, and the frames get resized afterwards anyway.
+const CAPTURE_SIZE: (u32, u32) = (640, 480);
+/// The number of buffers the driver fills in the background.
+const NUM_BUFFERS: u32 = 4;
+/// How long to wait for a new frame before giving up, in milliseconds.
+const CAPTURE_TIMEOUT: i32 = 2000;
+
+const V4L2_BUF_TYPE_VIDEO_CAPTURE: u32 = 1;
+const V4L2_MEMORY_MMAP: u32 = 1;
+const V4L2_FIELD_NONE: u32 = 1;
+const V4L2_CAP_VIDEO_CAPTURE: u32 = 0x0000_0001;
+const V4L2_CAP_STREAMING: u32 = 0x0400_0000;
+const V4L2_PIX_FMT_YUYV: u32 = fourcc(b"YUYV");
+const V4L2_PIX_FMT_MJPEG: u32 = fourcc(b"MJPG");
+
+const VIDIOC_QUERYCAP: libc::c_ulong = ioctl_number(2, 0, mem::size_of::<Capability>());
+const VIDIOC_S_FMT: libc::c_ulong = ioctl_number(3, 5, mem::size_of::<Format>());
+const VIDIOC_REQBUFS: libc::c_ulong = ioctl_number(3, 8, mem::size_of::<RequestBuffers>());
+const VIDIOC_QUERYBUF: libc::c_ulong = ioctl_number(3, 9, mem::size_of::<Buffer>());
+const VIDIOC_QBUF: libc::c_ulong = ioctl_number(3, 15, mem::size_of::<Buffer>());
+const VIDIOC_DQBUF: libc::c_ulong = ioctl_number(3, 17, mem::size_of::<Buffer>());
+const VIDIOC_STREAMON: libc::c_ulong = ioctl_number(1, 18, mem::size_of::<u32>());
+const VIDIOC_STREAMOFF: libc::c_ulong = ioctl_number(1, 19, mem::size_of::<u32>());
+
+const fn fourcc(code: &[u8; 4]) -> u32 {
+    code[0] as u32 | (code[1] as u32) << 8 | (code[2] as u32) << 16 | (code[3] as u32) << 24
+}
+
+/// The equivalent of the kernel's `_IOC()` macro for the `'V'` ioctls, where
+/// `direction` is 1 for writing, 2 for reading, and 3 for both.
+const fn ioctl_number(
+    direction: libc::c_ulong,
+    number: libc::c_ulong,
+    size: usize,
+) -> libc::c_ulong {
+    direction << 30 | (size as libc::c_ulong) << 16 | (b'V' as libc::c_ulong) << 8 | number
+}
+
+// These mirror the structs from `linux/videodev2.h`, but only the fields we
+// use get meaningful names
+
+#[repr(C)]
+struct Capability {
+    driver: [u8; 16],
+    card: [u8; 32],
+    bus_info: [u8; 32],
+    version: u32,
+    capabilities: u32,
+    device_caps: u32,
+    reserved: [u32; 3],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct PixFormat {
+    width: u32,
+    height: u32,
+    pixel_format: u32,
+    field: u32,
+    bytes_per_line: u32,
+    size_image: u32,
+    colorspace: u32,
+    private: u32,
+    flags: u32,
+    encoding: u32,
+    quantization: u32,
+    transfer_function: u32,
+}
+
+/// The union in `v4l2_format` also contains structs with pointers, which is
+/// where the alignment comes from.
+#[repr(C)]
+union FormatData {
+    pix: PixFormat,
+    raw: [u64; 25],
+}
+
+#[repr(C)]
+struct Format {
+    buffer_type: u32,
+    data: FormatData,
+}
+
+#[repr(C)]
+struct RequestBuffers {
+    count: u32,
+    buffer_type: u32,
+    memory: u32,
+    reserved: [u32; 2],
+}
+
+#[repr(C)]
+union BufferLocation {
+    offset: u32,
+    user_pointer: libc::c_ulong,
+}
+
+#[repr(C)]
+struct Buffer {
+    index: u32,
+    buffer_type: u32,
+    bytes_used: u32,
+    flags: u32,
+    field: u32,
+    timestamp: libc::timeval,
+    timecode: [u32; 4],
+    sequence: u32,
+    memory: u32,
+    location: BufferLocation,
+    length: u32,
+    reserved: [u32; 2],
+}
+
+impl Buffer {
+    fn new(index: u32) -> Self {
+        let mut buffer: Buffer = unsafe { mem::zeroed() };
+        buffer.index = index;
+        buffer.buffer_type = V4L2_BUF_TYPE_VIDEO_CAPTURE;
+        buffer.memory = V4L2_MEMORY_MMAP;
+
+        buffer
+    }
+}
+
+/// A video capture device that's streaming frames into memory mapped buffers.
+/// Streaming stops when this gets dropped.
+pub struct Camera {
+    fd: libc::c_int,
+    buffers: Vec<&'static [u8]>,
+    pixel_format: u32,
+    width: u32,
+    height: u32,
+    bytes_per_line: u32,
+}
+
+impl Camera {
+    /// Open the video device at `path` and start capturing. Cameras that can
+    /// output uncompressed YUYV frames or Motion JPEG are supported.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let c_path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        let fd = unsafe { libc::open(c_path.as_ptr(), libc::O_RDWR | libc::O_NONBLOCK) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // The file descriptor gets closed when this is dropped, so any errors
+        // from here on don't leak it
+        let mut camera = Camera {
+            fd,
+            buffers: Vec::new(),
+            pixel_format: 0,
+            width: 0,
+            height: 0,
+            bytes_per_line: 0,
+        };
+
+        let mut capability: Capability = unsafe { mem::zeroed() };
+        camera.ioctl(VIDIOC_QUERYCAP, &mut capability)?;
+        let required = V4L2_CAP_VIDEO_CAPTURE | V4L2_CAP_STREAMING;
+        if capability.capabilities & required != required {
+            return Err(io::Error::other(format!(
+                "'{}' can't stream video",
+                path.display()
+            )));
+        }
+
+        // The driver changes the format to the closest one it supports, so if
+        // it doesn't do YUYV we'll try again with Motion JPEG
+        let mut pix = camera.set_format(V4L2_PIX_FMT_YUYV)?;
+        if pix.pixel_format != V4L2_PIX_FMT_YUYV {
+            pix = camera.set_format(V4L2_PIX_FMT_MJPEG)?;
+        }
+        if pix.pixel_format != V4L2_PIX_FMT_YUYV && pix.pixel_format != V4L2_PIX_FMT_MJPEG {
+            return Err(io::Error::other(format!(
+                "'{}' only supports unknown pixel formats",
+                path.display()
+            )));
+        }
+        camera.pixel_format = pix.pixel_format;
+        camera.width = pix.width;
+        camera.height = pix.height;
+        camera.bytes_per_line = pix.bytes_per_line.max(pix.width * 2);
+
+        let mut request = RequestBuffers {
+            count: NUM_BUFFERS,
+            buffer_type: V4L2_BUF_TYPE_VIDEO_CAPTURE,
+            memory: V4L2_MEMORY_MMAP,
+            reserved: [0; 2],
+        };
+        camera.ioctl(VIDIOC_REQBUFS, &mut request)?;
+        for index in 0..request.count {
+            let mut buffer = Buffer::new(index);
+            camera.ioctl(VIDIOC_QUERYBUF, &mut buffer)?;
+            let memory = unsafe {
+                libc::mmap(
+                    ptr::null_mut(),
+                    buffer.length as usize,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_SHARED,
+                    fd,
+                    libc::off_t::from(buffer.location.offset),
+                )
+            };
+            if memory == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
+
+            camera.buffers.push(unsafe {
+                slice::from_raw_parts(memory as *const u8, buffer.length as usize)
+            });
+            camera.ioctl(VIDIOC_QBUF, &mut buffer)?;
+        }
+
+        let mut buffer_type = V4L2_BUF_TYPE_VIDEO_CAPTURE;
+        camera.ioctl(VIDIOC_STREAMON, &mut buffer_type)?;
+
+        Ok(camera)
+    }
+
+    /// Wait for the next frame and convert it to an RGBA image. The driver
+    /// keeps capturing in the background, so any older frames that have piled
+    /// up since the last call are skipped.
+    pub fn capture(&mut self) -> Result<RgbaImage, ImageError> {
+        let mut poll = libc::pollfd {
+            fd: self.fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        match unsafe { libc::poll(&mut poll, 1, CAPTURE_TIMEOUT) } {
+            0 => {
+                return Err(ImageError::from(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "The camera stopped sending frames",
+                )))
+            }
+            result if result < 0 => return Err(ImageError::from(io::Error::last_os_error())),
+            _ => (),
+        }
+
+        // The device was opened in non-blocking mode, so this drains every
+        // filled buffer and keeps only the last one
+        let mut latest: Option<Buffer> = None;
+        loop {
+            let mut buffer = Buffer::new(0);
+            match self.ioctl(VIDIOC_DQBUF, &mut buffer) {
+                Ok(()) => {
+                    if let Some(mut older) = latest.replace(buffer) {
+                        self.ioctl(VIDIOC_QBUF, &mut older)?;
+                    }
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) => return Err(ImageError::from(err)),
+            }
+        }
+        let mut buffer = latest.ok_or_else(|| {
+            ImageError::from(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                "The camera did not have a frame ready",
+            ))
+        })?;
+
+        let data = &self.buffers[buffer.index as usize][..buffer.bytes_used as usize];
+        let image = if self.pixel_format == V4L2_PIX_FMT_MJPEG {
+            image::load_from_memory_with_format(data, ImageFormat::JPEG)
+                .map(|image| image.to_rgba())
+        } else {
+            Ok(yuyv_to_rgba(
+                data,
+                self.width,
+                self.height,
+                self.bytes_per_line,
+            ))
+        };
+        self.ioctl(VIDIOC_QBUF, &mut buffer)?;
+
+        image
+    }
+
+    fn set_format(&self, pixel_format: u32) -> io::Result<PixFormat> {
+        let mut format: Format = unsafe { mem::zeroed() };
+        format.buffer_type = V4L2_BUF_TYPE_VIDEO_CAPTURE;
+        format.data.pix = PixFormat {
+            width: CAPTURE_SIZE.0,
+            height: CAPTURE_SIZE.1,
+            pixel_format,
+            field: V4L2_FIELD_NONE,
+            ..unsafe { mem::zeroed() }
+        };
+        self.ioctl(VIDIOC_S_FMT, &mut format)?;
+
+        Ok(unsafe { format.data.pix })
+    }
+
+    fn ioctl<T>(&self, request: libc::c_ulong, argument: &mut T) -> io::Result<()> {
+        // Interrupted calls should simply be retried
+        loop {
+            if unsafe { libc::ioctl(self.fd, request as _, argument as *mut T) } >= 0 {
+                return Ok(());
+            }
+
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+    }
+}
+
+impl Drop for Camera {
+    fn drop(&mut self) {
+        let mut buffer_type = V4L2_BUF_TYPE_VIDEO_CAPTURE;
+        let _ = self.ioctl(VIDIOC_STREAMOFF, &mut buffer_type);
+        for buffer in &self.buffers {
+            unsafe { libc::munmap(buffer.as_ptr() as *mut libc::c_void, buffer.len()) };
+        }
+        unsafe { libc::close(self.fd) };
+    }
+}
+
+/// Convert a YUYV frame, where every four bytes describe two pixels that share
+/// their chroma values, using the BT.601 coefficients webcams use.
+fn yuyv_to_rgba(data: &[u8], width: u32, height: u32, bytes_per_line: u32) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |x, y| {
+        let start = (y * bytes_per_line + (x / 2) * 4) as usize;
+        match data.get(start..start + 4) {
+            Some(&[y0, u, y1, v]) => {
+                let luma = f32::from(if x % 2 == 0 { y0 } else { y1 });
+                let u = f32::from(u) - 128.0;
+                let v = f32::from(v) - 128.0;
+                Rgba([
+                    (luma + 1.402 * v).round().clamp(0.0, 255.0) as u8,
+                    (luma - 0.344 * u - 0.714 * v).round().clamp(0.0, 255.0) as u8,
+                    (luma + 1.772 * u).round().clamp(0.0, 255.0) as u8,
+                    255,
+                ])
+            }
+            // Drivers sometimes hand out truncated frames
+            _ => Rgba([0, 0, 0, 255]),
+        }
+    })
+}
@@ -0,0 +1,1617 @@
+use fastping_rs::{PingResult, Pinger};
+use image::{DynamicImage, FilterType, GenericImageView, Rgba, RgbaImage};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+use std::fmt::{self, Write};
+use std::iter;
+use std::net::IpAddr;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::address::{build_address, PixelLayout, Prefix};
+use crate::animation::{frame_difference, Frame};
+use crate::backend::{Backend, Binding, RawSocket, MAX_PAYLOAD_SIZE};
+use crate::error::Error;
+use crate::limiter::RateLimiter;
+use crate::marquee::{self, ScrollDirection};
+use crate::order::{Order, Pixel};
+use crate::stats::Stats;
+use crate::transition::Transition;
+
+/// How long a worker without any pixels to draw should wait before checking
+/// whether there's something new to draw.
+const IDLE_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How often we check whether drawing should be stopped while waiting.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A single pixel that will be drawn, as returned by [`Canvas::pings()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ping {
+    /// The x coordinate on the screen.
+    pub x: u16,
+    /// The y coordinate on the screen.
+    pub y: u16,
+    pub color: Rgba<u8>,
+    /// The address that gets pinged to draw this pixel.
+    pub address: IpAddr,
+}
+
+/// The part of the screen an image actually covers after clipping, as returned
+/// by [`Canvas::extent()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Extent {
+    /// The screen coordinates of the top left pixel that gets drawn.
+    pub top_left: (u16, u16),
+    /// The screen coordinates of the bottom right pixel that gets drawn,
+    /// inclusive.
+    pub bottom_right: (u16, u16),
+    /// The number of pixels that get drawn, which excludes completely
+    /// transparent and clipped pixels.
+    pub pixels: usize,
+}
+
+/// The dimensions of the original Jinglepings screen in pixels.
+pub const DEFAULT_SCREEN_SIZE: (u32, u32) = (1920, 1080);
+
+/// How an image gets resized when both a width and a height are given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResizeMode {
+    /// Scale the image to fit within the box while preserving its aspect
+    /// ratio. The image can end up smaller than the box in one dimension.
+    #[default]
+    Fit,
+    /// Scale the image to cover the entire box while preserving its aspect
+    /// ratio, and then crop the center of the image to the size of the box.
+    Fill,
+    /// Scale the image to exactly the size of the box, ignoring its aspect
+    /// ratio.
+    Stretch,
+}
+
+/// Resize an image to `width` pixels wide. If `height` is set the image will be
+/// resized to the `width` by `height` box instead according to `mode`. The same
+/// `filter_type` is used for scaling in every mode.
+///
+/// Unless the image gets stretched or cropped, the dimensions of the returned
+/// image can be slightly different from the ones specified. The image is
+/// always at least one pixel wide and high, unless it was empty to begin with.
+pub fn resize_image(
+    image: &DynamicImage,
+    width: u32,
+    height: Option<u32>,
+    mode: ResizeMode,
+    filter_type: FilterType,
+) -> RgbaImage {
+    if image.width() == 0 || image.height() == 0 {
+        return image.to_rgba();
+    }
+
+    let height_given = height.is_some();
+    let (image_width, image_height) = (u64::from(image.width()), u64::from(image.height()));
+    let size = match (height, mode) {
+        // Without a height we'll calculate the new height based on the given
+        // width, so there's nothing to fill or stretch
+        (None, _) => (
+            width,
+            ((width as f32) / (image.width() as f32) * (image.height() as f32)) as u32,
+        ),
+        // This is the same calculation `DynamicImage::resize()` does
+        (Some(height), ResizeMode::Fit) => {
+            if u64::from(width) * image_height <= u64::from(height) * image_width {
+                (
+                    width,
+                    (image_height * u64::from(width) / image_width) as u32,
+                )
+            } else {
+                (
+                    (image_width * u64::from(height) / image_height) as u32,
+                    height,
+                )
+            }
+        }
+        (Some(height), ResizeMode::Fill | ResizeMode::Stretch) => (width, height),
+    };
+
+    // Very wide or very tall images can otherwise end up zero pixels high or
+    // wide, and then nothing would get drawn at all
+    let (width, height) = (size.0.max(1), size.1.max(1));
+    if (width, height) != size {
+        log::warn!(
+            "The image would be resized to {}x{} pixels, using {}x{} pixels instead",
+            size.0,
+            size.1,
+            width,
+            height
+        );
+    }
+
+    match (height_given, mode) {
+        (true, ResizeMode::Fill) => image.resize_to_fill(width, height, filter_type).to_rgba(),
+        _ => image.resize_exact(width, height, filter_type).to_rgba(),
+    }
+}
+
+/// Scale an image up by an integer `factor` so every pixel becomes a `factor`
+/// by `factor` block of the same color. Unlike resizing with
+/// [`FilterType::Nearest`], this never gets rounded, so pixel art keeps its
+/// exact shape.
+pub fn scale_image(image: &RgbaImage, factor: u32) -> RgbaImage {
+    RgbaImage::from_fn(image.width() * factor, image.height() * factor, |x, y| {
+        *image.get_pixel(x / factor, y / factor)
+    })
+}
+
+/// Mirror an image into a block twice as wide and twice as high, for a
+/// kaleidoscope effect. The original image ends up in the top left, with a
+/// horizontally flipped copy to its right and vertically flipped copies of both
+/// below them.
+pub fn mirror_quad(image: &RgbaImage) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    RgbaImage::from_fn(width * 2, height * 2, |x, y| {
+        let x = if x < width { x } else { width * 2 - 1 - x };
+        let y = if y < height { y } else { height * 2 - 1 - y };
+        *image.get_pixel(x, y)
+    })
+}
+
+/// Draws images to the screen. This is the main entry point when using pingas
+/// as a library.
+#[derive(Debug)]
+pub struct Canvas {
+    /// The pixels are spread over these prefixes round-robin. This always
+    /// contains at least one prefix.
+    prefixes: Vec<Prefix>,
+    /// The width and height of the screen in pixels.
+    screen_size: (u32, u32),
+    /// Drawing stops as soon as this gets set.
+    stop: Arc<AtomicBool>,
+    /// The workers stop sending while this is set, until it gets cleared again.
+    pause: Arc<AtomicBool>,
+    stats: Stats,
+    /// Whether pixels that fall outside of the screen should be dropped. If
+    /// this is not set, drawing an image that doesn't fit on the screen will
+    /// result in an error.
+    clip: bool,
+    /// Whether completely transparent pixels should be left out. Sending them
+    /// blanks out whatever someone else drew in the image's area.
+    skip_transparent: bool,
+    /// The combined send rate of all workers in packets per second, if
+    /// limited.
+    rate: Option<f64>,
+    /// How long it takes for the rate limit to ramp up to its full rate.
+    ramp: Option<Duration>,
+    /// Whether the rate limit should adapt to the number of errors.
+    adaptive_rate: bool,
+    /// The maximum random delay every worker waits before sending a pass, and
+    /// the seed for those delays if they should be reproducible.
+    jitter: Option<(Duration, Option<u64>)>,
+    /// Enforces the rate limit. This gets rebuilt whenever one of the options
+    /// above changes.
+    limiter: Option<Arc<RateLimiter>>,
+    /// Enforces the lower rate used after an image has been drawn once, if
+    /// set.
+    keepalive: Option<Arc<RateLimiter>>,
+    order: Order,
+    /// How colors are packed into the addresses.
+    pixel_layout: PixelLayout,
+    /// The number of times every worker pings its row before stopping, or
+    /// `None` to keep pinging until the stop flag gets set.
+    passes: Option<usize>,
+    /// The total number of packets that may be sent before the stop flag gets
+    /// set, if limited.
+    max_packets: Option<u64>,
+    /// The number of packets the workers have claimed from `max_packets` so
+    /// far. This is shared by every draw call on this canvas.
+    packets_claimed: Arc<AtomicU64>,
+    /// The number of workers the rows get spread over, or `None` to use a
+    /// worker for every row.
+    threads: Option<usize>,
+    backend: Backend,
+    /// The address or interface the raw backend sends from, if set.
+    binding: Option<Binding>,
+    /// How many times the raw backend tries to send a ping again after it
+    /// failed to send it.
+    retries: usize,
+    /// The number of bytes of padding the raw backend appends to every echo
+    /// request.
+    payload_size: usize,
+    /// Whether animations should send every pixel of every frame instead of
+    /// only the pixels that changed since the previous frame.
+    full_frames: bool,
+    /// Fade between frames and images instead of switching to them right
+    /// away.
+    transition: Option<Transition>,
+    /// The number of times the image is repeated horizontally and vertically.
+    tiles: (u32, u32),
+    /// The thickness and color of the outline drawn around the image, if any.
+    border: Option<(u32, Rgba<u8>)>,
+}
+
+impl Default for Canvas {
+    fn default() -> Self {
+        Canvas {
+            prefixes: vec![Prefix::default()],
+            screen_size: DEFAULT_SCREEN_SIZE,
+            stop: Arc::default(),
+            pause: Arc::default(),
+            stats: Stats::default(),
+            clip: false,
+            skip_transparent: true,
+            rate: None,
+            ramp: None,
+            adaptive_rate: false,
+            jitter: None,
+            limiter: None,
+            keepalive: None,
+            order: Order::default(),
+            pixel_layout: PixelLayout::default(),
+            passes: None,
+            max_packets: None,
+            packets_claimed: Arc::default(),
+            threads: None,
+            backend: Backend::default(),
+            binding: None,
+            retries: 0,
+            payload_size: 0,
+            full_frames: false,
+            transition: None,
+            tiles: (1, 1),
+            border: None,
+        }
+    }
+}
+
+impl Canvas {
+    pub fn new() -> Self {
+        Canvas::default()
+    }
+
+    /// Draw to the screen listening on `prefix` instead of the default one.
+    pub fn with_prefix(mut self, prefix: Prefix) -> Self {
+        self.prefixes = vec![prefix];
+        self
+    }
+
+    /// Spread the pixels over multiple prefixes that all lead to the same
+    /// screen, cycling through them pixel by pixel. This can be used to spread
+    /// the load when a screen listens on multiple prefixes. Passing an empty
+    /// list leaves the prefix unchanged.
+    pub fn with_prefixes(mut self, prefixes: Vec<Prefix>) -> Self {
+        if !prefixes.is_empty() {
+            self.prefixes = prefixes;
+        }
+        self
+    }
+
+    /// Draw to a screen of `width` by `height` pixels instead of the default
+    /// 1920x1080.
+    pub fn with_screen_size(mut self, width: u32, height: u32) -> Self {
+        self.screen_size = (width, height);
+        self
+    }
+
+    /// Silently drop pixels that fall outside of the screen instead of
+    /// returning an error when the image doesn't fit.
+    pub fn with_clipping(mut self, clip: bool) -> Self {
+        self.clip = clip;
+        self
+    }
+
+    /// Also send completely transparent pixels instead of skipping them, so
+    /// they overwrite anything else that was drawn in the image's area. This
+    /// doesn't apply to the pixels that stayed the same between two frames
+    /// of an animation, since those are still skipped.
+    pub fn with_skip_transparent(mut self, skip: bool) -> Self {
+        self.skip_transparent = skip;
+        self
+    }
+
+    /// Limit the combined send rate of all threads to `rate` packets per
+    /// second. There is no limit by default.
+    pub fn with_rate_limit(mut self, rate: f64) -> Self {
+        self.rate = Some(rate);
+        self.rebuild_limiter();
+        self
+    }
+
+    /// Start out at a fraction of the rate limit when drawing starts and
+    /// linearly increase the rate to the full limit over `ramp`. This avoids
+    /// flooding the queues when all workers start at the same time. This does
+    /// nothing without a rate limit.
+    pub fn with_ramp(mut self, ramp: Duration) -> Self {
+        self.ramp = Some(ramp);
+        self.rebuild_limiter();
+        self
+    }
+
+    /// Treat the rate limit as a maximum, and automatically lower the rate
+    /// when too many pings fail to send or go unanswered. The rate then slowly
+    /// increases again while there are no errors. The current rate can be read
+    /// from [`Stats::rate_limit()`]. This does nothing without a rate limit.
+    ///
+    /// Screens that don't reply to pings at all will cause every ping sent
+    /// through fastping_rs to count as unanswered, so this should only be used
+    /// with the raw backend for those screens.
+    pub fn with_adaptive_rate(mut self, adaptive: bool) -> Self {
+        self.adaptive_rate = adaptive;
+        self.rebuild_limiter();
+        self
+    }
+
+    /// Draw every image once at the normal rate, and then keep it on the
+    /// screen by sending it again at only `rate` packets per second. This is
+    /// enough to repair the occasional overwrite without flooding the screen.
+    /// Every new image or frame is drawn at the normal rate again, so this is
+    /// mostly useful for still images.
+    pub fn with_keepalive_rate(mut self, rate: f64) -> Self {
+        self.keepalive = Some(Arc::new(RateLimiter::new(
+            rate,
+            None,
+            false,
+            self.stats.clone(),
+        )));
+        // Creating the limiter publishes its rate, but the normal rate applies
+        // until the first pass has been sent
+        self.rebuild_limiter();
+        self
+    }
+
+    /// Wait for a random amount of time up to `max_delay` before every pass,
+    /// so the workers don't all send their packets at the same moment. This
+    /// smooths out the traffic the screen receives. With a seed every worker
+    /// always waits the same sequence of delays.
+    pub fn with_jitter(mut self, max_delay: Duration, seed: Option<u64>) -> Self {
+        self.jitter = Some((max_delay, seed));
+        self
+    }
+
+    /// Send pixels in `order` instead of row by row.
+    pub fn with_order(mut self, order: Order) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Pack colors into the addresses using `layout`, for screens that expect
+    /// the channels in a different order.
+    pub fn with_pixel_layout(mut self, layout: PixelLayout) -> Self {
+        self.pixel_layout = layout;
+        self
+    }
+
+    /// Stop drawing once every worker has pinged its row `passes` times instead
+    /// of drawing until the stop flag gets set.
+    pub fn with_passes(mut self, passes: usize) -> Self {
+        self.passes = Some(passes);
+        self
+    }
+
+    /// Set the stop flag once `max_packets` packets have been sent in total,
+    /// counting every worker and every draw call. A pass that would go over
+    /// the limit is not sent at all, so this is a hard cap that's never
+    /// exceeded.
+    pub fn with_max_packets(mut self, max_packets: u64) -> Self {
+        self.max_packets = Some(max_packets);
+        self
+    }
+
+    /// Split the addresses over `threads` workers instead of starting a worker
+    /// for every row, so tall images don't need hundreds of threads. Every
+    /// worker gets the same number of addresses, regardless of how long the
+    /// rows are. Every one of these workers is still repeated `repetitions`
+    /// times.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = Some(threads.max(1));
+        self
+    }
+
+    /// Send the pings using `backend` instead of fastping_rs.
+    pub fn with_backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Send the pings from a specific address or network interface instead of
+    /// letting the operating system pick one. This only works with
+    /// [`Backend::Raw`], drawing with the fastping_rs backend will return an
+    /// error.
+    pub fn with_binding(mut self, binding: Binding) -> Self {
+        self.binding = Some(binding);
+        self
+    }
+
+    /// Send pings that could not be sent again, up to `retries` times per pass,
+    /// instead of leaving those pixels for the next pass. fastping_rs does not
+    /// report which sends failed, so this only has an effect with
+    /// [`Backend::Raw`].
+    pub fn with_retries(mut self, retries: usize) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Pad every echo request with `size` bytes, capped at
+    /// [`MAX_PAYLOAD_SIZE`]. Some screens treat
+    /// larger packets differently, and this also makes it possible to measure
+    /// throughput at different packet sizes. fastping_rs always sends the same
+    /// packets, so this only has an effect with [`Backend::Raw`].
+    pub fn with_payload_size(mut self, size: usize) -> Self {
+        self.payload_size = size.min(MAX_PAYLOAD_SIZE);
+        self
+    }
+
+    /// Repeat the image `columns` times horizontally and `rows` times
+    /// vertically, starting at the origin. The tiles are treated as a single
+    /// large image, so the whole grid has to fit on the screen.
+    pub fn with_tiling(mut self, columns: u32, rows: u32) -> Self {
+        self.tiles = (columns.max(1), rows.max(1));
+        self
+    }
+
+    /// Draw a solid outline `thickness` pixels thick around the image. The
+    /// outline is drawn outside of the image, so the image itself stays at the
+    /// same position. This is drawn around the whole grid when tiling.
+    pub fn with_border(mut self, thickness: u32, color: Rgba<u8>) -> Self {
+        self.border = if thickness > 0 {
+            Some((thickness, color))
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Send every pixel of every frame when playing an animation. By default
+    /// only the first frame is sent in full, and after that only the pixels
+    /// that changed since the previous frame get sent. That saves a lot of
+    /// bandwidth, but it relies on nobody else drawing over the animation.
+    pub fn with_full_frames(mut self, full_frames: bool) -> Self {
+        self.full_frames = full_frames;
+        self
+    }
+
+    /// Fade from one frame of an animation to the next, and from one image to
+    /// the next in [`draw_updates()`](Canvas::draw_updates). The steps of a
+    /// fade are shown in addition to the animation's own frames, so every frame
+    /// of an animation takes `transition.duration` longer to play.
+    pub fn with_transition(mut self, transition: Transition) -> Self {
+        self.transition = Some(transition);
+        self
+    }
+
+    /// A flag that stops all drawing when set to `true`. The `draw_*()`
+    /// functions will return shortly after. This can be used to stop drawing
+    /// from another thread or from a signal handler.
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    /// A flag that pauses drawing while it's set to `true`. The workers stay
+    /// around and they resume sending their pixels as soon as the flag gets
+    /// cleared again. Animations and live images keep advancing while paused,
+    /// and passes and the stop flag work as usual.
+    pub fn pause_flag(&self) -> Arc<AtomicBool> {
+        self.pause.clone()
+    }
+
+    /// Counters for the packets sent by this canvas.
+    pub fn stats(&self) -> Stats {
+        self.stats.clone()
+    }
+
+    /// Ping `image` with its top left corner at `origin`. Every row gets
+    /// `repetitions` threads pinging it in a loop, unless the number of threads
+    /// has been limited with [`with_threads()`](Canvas::with_threads). This
+    /// function blocks until the stop flag gets set, or until every worker has
+    /// finished its passes if [`with_passes()`](Canvas::with_passes) has been
+    /// used.
+    pub fn draw_image(
+        &self,
+        image: &RgbaImage,
+        origin: (u16, u16),
+        repetitions: usize,
+    ) -> Result<(), Error> {
+        self.draw_addresses(self.addresses(image, origin)?, repetitions)
+    }
+
+    /// Ping copies of `image` with their top left corners at every position in
+    /// `origins`, like [`draw_image()`](Canvas::draw_image) would draw a single
+    /// copy. Every copy gets its own rows, so the copies fill in at the same
+    /// time.
+    pub fn draw_copies(
+        &self,
+        image: &RgbaImage,
+        origins: &[(u16, u16)],
+        repetitions: usize,
+    ) -> Result<(), Error> {
+        self.draw_addresses(self.copy_addresses(image, origins)?, repetitions)
+    }
+
+    /// The addresses [`draw_copies()`](Canvas::draw_copies) would ping, in the
+    /// same format as [`addresses()`](Canvas::addresses).
+    pub fn copy_addresses(
+        &self,
+        image: &RgbaImage,
+        origins: &[(u16, u16)],
+    ) -> Result<Vec<Vec<IpAddr>>, Error> {
+        let mut groups = Vec::new();
+        for &origin in origins {
+            groups.extend(self.addresses(image, origin)?);
+        }
+
+        Ok(groups)
+    }
+
+    /// Ping addresses that have already been computed, for instance with
+    /// [`addresses()`](Canvas::addresses). Every group of addresses is treated
+    /// like a row in [`draw_image()`](Canvas::draw_image).
+    pub fn draw_addresses(
+        &self,
+        groups: Vec<Vec<IpAddr>>,
+        repetitions: usize,
+    ) -> Result<(), Error> {
+        let rows = Arc::new(groups);
+        let workers = Workers::spawn(self, &rows, rows.len(), repetitions)?;
+        while !self.stop.load(Ordering::Relaxed) && !workers.finished() {
+            thread::sleep(POLL_INTERVAL);
+        }
+        workers.stop();
+
+        Ok(())
+    }
+
+    /// Play an animation with its top left corner at `origin`. Every frame is
+    /// pinged for the duration of its delay, using `repetitions` threads per
+    /// row. The animation is played `loop_count` times, or forever if
+    /// `loop_count` is `None`. Playback also ends when the stop flag gets set or
+    /// when every worker has finished its passes. Only the pixels that changed
+    /// between frames are sent, unless
+    /// [`with_full_frames()`](Canvas::with_full_frames) has been used.
+    pub fn draw_animation(
+        &self,
+        frames: &[Frame],
+        origin: (u16, u16),
+        repetitions: usize,
+        loop_count: Option<usize>,
+    ) -> Result<(), Error> {
+        if frames.is_empty() {
+            return Ok(());
+        }
+
+        // Unless we're sending full frames, the first frame is only sent in
+        // full once. After that every frame, including the first frame when
+        // looping, only contains the difference with the frame before it.
+        let mut first_frame = if self.full_frames {
+            None
+        } else {
+            Some(Arc::new(self.addresses(&frames[0].image, origin)?))
+        };
+        // With a transition every frame is followed by a fade into the next
+        // frame, so the next frame gets drawn over the fade's last step
+        // instead of over the frame before it
+        let fades: Vec<Vec<Frame>> = match self.transition {
+            Some(transition) => frames
+                .iter()
+                .enumerate()
+                .map(|(idx, frame)| {
+                    transition.frames(&frame.image, &frames[(idx + 1) % frames.len()].image)
+                })
+                .collect(),
+            None => vec![Vec::new(); frames.len()],
+        };
+        let frames = frames
+            .iter()
+            .zip(&fades)
+            .enumerate()
+            .map(|(idx, (frame, fade))| {
+                let previous_idx = (idx + frames.len() - 1) % frames.len();
+                let previous = fades[previous_idx].last().unwrap_or(&frames[previous_idx]);
+                let rows = self.frame_rows(&previous.image, &frame.image, origin)?;
+
+                let mut previous = &frame.image;
+                let fade = fade
+                    .iter()
+                    .map(|step| {
+                        let rows = self.frame_rows(previous, &step.image, origin)?;
+                        previous = &step.image;
+
+                        Ok((rows, step.delay))
+                    })
+                    .collect::<Result<Vec<_>, Error>>()?;
+
+                Ok((rows, frame.delay, fade))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let num_rows = frames
+            .iter()
+            .flat_map(|(rows, _, fade)| iter::once(rows).chain(fade.iter().map(|(rows, _)| rows)))
+            .chain(first_frame.iter())
+            .map(|rows| rows.len())
+            .max()
+            .unwrap();
+
+        let workers = Workers::spawn(
+            self,
+            first_frame.as_ref().unwrap_or(&frames[0].0),
+            num_rows,
+            repetitions,
+        )?;
+        let mut remaining_loops = loop_count;
+        // Frames are scheduled relative to when playback started, so the time
+        // spent switching between frames doesn't accumulate over time
+        let mut deadline = Instant::now();
+        'playback: while remaining_loops != Some(0) {
+            for (idx, (rows, delay, fade)) in frames.iter().enumerate() {
+                workers.show(first_frame.as_ref().unwrap_or(rows));
+                first_frame = None;
+                deadline += *delay;
+                if !self.sleep_until(deadline) || workers.finished() {
+                    break 'playback;
+                }
+
+                // There's nothing to fade into after the very last frame
+                if idx == frames.len() - 1 && remaining_loops == Some(1) {
+                    break;
+                }
+                for (rows, delay) in fade {
+                    workers.show(rows);
+                    deadline += *delay;
+                    if !self.sleep_until(deadline) || workers.finished() {
+                        break 'playback;
+                    }
+                }
+            }
+
+            if let Some(remaining) = remaining_loops.as_mut() {
+                *remaining -= 1;
+            }
+        }
+        workers.stop();
+
+        Ok(())
+    }
+
+    /// Scroll `image` through a region `width` pixels wide with its top left
+    /// corner at `origin`, moving `speed` pixels per second in `direction`.
+    /// Once the image has fully left the region it enters again from the other
+    /// side. This blocks until the stop flag gets set, or until every worker
+    /// has finished its passes if [`with_passes()`](Canvas::with_passes) has
+    /// been used.
+    pub fn draw_marquee(
+        &self,
+        image: &RgbaImage,
+        origin: (u16, u16),
+        width: u32,
+        direction: ScrollDirection,
+        speed: f64,
+        repetitions: usize,
+    ) -> Result<(), Error> {
+        let cycle = width + image.width();
+        let rows_at = |step: u64| {
+            let position =
+                direction.position((step % u64::from(cycle)) as u32, width, image.width());
+            self.addresses(&marquee::window(image, width, position), origin)
+                .map(Arc::new)
+        };
+
+        // The image starts out outside of the region, so the number of workers
+        // is based on the rows of a completely filled in region instead. This
+        // includes the border and any tiles.
+        let filled = RgbaImage::from_pixel(width, image.height(), Rgba([0, 0, 0, 255]));
+        let num_rows = self.addresses(&filled, origin)?.len();
+        let workers = Workers::spawn(self, &rows_at(0)?, num_rows, repetitions)?;
+        // Like with animations the steps are scheduled relative to the start,
+        // and when we fall behind we'll skip ahead instead of slowing down
+        let start = Instant::now();
+        let mut step = 0;
+        loop {
+            let deadline = start + Duration::from_secs_f64((step + 1) as f64 / speed);
+            if !self.sleep_until(deadline) || workers.finished() {
+                break;
+            }
+
+            step = (start.elapsed().as_secs_f64() * speed) as u64;
+            workers.show(&rows_at(step)?);
+        }
+        workers.stop();
+
+        Ok(())
+    }
+
+    /// Keep drawing whatever `render` returns with its top left corner at
+    /// `origin`, calling it again every `interval`. This is meant for images
+    /// that change over time, like a clock. Like with animations only the
+    /// pixels that changed since the previous image are sent, unless
+    /// [`with_full_frames()`](Canvas::with_full_frames) has been used. The
+    /// number of workers is based on the first image, so later images should
+    /// not be any taller. This blocks until the stop flag gets set, or until
+    /// every worker has finished its passes.
+    pub fn draw_live(
+        &self,
+        origin: (u16, u16),
+        interval: Duration,
+        repetitions: usize,
+        mut render: impl FnMut() -> RgbaImage,
+    ) -> Result<(), Error> {
+        let mut previous = render();
+        let rows = Arc::new(self.addresses(&previous, origin)?);
+        let workers = Workers::spawn(self, &rows, rows.len(), repetitions)?;
+        let mut deadline = Instant::now();
+        loop {
+            deadline += interval;
+            if !self.sleep_until(deadline) || workers.finished() {
+                break;
+            }
+
+            let image = render();
+            workers.show(&self.frame_rows(&previous, &image, origin)?);
+            previous = image;
+        }
+        workers.stop();
+
+        Ok(())
+    }
+
+    /// Draw `image` like [`draw_image()`](Canvas::draw_image), but replace it
+    /// whenever `update` returns a new image and the position to draw it at.
+    /// `update` gets polled a couple of times per second, and it should return
+    /// `None` when there's nothing new to draw. New images are always sent in
+    /// full, after fading into them if a transition has been set and the image
+    /// is drawn at the same position as the one before it. Starting with an
+    /// empty image is fine. This blocks until the stop flag gets set, or until
+    /// every worker has finished its passes.
+    pub fn draw_updates(
+        &self,
+        image: &RgbaImage,
+        origin: (u16, u16),
+        repetitions: usize,
+        mut update: impl FnMut() -> Option<(RgbaImage, (u16, u16))>,
+    ) -> Result<(), Error> {
+        let rows = Arc::new(self.addresses(image, origin)?);
+        let mut num_rows = rows.len();
+        let mut workers = Workers::spawn(self, &rows, num_rows, repetitions)?;
+        let mut current = (image.clone(), origin);
+        // Without any workers there's nothing to finish, so we'll keep waiting
+        // for something to draw
+        'drawing: while !self.stop.load(Ordering::Relaxed) && (num_rows == 0 || !workers.finished())
+        {
+            thread::sleep(POLL_INTERVAL);
+
+            if let Some((image, origin)) = update() {
+                // Fading only makes sense when the new image replaces the old
+                // one in place
+                let fade = match self.transition {
+                    Some(transition) if origin == current.1 => {
+                        transition.frames(&current.0, &image)
+                    }
+                    _ => Vec::new(),
+                };
+                let mut previous = &current.0;
+                let mut steps = Vec::with_capacity(fade.len());
+                for step in &fade {
+                    steps.push((self.frame_rows(previous, &step.image, origin)?, step.delay));
+                    previous = &step.image;
+                }
+                let rows = Arc::new(self.addresses(&image, origin)?);
+
+                // Every worker is tied to a fixed set of rows, so a taller
+                // image needs a new set of workers
+                let needed_rows = steps
+                    .iter()
+                    .map(|(rows, _)| rows.len())
+                    .chain(iter::once(rows.len()))
+                    .max()
+                    .unwrap();
+                if needed_rows > num_rows {
+                    workers.stop();
+                    num_rows = needed_rows;
+                    workers = Workers::spawn(self, &Arc::default(), num_rows, repetitions)?;
+                }
+
+                let mut deadline = Instant::now();
+                for (rows, delay) in &steps {
+                    workers.show(rows);
+                    deadline += *delay;
+                    if !self.sleep_until(deadline) {
+                        break 'drawing;
+                    }
+                }
+                workers.show(&rows);
+                current = (image, origin);
+            }
+        }
+        workers.stop();
+
+        Ok(())
+    }
+
+    /// Draw `image` with its top left corner at `origin`, and switch to the
+    /// next image as soon as `next` returns one. `next` gets polled a couple of
+    /// times per second and it should return `None` when there's nothing new
+    /// to draw, in which case the current image stays on screen. This is meant
+    /// for frames that come in from an external source at their own pace. Like
+    /// with animations only the pixels that changed since the previous image
+    /// are sent, unless [`with_full_frames()`](Canvas::with_full_frames) has
+    /// been used. This blocks until the stop flag gets set, or until every
+    /// worker has finished its passes.
+    pub fn draw_stream(
+        &self,
+        image: &RgbaImage,
+        origin: (u16, u16),
+        repetitions: usize,
+        mut next: impl FnMut() -> Option<RgbaImage>,
+    ) -> Result<(), Error> {
+        let rows = Arc::new(self.addresses(image, origin)?);
+        let mut num_rows = rows.len();
+        let mut workers = Workers::spawn(self, &rows, num_rows, repetitions)?;
+        let mut previous = image.clone();
+        while !self.stop.load(Ordering::Relaxed) && (num_rows == 0 || !workers.finished()) {
+            thread::sleep(POLL_INTERVAL);
+
+            let image = match next() {
+                Some(image) => image,
+                None => continue,
+            };
+
+            // Every worker is tied to a fixed set of rows, so a taller image
+            // needs a new set of workers. Those haven't drawn anything yet, so
+            // they get the entire image.
+            if image.height() > previous.height() {
+                let rows = self.addresses(&image, origin)?;
+                if rows.len() > num_rows {
+                    workers.stop();
+                    num_rows = rows.len();
+                    workers = Workers::spawn(self, &Arc::new(rows), num_rows, repetitions)?;
+                    previous = image;
+                    continue;
+                }
+            }
+
+            workers.show(&self.frame_rows(&previous, &image, origin)?);
+            previous = image;
+        }
+        workers.stop();
+
+        Ok(())
+    }
+
+    /// Compute the addresses for every non-transparent pixel in an image,
+    /// grouped by the worker that will ping them. With the default order every
+    /// group is a single row. Completely transparent rows are omitted, unless
+    /// [`with_skip_transparent()`](Canvas::with_skip_transparent) has been
+    /// used to send those pixels as well. This
+    /// returns an error if the image does not fit on the screen, unless
+    /// clipping is enabled.
+    ///
+    /// These are the exact addresses the `draw_*()` functions would ping for
+    /// this image, so this can be used to inspect what would be sent without
+    /// sending anything.
+    pub fn addresses(
+        &self,
+        image: &RgbaImage,
+        origin: (u16, u16),
+    ) -> Result<Vec<Vec<IpAddr>>, Error> {
+        self.compute_addresses(image, origin, self.skip_transparent)
+    }
+
+    /// [`addresses()`](Self::addresses), but with the option to skip
+    /// transparent pixels regardless of the setting. Frame differences need
+    /// this since they mark unchanged pixels as transparent.
+    fn compute_addresses(
+        &self,
+        image: &RgbaImage,
+        origin: (u16, u16),
+        skip_transparent: bool,
+    ) -> Result<Vec<Vec<IpAddr>>, Error> {
+        let start = Instant::now();
+        let groups = self.arrange(image, origin, skip_transparent)?;
+        self.stats
+            .add_addresses_requested(groups.iter().map(Vec::len).sum());
+
+        // Formatting the addresses is the slow part, and every group can be
+        // done on its own. This keeps the groups in order.
+        let addresses: Vec<Vec<IpAddr>> = groups
+            .into_par_iter()
+            .enumerate()
+            .map(|(group_idx, group)| {
+                let addresses = group
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, &(x, y, pixel))| {
+                        build_address(
+                            self.prefix_for(group_idx, idx),
+                            self.pixel_layout,
+                            x as u16,
+                            y as u16,
+                            &pixel,
+                        )
+                    })
+                    .collect();
+                self.stats.add_addresses_computed(group.len());
+
+                addresses
+            })
+            .collect();
+        log::debug!(
+            "Computed {} addresses in {} rows in {:.2?}",
+            addresses.iter().map(Vec::len).sum::<usize>(),
+            addresses.len(),
+            start.elapsed()
+        );
+
+        Ok(addresses)
+    }
+
+    /// The same as [`addresses()`](Self::addresses), but every address comes
+    /// with the screen coordinates and the color of the pixel it draws.
+    pub fn pings(&self, image: &RgbaImage, origin: (u16, u16)) -> Result<Vec<Vec<Ping>>, Error> {
+        Ok(self
+            .arrange(image, origin, self.skip_transparent)?
+            .into_iter()
+            .enumerate()
+            .map(|(group_idx, group)| {
+                group
+                    .into_iter()
+                    .enumerate()
+                    .map(|(idx, (x, y, color))| Ping {
+                        x: x as u16,
+                        y: y as u16,
+                        color,
+                        address: build_address(
+                            self.prefix_for(group_idx, idx),
+                            self.pixel_layout,
+                            x as u16,
+                            y as u16,
+                            &color,
+                        ),
+                    })
+                    .collect()
+            })
+            .collect())
+    }
+
+    /// The part of the screen that drawing `image` at `origin` would actually
+    /// cover, including the border and any tiles. This is `None` if none of
+    /// the image's pixels would be drawn, and it returns the same error as
+    /// [`addresses()`](Self::addresses) if the image doesn't fit.
+    pub fn extent(&self, image: &RgbaImage, origin: (u16, u16)) -> Result<Option<Extent>, Error> {
+        let groups = self.arrange(image, origin, self.skip_transparent)?;
+        let mut pixels = groups.iter().flatten();
+        let first = match pixels.next() {
+            Some(&(x, y, _)) => (x, y),
+            None => return Ok(None),
+        };
+
+        let (mut top_left, mut bottom_right, mut count) = (first, first, 1);
+        for &(x, y, _) in pixels {
+            top_left = (top_left.0.min(x), top_left.1.min(y));
+            bottom_right = (bottom_right.0.max(x), bottom_right.1.max(y));
+            count += 1;
+        }
+
+        Ok(Some(Extent {
+            top_left: (top_left.0 as u16, top_left.1 as u16),
+            bottom_right: (bottom_right.0 as u16, bottom_right.1 as u16),
+            pixels: count,
+        }))
+    }
+
+    /// Collect the pixels that should be drawn, grouped by worker, with their
+    /// coordinates translated to screen coordinates.
+    fn arrange(
+        &self,
+        image: &RgbaImage,
+        origin: (u16, u16),
+        skip_transparent: bool,
+    ) -> Result<Vec<Vec<Pixel>>, Error> {
+        let (origin_x, origin_y) = (u32::from(origin.0), u32::from(origin.1));
+        let (screen_width, screen_height) = self.screen_size;
+        let (width, height) = self.tiled_size(image.dimensions());
+        self.check_bounds((width, height), origin)?;
+
+        // The border is drawn as part of a slightly larger image that starts
+        // `thickness` pixels above and to the left of the origin
+        let (thickness, border_color) = self.border.unwrap_or((0, Rgba([0, 0, 0, 0])));
+        let pixel_at = |x: u32, y: u32| {
+            if x < thickness || y < thickness || x >= width + thickness || y >= height + thickness {
+                border_color
+            } else {
+                // All tiles read from the same image, so tiling only costs
+                // memory for the pixels that actually get sent
+                let (x, y) = (x - thickness, y - thickness);
+                *image.get_pixel(x % image.width(), y % image.height())
+            }
+        };
+        let (width, height) = (width + thickness * 2, height + thickness * 2);
+        let rows: Vec<Vec<Pixel>> = (0..height)
+            .map(|y| {
+                (0..width)
+                    .map(|x| (x, y, pixel_at(x, y)))
+                    // Skip any completely transparent pixels
+                    .filter(|(_, _, Rgba([_, _, _, alpha]))| !skip_transparent || *alpha > 0)
+                    // This only does something when clipping is enabled
+                    .filter(|&(x, y, _)| {
+                        (origin_x + x)
+                            .checked_sub(thickness)
+                            .is_some_and(|x| x < screen_width)
+                            && (origin_y + y)
+                                .checked_sub(thickness)
+                                .is_some_and(|y| y < screen_height)
+                    })
+                    .collect()
+            })
+            // Skip any completely transparent rows
+            .filter(|pixels: &Vec<_>| !pixels.is_empty())
+            .collect();
+
+        Ok(self
+            .order
+            .arrange(rows, (width, height))
+            .into_iter()
+            .map(|group| {
+                group
+                    .into_iter()
+                    .map(|(x, y, pixel)| {
+                        (origin_x + x - thickness, origin_y + y - thickness, pixel)
+                    })
+                    .collect()
+            })
+            .collect())
+    }
+
+    /// Check whether an image of `image_size` pixels drawn at `origin` fits on
+    /// the screen, returning the same error the `draw_*()` functions would
+    /// return if it doesn't. This always succeeds when clipping is enabled.
+    /// The border is included in the check, but tiling is not taken into
+    /// account. Use [`tiled_size()`](Canvas::tiled_size) for that.
+    pub fn check_bounds(&self, image_size: (u32, u32), origin: (u16, u16)) -> Result<(), Error> {
+        if self.clip {
+            return Ok(());
+        }
+
+        let (screen_width, screen_height) = self.screen_size;
+        let thickness = self.border.map_or(0, |(thickness, _)| thickness);
+        let left = thickness.saturating_sub(u32::from(origin.0));
+        let top = thickness.saturating_sub(u32::from(origin.1));
+        let right = (u32::from(origin.0) + image_size.0 + thickness).saturating_sub(screen_width);
+        let bottom = (u32::from(origin.1) + image_size.1 + thickness).saturating_sub(screen_height);
+        if left > 0 || top > 0 || right > 0 || bottom > 0 {
+            Err(Error::OutOfBounds {
+                screen_size: self.screen_size,
+                left,
+                top,
+                right,
+                bottom,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The prefix for the `idx`th pixel in the `group_idx`th group. Offsetting
+    /// by the group's index also spreads groups containing a single pixel over
+    /// all prefixes.
+    fn prefix_for(&self, group_idx: usize, idx: usize) -> Prefix {
+        self.prefixes[(group_idx + idx) % self.prefixes.len()]
+    }
+
+    /// The size of an image of `image_size` pixels after it has been tiled.
+    pub fn tiled_size(&self, (width, height): (u32, u32)) -> (u32, u32) {
+        (width * self.tiles.0, height * self.tiles.1)
+    }
+
+    /// The addresses for drawing `image` after `previous` has been drawn. This
+    /// only contains the pixels that changed, unless
+    /// [`with_full_frames()`](Canvas::with_full_frames) has been used.
+    fn frame_rows(
+        &self,
+        previous: &RgbaImage,
+        image: &RgbaImage,
+        origin: (u16, u16),
+    ) -> Result<Arc<Vec<Vec<IpAddr>>>, Error> {
+        let rows = if self.full_frames {
+            self.addresses(image, origin)?
+        } else {
+            self.compute_addresses(&frame_difference(previous, image), origin, true)?
+        };
+
+        Ok(Arc::new(rows))
+    }
+
+    fn rebuild_limiter(&mut self) {
+        self.limiter = self.rate.map(|rate| {
+            Arc::new(RateLimiter::new(
+                rate,
+                self.ramp,
+                self.adaptive_rate,
+                self.stats.clone(),
+            ))
+        });
+        if self.limiter.is_none() {
+            // A rate of zero means there's no limit
+            self.stats.set_rate_limit(0.0);
+        }
+    }
+
+    /// Sleep until `deadline`, or until the stop flag gets set. Returns `false`
+    /// if drawing should be stopped.
+    fn sleep_until(&self, deadline: Instant) -> bool {
+        sleep_unless_stopped(deadline, &self.stop)
+    }
+}
+
+/// The threads doing the actual pinging. We ping per row to avoid hammering the
+/// server, so every worker is responsible for a single row of whatever is
+/// currently being shown. With a different [`Order`] these rows are groups of
+/// pixels from all over the image instead. The rows can be swapped out while
+/// the workers are running to play animations. Creating a `Pinger` opens new
+/// sockets that never get closed, so we can't create new workers for every
+/// frame.
+struct Workers {
+    /// The rows that should currently be drawn.
+    current: Arc<Mutex<Arc<Vec<Vec<IpAddr>>>>>,
+    /// Incremented every time `current` changes, so the workers know when they
+    /// have to update their addresses.
+    generation: Arc<AtomicUsize>,
+    stop: Arc<AtomicBool>,
+    /// The number of workers that have finished their passes.
+    finished: Arc<AtomicUsize>,
+    /// The number of workers for every set of addresses.
+    repetitions: usize,
+    stats: Stats,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl Workers {
+    /// Start `repetitions` workers for each of the `num_rows` rows, and start
+    /// drawing `rows`. If `canvas` limits the number of threads, the rows are
+    /// instead dealt out over that many workers like a deck of cards, so every
+    /// worker ends up with roughly the same number of rows.
+    /// The workers share `canvas`'s statistics and rate limiter.
+    fn spawn(
+        canvas: &Canvas,
+        rows: &Arc<Vec<Vec<IpAddr>>>,
+        num_rows: usize,
+        repetitions: usize,
+    ) -> Result<Self, Error> {
+        for (row, addresses) in rows.iter().enumerate() {
+            log::debug!("Row {} contains {} addresses", row, addresses.len());
+        }
+
+        let current = Arc::new(Mutex::new(rows.clone()));
+        let generation = Arc::new(AtomicUsize::new(0));
+        let stop = Arc::new(AtomicBool::new(false));
+        let finished = Arc::new(AtomicUsize::new(0));
+
+        // We can have multiple threads pinging the same row, this is useful for
+        // pinging small images faster. Those threads share a single pinger so
+        // the addresses only have to be added once. All pingers are created up
+        // front so we can bail out before anything gets sent.
+        let binding = match (&canvas.binding, canvas.backend) {
+            (None, _) => None,
+            (Some(binding), Backend::Raw) => Some(binding.resolve()?),
+            (Some(_), Backend::Fastping) => {
+                return Err(Error::Bind(String::from(
+                    "Binding to an address or interface requires the raw backend",
+                )))
+            }
+        };
+        let num_workers = canvas
+            .threads
+            .map_or(num_rows, |threads| threads.min(num_rows));
+        let mut pingers = Vec::with_capacity(num_workers);
+        for worker in 0..num_workers {
+            let sender = match canvas.backend {
+                Backend::Fastping => {
+                    let (pinger, results) = Pinger::new(Some(1), Some(0)).map_err(Error::Pinger)?;
+                    Sender::Fastping {
+                        pinger,
+                        results: Mutex::new(results),
+                    }
+                }
+                Backend::Raw => Sender::Raw(RawSocket::new(binding, canvas.payload_size)?),
+            };
+            // Without a thread limit every worker simply gets its own row
+            let share = match canvas.threads {
+                Some(_) => Share::Part {
+                    index: worker,
+                    num_parts: num_workers,
+                },
+                None => Share::Row(worker),
+            };
+            pingers.push(Arc::new(SharedPinger {
+                share,
+                sender,
+                // This forces the addresses to be added on the first iteration
+                shown: Mutex::new((usize::MAX, Vec::new())),
+            }));
+        }
+
+        let handles = pingers
+            .iter()
+            .flat_map(|pinger| iter::repeat_n(pinger, repetitions))
+            .enumerate()
+            .map(|(worker, pinger)| {
+                let pinger = pinger.clone();
+                let current = current.clone();
+                let generation = generation.clone();
+                let stop = stop.clone();
+                let finished = finished.clone();
+                let stats = canvas.stats.clone();
+                let limiter = canvas.limiter.clone();
+                let keepalive = canvas.keepalive.clone();
+                let passes = canvas.passes;
+                let retries = canvas.retries;
+                let max_packets = canvas.max_packets;
+                let packets_claimed = canvas.packets_claimed.clone();
+                let canvas_stop = canvas.stop.clone();
+                let pause = canvas.pause.clone();
+                // Every worker gets its own generator, so seeded delays don't
+                // depend on how the threads get scheduled
+                let mut jitter = canvas.jitter.map(|(max_delay, seed)| {
+                    let rng = match seed {
+                        Some(seed) => StdRng::seed_from_u64(seed.wrapping_add(worker as u64)),
+                        None => StdRng::from_entropy(),
+                    };
+                    (max_delay, rng)
+                });
+
+                thread::spawn(move || {
+                    let mut passes_done = 0;
+                    // The generation this worker has sent a full pass of
+                    let mut drawn_generation = None;
+                    while !stop.load(Ordering::Relaxed) {
+                        if Some(passes_done) == passes {
+                            finished.fetch_add(1, Ordering::Relaxed);
+                            break;
+                        }
+                        if pause.load(Ordering::Relaxed) {
+                            thread::sleep(POLL_INTERVAL);
+                            continue;
+                        }
+
+                        let latest_generation = generation.load(Ordering::Acquire);
+                        let num_addresses = pinger.update(&current, latest_generation);
+                        if num_addresses == 0 {
+                            thread::sleep(IDLE_INTERVAL);
+                        } else {
+                            if let Some(max_packets) = max_packets {
+                                let claimed = packets_claimed
+                                    .fetch_add(num_addresses as u64, Ordering::Relaxed);
+                                if claimed + num_addresses as u64 > max_packets {
+                                    if !canvas_stop.swap(true, Ordering::Relaxed) {
+                                        log::info!("Reached the limit of {} packets", max_packets);
+                                    }
+                                    break;
+                                }
+                            }
+                            if let Some((max_delay, rng)) = &mut jitter {
+                                thread::sleep(max_delay.mul_f64(rng.gen::<f64>()));
+                            }
+                            match &keepalive {
+                                // Waiting at the keepalive rate can take long
+                                // enough that it should be possible to stop
+                                Some(keepalive) if drawn_generation == Some(latest_generation) => {
+                                    let wait = keepalive.reserve(num_addresses);
+                                    if !sleep_unless_stopped(Instant::now() + wait, &stop) {
+                                        break;
+                                    }
+                                }
+                                _ => {
+                                    if let Some(limiter) = &limiter {
+                                        limiter.acquire(num_addresses);
+                                    }
+                                }
+                            }
+
+                            pinger.ping(&stats, retries);
+                            stats.add_packets_sent(num_addresses);
+                            passes_done += 1;
+                            drawn_generation = Some(latest_generation);
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        canvas.stats.add_frame_shown();
+        canvas
+            .stats
+            .set_packets_per_pass(rows.iter().map(Vec::len).sum::<usize>() * repetitions);
+        Ok(Workers {
+            current,
+            generation,
+            stop,
+            finished,
+            repetitions,
+            stats: canvas.stats.clone(),
+            handles,
+        })
+    }
+
+    /// Start drawing `rows` instead of whatever was being drawn before.
+    fn show(&self, rows: &Arc<Vec<Vec<IpAddr>>>) {
+        *self.current.lock().unwrap() = rows.clone();
+        self.generation.fetch_add(1, Ordering::Release);
+        self.stats.add_frame_shown();
+        self.stats
+            .set_packets_per_pass(rows.iter().map(Vec::len).sum::<usize>() * self.repetitions);
+    }
+
+    /// Whether every worker has finished its passes.
+    fn finished(&self) -> bool {
+        self.finished.load(Ordering::Relaxed) == self.handles.len()
+    }
+
+    /// Signal all workers to stop and wait for them to exit.
+    fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        for handle in self.handles {
+            handle.join().unwrap();
+        }
+    }
+}
+
+/// A pinger shared by all of the workers pinging the same addresses.
+struct SharedPinger {
+    /// Which of the addresses this pinger is responsible for.
+    share: Share,
+    sender: Sender,
+    /// The generation of the rows the pinger's addresses were taken from,
+    /// along with those addresses.
+    shown: Mutex<(usize, Vec<IpAddr>)>,
+}
+
+impl SharedPinger {
+    /// Replace the pinger's addresses with those from `current` if they're
+    /// from an older generation than `latest_generation`. Only the first worker
+    /// to notice the change has to do this. Returns the number of addresses
+    /// the pinger will now ping.
+    fn update(&self, current: &Mutex<Arc<Vec<Vec<IpAddr>>>>, latest_generation: usize) -> usize {
+        let mut shown = self.shown.lock().unwrap();
+        let (shown_generation, addresses) = &mut *shown;
+        if *shown_generation != latest_generation {
+            let rows = current.lock().unwrap().clone();
+            let new_addresses: Vec<IpAddr> = match self.share {
+                Share::Row(row) => rows.get(row).cloned().unwrap_or_default(),
+                Share::Part { index, num_parts } => {
+                    let num_addresses = rows.iter().map(Vec::len).sum();
+                    let part = partition(num_addresses, num_parts, index);
+                    rows.iter()
+                        .flatten()
+                        .skip(part.start)
+                        .take(part.len())
+                        .cloned()
+                        .collect()
+                }
+            };
+
+            match &self.sender {
+                Sender::Fastping { pinger, .. } => {
+                    // fastping_rs only accepts addresses as strings, so we'll
+                    // format them into a buffer on the stack instead of
+                    // allocating a new string for every address
+                    let mut buffer = AddressBuffer::new();
+                    for address in addresses.iter() {
+                        pinger.remove_ipaddr(buffer.format(address));
+                    }
+                    for address in &new_addresses {
+                        pinger.add_ipaddr(buffer.format(address));
+                    }
+                }
+                Sender::Raw(socket) => socket.set_targets(&new_addresses),
+            }
+
+            *addresses = new_addresses;
+            *shown_generation = latest_generation;
+        }
+
+        addresses.len()
+    }
+
+    /// Send a single ping to every address. With the raw backend pings that
+    /// could not be sent are sent again up to `retries` times.
+    fn ping(&self, stats: &Stats, retries: usize) {
+        match &self.sender {
+            Sender::Fastping { pinger, results } => {
+                pinger.ping_once();
+
+                // fastping_rs reports every address that did not reply in
+                // time. These results have to be drained either way since they
+                // would otherwise pile up.
+                let idle = results
+                    .lock()
+                    .unwrap()
+                    .try_iter()
+                    .filter(|result| matches!(result, PingResult::Idle { .. }))
+                    .count();
+                stats.add_unanswered(idle);
+            }
+            Sender::Raw(socket) => {
+                let (retried, failed) = socket.send(retries);
+                stats.add_retried_sends(retried);
+                stats.add_failed_sends(failed);
+            }
+        }
+    }
+}
+
+/// The addresses a [`SharedPinger`] pings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Share {
+    /// Every address in a single row.
+    Row(usize),
+    /// Part `index` out of `num_parts` equally sized parts of all addresses,
+    /// in order. Rows can differ a lot in length, so this keeps a short row
+    /// from getting a worker all to itself while another worker has to ping a
+    /// long row.
+    Part { index: usize, num_parts: usize },
+}
+
+/// The range of indices for part `index` when splitting `len` elements into
+/// `num_parts` contiguous parts. The parts differ in size by at most one
+/// element, with the larger parts coming first.
+fn partition(len: usize, num_parts: usize, index: usize) -> Range<usize> {
+    let (size, remainder) = (len / num_parts, len % num_parts);
+    let start = index * size + index.min(remainder);
+    let end = start + size + usize::from(index < remainder);
+
+    start..end
+}
+
+/// Sleep until `deadline`, or until `stop` gets set. Returns `false` if
+/// drawing should be stopped.
+fn sleep_unless_stopped(deadline: Instant, stop: &AtomicBool) -> bool {
+    loop {
+        if stop.load(Ordering::Relaxed) {
+            return false;
+        }
+
+        let now = Instant::now();
+        if now >= deadline {
+            return true;
+        }
+        thread::sleep((deadline - now).min(POLL_INTERVAL));
+    }
+}
+
+/// The backend-specific part of a [`SharedPinger`].
+enum Sender {
+    Fastping {
+        pinger: Pinger,
+        results: Mutex<Receiver<PingResult>>,
+    },
+    Raw(RawSocket),
+}
+
+/// Enough room for the longest possible IPv6 address.
+const ADDRESS_BUFFER_SIZE: usize = 45;
+
+/// A fixed size buffer for formatting addresses without allocating.
+struct AddressBuffer {
+    buffer: [u8; ADDRESS_BUFFER_SIZE],
+    len: usize,
+}
+
+impl AddressBuffer {
+    fn new() -> Self {
+        AddressBuffer {
+            buffer: [0; ADDRESS_BUFFER_SIZE],
+            len: 0,
+        }
+    }
+
+    /// Format `address`, overwriting whatever was formatted before.
+    fn format(&mut self, address: &IpAddr) -> &str {
+        self.len = 0;
+        write!(self, "{}", address).unwrap();
+
+        // Only complete `&str`s get written to the buffer
+        std::str::from_utf8(&self.buffer[..self.len]).unwrap()
+    }
+}
+
+impl fmt::Write for AddressBuffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        if end > ADDRESS_BUFFER_SIZE {
+            return Err(fmt::Error);
+        }
+
+        self.buffer[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scales_to_blocks() {
+        let image = RgbaImage::from_fn(2, 1, |x, _| Rgba([x as u8, 0, 0, 255]));
+        let scaled = scale_image(&image, 3);
+        assert_eq!(scaled.dimensions(), (6, 3));
+        for (x, _, pixel) in scaled.enumerate_pixels() {
+            assert_eq!(*pixel, Rgba([(x / 3) as u8, 0, 0, 255]));
+        }
+    }
+
+    #[test]
+    fn resizing_keeps_at_least_one_pixel() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(1000, 1, Rgba([255; 4])));
+        for &(height, mode) in &[
+            (None, ResizeMode::Fit),
+            (Some(10), ResizeMode::Fit),
+            (Some(10), ResizeMode::Fill),
+        ] {
+            let resized = resize_image(&image, 10, height, mode, FilterType::Nearest);
+            assert_eq!(resized.width(), 10);
+            assert!(resized.height() > 0, "{:?} gave a zero height", mode);
+        }
+    }
+
+    #[test]
+    fn extent_is_clipped() {
+        let canvas = Canvas::new().with_screen_size(10, 10).with_clipping(true);
+        let image = RgbaImage::from_fn(4, 4, |x, y| match (x, y) {
+            (0, _) => Rgba([0, 0, 0, 0]),
+            _ => Rgba([255, 255, 255, 255]),
+        });
+
+        // The transparent column and everything past the right edge is left out
+        let extent = canvas.extent(&image, (7, 2)).unwrap();
+        assert_eq!(
+            extent,
+            Some(Extent {
+                top_left: (8, 2),
+                bottom_right: (9, 5),
+                pixels: 8,
+            })
+        );
+        assert_eq!(canvas.extent(&image, (10, 0)).unwrap(), None);
+    }
+
+    #[test]
+    fn mirrors_into_quadrants() {
+        let image = RgbaImage::from_fn(2, 1, |x, _| Rgba([x as u8, 0, 0, 255]));
+        let mirrored = mirror_quad(&image);
+        assert_eq!(mirrored.dimensions(), (4, 2));
+        for y in 0..2 {
+            let row: Vec<u8> = (0..4).map(|x| mirrored.get_pixel(x, y)[0]).collect();
+            assert_eq!(row, vec![0, 1, 1, 0]);
+        }
+    }
+
+    #[test]
+    fn partitions_are_balanced() {
+        for len in 0..50 {
+            for num_parts in 1..12 {
+                let parts: Vec<Range<usize>> = (0..num_parts)
+                    .map(|index| partition(len, num_parts, index))
+                    .collect();
+
+                // The parts should cover every element exactly once, in order
+                assert_eq!(parts[0].start, 0);
+                assert_eq!(parts[num_parts - 1].end, len);
+                for pair in parts.windows(2) {
+                    assert_eq!(pair[0].end, pair[1].start);
+                }
+
+                let sizes = parts.iter().map(|part| part.len());
+                let (min, max) = (sizes.clone().min().unwrap(), sizes.max().unwrap());
+                assert!(
+                    max - min <= 1,
+                    "{} elements in {} parts: {:?}",
+                    len,
+                    num_parts,
+                    parts
+                );
+            }
+        }
+    }
+}
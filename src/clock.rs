@@ -0,0 +1,152 @@
+//! Rendering the current time for the `clock` subcommand, and the time left
+//! until some moment for the `countdown` subcommand.
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local, NaiveDateTime, NaiveTime, TimeZone, Timelike};
+use image::{Rgba, RgbaImage};
+use pingas::TextRenderer;
+use std::time::Duration;
+
+/// The format used when `--format` is not passed, e.g. 13:37:00.
+pub const DEFAULT_FORMAT: &str = "%H:%M:%S";
+
+/// What a [`Clock`] shows.
+pub enum Face {
+    /// The local time in a strftime-style format.
+    Time(String),
+    /// The time left until `target`. Once that has passed `done_text` is shown
+    /// instead, or zero if that's not set. With `flash` set that then blinks
+    /// on and off every second.
+    Countdown {
+        target: DateTime<Local>,
+        done_text: Option<String>,
+        flash: bool,
+    },
+}
+
+/// Renders the local time or a countdown as text.
+pub struct Clock {
+    renderer: TextRenderer,
+    face: Face,
+    size: f32,
+    color: Rgba<u8>,
+}
+
+impl Clock {
+    pub fn new(renderer: TextRenderer, face: Face, size: f32, color: Rgba<u8>) -> Self {
+        Clock {
+            renderer,
+            face,
+            size,
+            color,
+        }
+    }
+
+    /// Render the current time or the time left to a tightly cropped image.
+    pub fn render(&self) -> RgbaImage {
+        let now = Local::now();
+        let text = match &self.face {
+            Face::Time(format) => now.format(format).to_string(),
+            Face::Countdown { target, .. } if *target > now => {
+                // The last second should still show a one instead of a zero
+                let millis = (*target - now).num_milliseconds();
+                format_remaining(((millis + 999) / 1000) as u64)
+            }
+            Face::Countdown {
+                target,
+                done_text,
+                flash,
+            } => {
+                // The text blinks off during every other second after the
+                // countdown has ended. This keeps the image's size the same
+                // so the text doesn't jump around.
+                let text = done_text.clone().unwrap_or_else(|| format_remaining(0));
+                let image = self.renderer.render(&text, self.size, self.color);
+                if *flash && (now - *target).num_seconds() % 2 == 1 {
+                    return RgbaImage::new(image.width(), image.height());
+                }
+
+                return image;
+            }
+        };
+
+        self.renderer.render(&text, self.size, self.color)
+    }
+}
+
+/// Format a number of seconds as `MM:SS`, or as `H:MM:SS` when it's at least
+/// an hour.
+fn format_remaining(seconds: u64) -> String {
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{:02}:{:02}", minutes, seconds)
+    }
+}
+
+/// Parse the end of a countdown. This can be a duration from now like `5m` or
+/// `1h 30m`, a time like `18:30` that's either later today or tomorrow, or a
+/// date and a time like `2019-12-27 18:30`.
+pub fn parse_target(target: &str) -> Result<DateTime<Local>, String> {
+    // The clock ticks on the second, so durations should also end on the
+    // second
+    let now = Local::now().with_nanosecond(0).unwrap();
+    let invalid = || {
+        format!(
+            "'{}' should be a duration like 5m, a time like 18:30, or a date \
+             and a time like 2019-12-27 18:30",
+            target
+        )
+    };
+
+    if let Ok(duration) = humantime::parse_duration(target) {
+        return chrono::Duration::from_std(duration)
+            .ok()
+            .and_then(|duration| now.checked_add_signed(duration))
+            .ok_or_else(invalid);
+    }
+
+    let time = ["%H:%M:%S", "%H:%M"]
+        .iter()
+        .find_map(|format| NaiveTime::parse_from_str(target, format).ok());
+    let date_time = match time {
+        Some(time) => {
+            let today = now.naive_local().date().and_time(time);
+            if today > now.naive_local() {
+                today
+            } else {
+                today + chrono::Duration::days(1)
+            }
+        }
+        None => ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"]
+            .iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(target, format).ok())
+            .ok_or_else(invalid)?,
+    };
+
+    // Times that are skipped or repeated because of daylight saving time are
+    // ambiguous
+    Local
+        .from_local_datetime(&date_time)
+        .single()
+        .ok_or_else(|| format!("'{}' is ambiguous in the local time zone", target))
+}
+
+/// The time until the next second starts. Ticking on the second keeps the
+/// clock from lagging behind by up to a second.
+pub fn until_next_second() -> Duration {
+    // This can exceed a full second during a leap second
+    let nanos = Local::now().nanosecond();
+    Duration::from_nanos(1_000_000_000u64.saturating_sub(u64::from(nanos)))
+}
+
+/// Check whether `format` is a valid strftime-style format string. Formatting
+/// the time with an invalid format would otherwise panic.
+pub fn validate_format(format: String) -> Result<(), String> {
+    if StrftimeItems::new(&format).any(|item| item == Item::Error) {
+        Err(format!("'{}' is not a valid time format", format))
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,18 @@
+use image::Rgba;
+
+use crate::error::Error;
+
+/// Parse a hexadecimal `RRGGBB` or `RRGGBBAA` color, optionally prefixed with a
+/// `#`. Colors without an alpha component are fully opaque.
+pub fn parse_color(color: &str) -> Result<Rgba<u8>, Error> {
+    let hex = color.trim_start_matches('#');
+    let invalid = || Error::InvalidColor(color.to_owned());
+    if !(hex.len() == 6 || hex.len() == 8) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+
+    let channel = |idx: usize| u8::from_str_radix(&hex[idx * 2..idx * 2 + 2], 16).unwrap();
+    let alpha = if hex.len() == 8 { channel(3) } else { 255 };
+
+    Ok(Rgba([channel(0), channel(1), channel(2), alpha]))
+}
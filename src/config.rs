@@ -0,0 +1,159 @@
+//! Loading default options from a `pingas.toml` file.
+//!
+//! Options are resolved in the following order, from highest to lowest
+//! precedence:
+//!
+//! 1. Options passed on the command line.
+//! 2. Options set in the config file passed with `--config`, or in the first
+//!    `pingas.toml` found in the current directory or in
+//!    `$XDG_CONFIG_HOME/pingas/` (`~/.config/pingas/` if `XDG_CONFIG_HOME` is
+//!    not set).
+//! 3. The built-in defaults.
+
+use pingas::Prefix;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::args::check_rate;
+
+/// The name of the config file we'll look for when `--config` is not passed.
+const CONFIG_FILE_NAME: &str = "pingas.toml";
+
+/// The scaling filters that can be passed to `--filter`.
+pub const FILTERS: &[&str] = &["nearest", "linear", "cubic", "gaussian", "lanczos3"];
+
+/// A short description of one of the [`FILTERS`], as listed by
+/// `--list-filters`.
+pub fn filter_description(filter: &str) -> &'static str {
+    match filter {
+        "nearest" => {
+            "Keeps hard edges, and the only filter that doesn't create semitransparent pixels"
+        }
+        "linear" => "A triangle filter that interpolates between neighbouring pixels",
+        "cubic" => "The Catmull-Rom filter, sharper than linear",
+        "gaussian" => "A soft Gaussian blur",
+        "lanczos3" => "The sharpest filter, best suited for photos",
+        _ => unreachable!(),
+    }
+}
+
+/// Defaults for options that can also be passed on the command line. Every
+/// field is optional, and anything not set here falls back to the built-in
+/// default.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub repeat: Option<usize>,
+    pub filter: Option<String>,
+    pub rate: Option<f64>,
+    pub prefix: Option<String>,
+    pub screen_width: Option<u32>,
+    pub screen_height: Option<u32>,
+    /// The file this config was loaded from, if any.
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+}
+
+/// The values pingas ends up using for the options that can be set in the
+/// config file, after applying the command line and the built-in defaults. This
+/// uses the same keys as the config file, except that `prefix` is a list
+/// since `--prefix` can be passed more than once.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct EffectiveConfig {
+    pub repeat: usize,
+    pub filter: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate: Option<f64>,
+    pub prefix: Vec<String>,
+    pub screen_width: u32,
+    pub screen_height: u32,
+}
+
+impl Config {
+    /// Load the config file passed through `--config` in `args`, or the
+    /// default config file if it exists. An empty config is returned if there's
+    /// no config file to load.
+    pub fn load(args: &[OsString]) -> Result<Config, String> {
+        let path = match config_path_from_args(args) {
+            Some(path) => path,
+            None => match default_config_path() {
+                Some(path) => path,
+                None => return Ok(Config::default()),
+            },
+        };
+
+        let config = Config::load_from(&path)
+            .map_err(|err| format!("Can't load the config file '{}':\n{}", path.display(), err))?;
+
+        Ok(Config {
+            path: Some(path),
+            ..config
+        })
+    }
+
+    fn load_from(path: &Path) -> Result<Config, String> {
+        let contents = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let config: Config = toml::from_str(&contents).map_err(|err| err.to_string())?;
+
+        // clap doesn't validate default values, so we'll have to do that here
+        if let Some(filter) = &config.filter {
+            if !FILTERS.contains(&filter.as_str()) {
+                return Err(format!(
+                    "'{}' is not a valid filter, expected one of: {}",
+                    filter,
+                    FILTERS.join(", ")
+                ));
+            }
+        }
+        if let Some(prefix) = &config.prefix {
+            prefix.parse::<Prefix>().map_err(|err| err.to_string())?;
+        }
+        if config.screen_width == Some(0) || config.screen_height == Some(0) {
+            return Err(String::from(
+                "The screen's dimensions should be positive integers",
+            ));
+        }
+        if config.repeat == Some(0) {
+            return Err(String::from(
+                "The number of repetitions should be a positive integer",
+            ));
+        }
+        if let Some(rate) = config.rate {
+            check_rate(rate, "The rate")?;
+        }
+
+        Ok(config)
+    }
+}
+
+/// The config file has to be loaded before clap parses the arguments since its
+/// contents are used as clap's default values, so we'll have to look for the
+/// `--config` option ourselves.
+fn config_path_from_args(args: &[OsString]) -> Option<PathBuf> {
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            return args.next().map(PathBuf::from);
+        } else if let Some(path) = arg.to_str().and_then(|arg| arg.strip_prefix("--config=")) {
+            return Some(PathBuf::from(path));
+        } else if arg == "--" {
+            break;
+        }
+    }
+
+    None
+}
+
+fn default_config_path() -> Option<PathBuf> {
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")));
+
+    let local = Some(PathBuf::from(CONFIG_FILE_NAME));
+    let global = config_dir.map(|dir| dir.join("pingas").join(CONFIG_FILE_NAME));
+    local.into_iter().chain(global).find(|path| path.is_file())
+}
@@ -0,0 +1,339 @@
+use image::pnm::PNMDecoder;
+use image::tiff::TIFFDecoder;
+use image::{
+    ColorType, DynamicImage, GenericImageView, ImageBuffer, ImageDecoder, ImageError, ImageFormat,
+};
+use std::io::Cursor;
+
+use crate::animation::{decode_apng, decode_gif};
+use crate::canvas::ResizeMode;
+use crate::error::Error;
+use crate::source::Source;
+use crate::svg::{is_svg, rasterize_svg, SVG_DPI};
+use crate::webp::decode_webp;
+
+/// Decode `data` as an image in `format`. `image` only supports eight bits per
+/// channel, so images with 16 bits per channel are converted by keeping the
+/// most significant byte of every sample. The PNG decoder already does this on
+/// its own, so this makes 16-bit PNM and TIFF images end up with the same
+/// colors as a 16-bit PNG would.
+pub fn decode_image(data: &[u8], format: ImageFormat) -> Result<DynamicImage, ImageError> {
+    if let Some(err) = unsupported_format(data) {
+        return Err(ImageError::UnsupportedError(err.to_string()));
+    }
+    if cfg!(feature = "webp") && format == ImageFormat::WEBP {
+        let mut frames = decode_webp(data)?;
+        return Ok(DynamicImage::ImageRgba8(frames.swap_remove(0).image));
+    }
+
+    match image::load_from_memory_with_format(data, format) {
+        Err(ImageError::UnsupportedColor(color)) if is_16_bit(color) => match format {
+            ImageFormat::PNM => decode_16_bit(PNMDecoder::new(data)?),
+            ImageFormat::TIFF => decode_16_bit(TIFFDecoder::new(Cursor::new(data))?),
+            _ => Err(ImageError::UnsupportedColor(color)),
+        },
+        result => result,
+    }
+}
+
+/// Recognize images in formats this build of pingas can't decode, so they can
+/// be rejected with a clear error instead of a vague decoding error. Without
+/// the `webp` feature only lossy WebP images without an alpha channel can be
+/// decoded. AVIF images can't be decoded at all.
+pub fn unsupported_format(data: &[u8]) -> Option<Error> {
+    // AVIF uses the ISO base media file format, which starts with a box listing
+    // the brands the file is compatible with
+    if data.get(4..8) == Some(b"ftyp") && matches!(data.get(8..12), Some(b"avif") | Some(b"avis")) {
+        return Some(Error::UnsupportedFormat("AVIF"));
+    }
+
+    if cfg!(feature = "webp") || data.get(..4) != Some(b"RIFF") || data.get(8..12) != Some(b"WEBP")
+    {
+        return None;
+    }
+    let format = match data.get(12..16) {
+        Some(b"VP8L") => "Lossless WebP",
+        // The extended format's header contains a flag for animations
+        Some(b"VP8X")
+            if data
+                .get(20)
+                .is_some_and(|flags| flags & ANIMATION_FLAG != 0) =>
+        {
+            "Animated WebP"
+        }
+        Some(b"VP8X") => "Extended WebP",
+        _ => return None,
+    };
+
+    Some(Error::MissingFeature {
+        format,
+        feature: "webp",
+    })
+}
+
+/// The bit in a WebP `VP8X` chunk's flags that's set for animated images.
+const ANIMATION_FLAG: u8 = 0b0000_0010;
+
+/// Decode an image, an animated GIF, an animated PNG, or with the `webp`
+/// feature an animated WebP image from memory. SVG images
+/// are rasterized at their natural size. If `auto_orient` is set, JPEG images
+/// get rotated according to their EXIF orientation tag since phones tend to
+/// store photos sideways.
+pub fn decode_source(data: &[u8], auto_orient: bool) -> Result<Source, Error> {
+    if let Some(err) = unsupported_format(data) {
+        return Err(err);
+    }
+    if is_svg(data) {
+        let image = rasterize_svg(data, None, None, ResizeMode::default(), SVG_DPI)?;
+        return Ok(Source::Still(DynamicImage::ImageRgba8(image)));
+    }
+
+    let format = image::guess_format(data).map_err(|_| Error::UnrecognizedFormat)?;
+    let frames = match format {
+        ImageFormat::GIF => Some(decode_gif(data)?),
+        ImageFormat::PNG => Some(decode_apng(data)?),
+        ImageFormat::WEBP if cfg!(feature = "webp") => Some(decode_webp(data)?),
+        _ => None,
+    };
+    if let Some(mut frames) = frames {
+        // Every frame is as large as the GIF's logical screen or the entire
+        // image
+        if frames
+            .first()
+            .is_some_and(|frame| frame.image.width() == 0 || frame.image.height() == 0)
+        {
+            return Err(Error::EmptyImage);
+        }
+        if frames.len() > 1 {
+            return Ok(Source::Animation(frames));
+        } else if let Some(frame) = frames.pop() {
+            return Ok(Source::Still(DynamicImage::ImageRgba8(frame.image)));
+        }
+    }
+
+    let image = decode_image(data, format)?;
+    if image.width() == 0 || image.height() == 0 {
+        return Err(Error::EmptyImage);
+    }
+    if auto_orient && format == ImageFormat::JPEG {
+        if let Some(orientation) = exif_orientation(data) {
+            return Ok(Source::Still(apply_orientation(image, orientation)));
+        }
+    }
+
+    Ok(Source::Still(image))
+}
+
+/// Read the EXIF orientation tag from an image, if it has one.
+fn exif_orientation(data: &[u8]) -> Option<u32> {
+    let exif = exif::Reader::new()
+        .read_from_container(&mut Cursor::new(data))
+        .ok()?;
+
+    exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)?
+        .value
+        .get_uint(0)
+}
+
+/// Rotate and flip an image so it's the right way up according to its EXIF
+/// orientation. See the EXIF specification for the meaning of these values.
+pub fn apply_orientation(image: DynamicImage, orientation: u32) -> DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    }
+}
+
+fn is_16_bit(color: ColorType) -> bool {
+    match color {
+        ColorType::Gray(bits)
+        | ColorType::GrayA(bits)
+        | ColorType::RGB(bits)
+        | ColorType::RGBA(bits)
+        | ColorType::BGR(bits)
+        | ColorType::BGRA(bits) => bits == 16,
+        ColorType::Palette(_) => false,
+    }
+}
+
+/// Read an image with 16 bits per channel from a decoder that outputs its
+/// samples in native byte order, and convert it to eight bits per channel.
+fn decode_16_bit<'a>(decoder: impl ImageDecoder<'a>) -> Result<DynamicImage, ImageError> {
+    let (width, height) = decoder.dimensions();
+    let (width, height) = (width as u32, height as u32);
+    let num_pixels = u64::from(width) * u64::from(height);
+    if num_pixels == 0 {
+        return Err(ImageError::DimensionError);
+    }
+
+    let samples: Vec<u8> = decoder
+        .read_image()?
+        .chunks_exact(2)
+        .map(|sample| (u16::from_ne_bytes([sample[0], sample[1]]) >> 8) as u8)
+        .collect();
+
+    // The PNM decoder reports 16-bit RGB images as grayscale with an alpha
+    // channel, so we'll go by the number of samples per pixel instead
+    let image = match samples.len() as u64 / num_pixels {
+        1 => ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageLuma8),
+        2 => ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageLumaA8),
+        3 => ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageRgb8),
+        4 => ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageRgba8),
+        _ => None,
+    };
+
+    image.ok_or(ImageError::DimensionError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::png::PNGEncoder;
+    use image::{Rgba, RgbaImage};
+
+    /// Encode 16-bit samples in the big endian byte order PNM and PNG use.
+    fn big_endian(samples: &[u16]) -> Vec<u8> {
+        samples
+            .iter()
+            .flat_map(|sample| sample.to_be_bytes().to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn decodes_16_bit_rgb_pnm() {
+        let mut data = b"P6 2 1 65535\n".to_vec();
+        data.extend(big_endian(&[
+            0xffff, 0x0000, 0x8000, 0x0000, 0xfeff, 0x01ff,
+        ]));
+
+        let image = decode_image(&data, ImageFormat::PNM).unwrap().to_rgba();
+        assert_eq!(image.dimensions(), (2, 1));
+        assert_eq!(*image.get_pixel(0, 0), Rgba([0xff, 0x00, 0x80, 0xff]));
+        assert_eq!(*image.get_pixel(1, 0), Rgba([0x00, 0xfe, 0x01, 0xff]));
+    }
+
+    #[test]
+    fn decodes_16_bit_grayscale_pnm() {
+        let mut data = b"P5 3 1 65535\n".to_vec();
+        data.extend(big_endian(&[0x0000, 0x7fff, 0xffff]));
+
+        let image = decode_image(&data, ImageFormat::PNM).unwrap().to_rgba();
+        assert_eq!(*image.get_pixel(0, 0), Rgba([0x00, 0x00, 0x00, 0xff]));
+        assert_eq!(*image.get_pixel(1, 0), Rgba([0x7f, 0x7f, 0x7f, 0xff]));
+        assert_eq!(*image.get_pixel(2, 0), Rgba([0xff, 0xff, 0xff, 0xff]));
+    }
+
+    /// The start of a WebP file with a single chunk of the given type. The rest
+    /// of the file doesn't matter for recognizing the format.
+    fn webp_header(chunk: &[u8; 4], flags: u8) -> Vec<u8> {
+        let mut data = b"RIFF\0\0\0\0WEBP".to_vec();
+        data.extend_from_slice(chunk);
+        data.extend_from_slice(&[10, 0, 0, 0, flags, 0, 0, 0]);
+        data
+    }
+
+    #[test]
+    fn rejects_unsupported_formats() {
+        let avif = b"\0\0\0\x1cftypavif\0\0\0\0avifmif1miaf";
+        assert!(matches!(
+            unsupported_format(avif),
+            Some(Error::UnsupportedFormat("AVIF"))
+        ));
+    }
+
+    /// The name of the format `data` is in if decoding it requires the `webp`
+    /// feature.
+    #[cfg(not(feature = "webp"))]
+    fn missing_webp_feature(data: &[u8]) -> Option<&'static str> {
+        match unsupported_format(data) {
+            Some(Error::MissingFeature {
+                format,
+                feature: "webp",
+            }) => Some(format),
+            _ => None,
+        }
+    }
+
+    #[cfg(not(feature = "webp"))]
+    #[test]
+    fn requires_the_webp_feature_for_extended_webp() {
+        assert_eq!(
+            missing_webp_feature(&webp_header(b"VP8L", 0)),
+            Some("Lossless WebP")
+        );
+        assert_eq!(
+            missing_webp_feature(&webp_header(b"VP8X", ANIMATION_FLAG)),
+            Some("Animated WebP")
+        );
+        assert_eq!(
+            missing_webp_feature(&webp_header(b"VP8X", 0)),
+            Some("Extended WebP")
+        );
+        assert!(matches!(
+            decode_image(&webp_header(b"VP8L", 0), ImageFormat::WEBP),
+            Err(ImageError::UnsupportedError(message)) if message.contains("`webp` feature")
+        ));
+    }
+
+    #[cfg(feature = "webp")]
+    #[test]
+    fn accepts_extended_webp_with_the_webp_feature() {
+        assert!(unsupported_format(&webp_header(b"VP8L", 0)).is_none());
+        assert!(unsupported_format(&webp_header(b"VP8X", ANIMATION_FLAG)).is_none());
+    }
+
+    #[test]
+    fn accepts_supported_formats() {
+        assert!(unsupported_format(&webp_header(b"VP8 ", 0)).is_none());
+        assert!(unsupported_format(b"\x89PNG\r\n\x1a\n").is_none());
+        assert!(unsupported_format(b"\0\0\0\x18ftypmp42").is_none());
+    }
+
+    #[test]
+    fn applies_exif_orientations() {
+        let image =
+            DynamicImage::ImageRgba8(RgbaImage::from_fn(2, 1, |x, _| Rgba([x as u8, 0, 0, 255])));
+
+        let rotated = apply_orientation(image.clone(), 6).to_rgba();
+        assert_eq!(rotated.dimensions(), (1, 2));
+        assert_eq!(*rotated.get_pixel(0, 1), Rgba([1, 0, 0, 255]));
+        let flipped = apply_orientation(image.clone(), 2).to_rgba();
+        assert_eq!(*flipped.get_pixel(0, 0), Rgba([1, 0, 0, 255]));
+        assert_eq!(apply_orientation(image, 1).dimensions(), (2, 1));
+    }
+
+    #[test]
+    fn decodes_still_images() {
+        let mut data = Vec::new();
+        PNGEncoder::new(&mut data)
+            .encode(&[255, 0, 0, 255], 1, 1, ColorType::RGBA(8))
+            .unwrap();
+        assert!(matches!(decode_source(&data, true), Ok(Source::Still(_))));
+        assert!(matches!(
+            decode_source(b"not an image", true),
+            Err(Error::UnrecognizedFormat)
+        ));
+    }
+
+    #[test]
+    fn decodes_16_bit_rgba_png() {
+        let samples = [
+            0xff00, 0x8080, 0x0001, 0xffff, // The first pixel
+            0x1234, 0xabcd, 0xfffe, 0x8000, // The second pixel
+        ];
+        let mut data = Vec::new();
+        PNGEncoder::new(&mut data)
+            .encode(&big_endian(&samples), 2, 1, ColorType::RGBA(16))
+            .unwrap();
+
+        let image = decode_image(&data, ImageFormat::PNG).unwrap().to_rgba();
+        assert_eq!(*image.get_pixel(0, 0), Rgba([0xff, 0x80, 0x00, 0xff]));
+        assert_eq!(*image.get_pixel(1, 0), Rgba([0x12, 0xab, 0xff, 0x80]));
+    }
+}
@@ -0,0 +1,160 @@
+use image::RgbaImage;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::Error;
+
+/// The number of bits used for the red, green, and blue channels of a color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColorDepth {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+}
+
+impl ColorDepth {
+    /// 5 bits for red and blue and 6 bits for green.
+    pub const RGB565: ColorDepth = ColorDepth {
+        red: 5,
+        green: 6,
+        blue: 5,
+    };
+}
+
+impl Default for ColorDepth {
+    fn default() -> Self {
+        ColorDepth::RGB565
+    }
+}
+
+impl fmt::Display for ColorDepth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "rgb{}{}{}", self.red, self.green, self.blue)
+    }
+}
+
+impl FromStr for ColorDepth {
+    type Err = Error;
+
+    /// Parse a color depth in the `rgbRGB` format, where every channel's
+    /// number of bits is a single digit between 1 and 8. `rgb332` for instance
+    /// stands for 3 bits of red, 3 bits of green, and 2 bits of blue.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bits: Vec<u8> = match s.strip_prefix("rgb") {
+            Some(digits) if digits.len() == 3 => digits
+                .chars()
+                .filter_map(|c| c.to_digit(10))
+                .filter(|bits| (1..=8).contains(bits))
+                .map(|bits| bits as u8)
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        match bits[..] {
+            [red, green, blue] => Ok(ColorDepth { red, green, blue }),
+            _ => Err(Error::InvalidColorDepth(s.to_owned())),
+        }
+    }
+}
+
+/// Reduce an image to `depth` using Floyd–Steinberg dithering. The
+/// quantization error of every pixel gets spread out over its neighbours, which
+/// avoids the banding you would otherwise get in gradients when the screen
+/// can't reproduce subtle differences in color. The alpha channel is left
+/// untouched.
+pub fn dither(image: &mut RgbaImage, depth: ColorDepth) {
+    let levels = [depth.red, depth.green, depth.blue].map(|bits| f32::from((1u16 << bits) - 1));
+
+    floyd_steinberg(image, |old| {
+        let mut new = [0.0; 3];
+        for channel in 0..3 {
+            new[channel] =
+                (old[channel] / 255.0 * levels[channel]).round() / levels[channel] * 255.0;
+        }
+
+        new
+    });
+}
+
+/// Reduce an image to `depth` without dithering. Only the most significant
+/// bits of every channel are kept, which is what a screen with that color depth
+/// would show anyway, so any banding shows up before the image gets sent
+/// instead of on the screen. The remaining bits are scaled back up to the full
+/// range so black and white stay intact, matching the colors [`dither()`]
+/// produces. The alpha channel is left untouched, so it still gets packed into
+/// the address with all 8 of its bits.
+pub fn reduce_color_depth(image: &mut RgbaImage, depth: ColorDepth) {
+    let bits = [depth.red, depth.green, depth.blue];
+    for pixel in image.pixels_mut() {
+        for channel in 0..3 {
+            let max_level = (1u16 << bits[channel]) - 1;
+            let level = u16::from(pixel[channel]) >> (8 - bits[channel]);
+            pixel[channel] = ((level * 255 + max_level / 2) / max_level) as u8;
+        }
+    }
+}
+
+/// Floyd–Steinberg dithering with an arbitrary quantization function. The
+/// function receives a pixel's red, green, and blue values clamped to
+/// `[0, 255]`, and should return the closest color that can be displayed.
+pub(crate) fn floyd_steinberg(image: &mut RgbaImage, quantize: impl Fn([f32; 3]) -> [f32; 3]) {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+
+    // The accumulated error can push channels outside of the [0, 255] range,
+    // so we'll work with floats until the very end
+    let mut channels: Vec<[f32; 3]> = image
+        .pixels()
+        .map(|pixel| [0, 1, 2].map(|channel| f32::from(pixel[channel])))
+        .collect();
+
+    for y in 0..height {
+        for x in 0..width {
+            let idx = y * width + x;
+            let old = channels[idx].map(|value| value.clamp(0.0, 255.0));
+            let new = quantize(old);
+            channels[idx] = new;
+
+            let mut spread = |x: usize, y: usize, weight: f32| {
+                if x < width && y < height {
+                    let neighbour = &mut channels[y * width + x];
+                    for channel in 0..3 {
+                        neighbour[channel] += (old[channel] - new[channel]) * weight;
+                    }
+                }
+            };
+            spread(x + 1, y, 7.0 / 16.0);
+            if x > 0 {
+                spread(x - 1, y + 1, 3.0 / 16.0);
+            }
+            spread(x, y + 1, 5.0 / 16.0);
+            spread(x + 1, y + 1, 1.0 / 16.0);
+        }
+    }
+
+    for (pixel, values) in image.pixels_mut().zip(channels) {
+        for channel in 0..3 {
+            pixel[channel] = values[channel].round() as u8;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn reduces_color_depth() {
+        let mut image = RgbaImage::from_fn(3, 1, |x, _| match x {
+            0 => Rgba([255, 255, 255, 255]),
+            1 => Rgba([0, 3, 7, 128]),
+            _ => Rgba([0b1010_1111, 0b1010_1111, 0b1010_1111, 0]),
+        });
+        reduce_color_depth(&mut image, "rgb332".parse().unwrap());
+
+        assert_eq!(*image.get_pixel(0, 0), Rgba([255, 255, 255, 255]));
+        assert_eq!(*image.get_pixel(1, 0), Rgba([0, 0, 0, 128]));
+        // 101 out of 7 and 10 out of 3 for blue
+        assert_eq!(*image.get_pixel(2, 0), Rgba([182, 182, 170, 0]));
+    }
+}
@@ -0,0 +1,49 @@
+use image::{imageops, RgbaImage};
+
+/// Only keep the pixels in `image` that lie on an edge, making every other
+/// pixel completely transparent. Edges are found by running a Sobel filter
+/// over the image's brightness. `threshold` is the gradient strength a pixel
+/// needs to count as an edge, between 0 for any change at all and 1 for the
+/// jump from black to white. The edge pixels keep their original colors.
+///
+/// Transparent pixels count as black, so the outline of a shape on a
+/// transparent background is also detected.
+pub fn outline(image: &mut RgbaImage, threshold: f32) {
+    let gray = imageops::grayscale(image);
+    let (width, height) = image.dimensions();
+    if width == 0 || height == 0 {
+        return;
+    }
+
+    // Pixels past the edges of the image repeat the outermost pixels
+    let brightness = |x: i64, y: i64| {
+        let x = x.clamp(0, i64::from(width) - 1) as u32;
+        let y = y.clamp(0, i64::from(height) - 1) as u32;
+        f32::from(gray.get_pixel(x, y)[0]) * f32::from(image.get_pixel(x, y)[3]) / 255.0
+    };
+    let is_edge = |x: u32, y: u32| {
+        let (x, y) = (i64::from(x), i64::from(y));
+        let horizontal = (brightness(x + 1, y - 1)
+            + 2.0 * brightness(x + 1, y)
+            + brightness(x + 1, y + 1))
+            - (brightness(x - 1, y - 1) + 2.0 * brightness(x - 1, y) + brightness(x - 1, y + 1));
+        let vertical = (brightness(x - 1, y + 1)
+            + 2.0 * brightness(x, y + 1)
+            + brightness(x + 1, y + 1))
+            - (brightness(x - 1, y - 1) + 2.0 * brightness(x, y - 1) + brightness(x + 1, y - 1));
+
+        // The strongest possible gradient in either direction is four times
+        // the difference between black and white
+        horizontal.hypot(vertical) / (4.0 * 255.0) > threshold
+    };
+
+    let edges: Vec<bool> = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| is_edge(x, y))
+        .collect();
+    for (pixel, is_edge) in image.pixels_mut().zip(edges) {
+        if !is_edge {
+            pixel[3] = 0;
+        }
+    }
+}
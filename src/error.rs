@@ -0,0 +1,154 @@
+use std::fmt;
+use std::path::PathBuf;
+
+/// Everything that can go wrong while loading or drawing an image.
+#[derive(Debug)]
+pub enum Error {
+    /// The image could not be opened or decoded.
+    Image(image::ImageError),
+    /// The image data is not in any of the formats we can decode.
+    UnrecognizedFormat,
+    /// The image is in a format we recognize but can't decode, like AVIF.
+    UnsupportedFormat(&'static str),
+    /// The image is in a format that can only be decoded when pingas is built
+    /// with a cargo feature that's not enabled.
+    MissingFeature {
+        format: &'static str,
+        feature: &'static str,
+    },
+    /// The image was decoded successfully, but it is zero pixels wide or high.
+    EmptyImage,
+    /// A frame of an image sequence could not be opened or decoded.
+    Frame(PathBuf, image::ImageError),
+    /// The directory passed as an image sequence does not contain any files.
+    EmptySequence(PathBuf),
+    /// The SVG image could not be parsed.
+    Svg(String),
+    /// The font could not be loaded.
+    Font(String),
+    /// A color was not in the `RRGGBB` or `RRGGBBAA` format.
+    InvalidColor(String),
+    /// A color depth was not in the `rgbRGB` format.
+    InvalidColorDepth(String),
+    /// The palette could not be read, was empty, or contained an invalid
+    /// color.
+    Palette(String),
+    /// The crop rectangle does not lie within the image. The rectangle is
+    /// stored as `(x, y, width, height)`.
+    InvalidCrop {
+        crop: (u32, u32, u32, u32),
+        image_size: (u32, u32),
+    },
+    /// The IPv6 prefix passed to pingas was not a valid /64 prefix.
+    InvalidPrefix(String),
+    /// The image does not fit on the screen. `left`, `top`, `right`, and
+    /// `bottom` contain the number of pixels the image extends past those
+    /// edges of the screen. The image itself can only extend past the left and
+    /// top edges because of its border.
+    OutOfBounds {
+        screen_size: (u32, u32),
+        left: u32,
+        top: u32,
+        right: u32,
+        bottom: u32,
+    },
+    /// fastping_rs could not set up its sockets. This usually means we're
+    /// missing the privileges required to send ICMP packets.
+    Pinger(String),
+    /// The sockets could not be bound to the requested address or interface.
+    Bind(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Image(err) => write!(f, "Can't open file:\n{}", err),
+            Error::EmptyImage => write!(f, "The image does not contain any pixels"),
+            Error::UnrecognizedFormat => write!(f, "The data is not in a recognized image format"),
+            Error::UnsupportedFormat(name) => write!(
+                f,
+                "{} images are not supported, convert the image to PNG or to GIF for animations",
+                name
+            ),
+            Error::MissingFeature { format, feature } => write!(
+                f,
+                "{} images are not supported by this build of pingas, enable the `{}` feature to \
+                 decode them",
+                format, feature
+            ),
+            Error::Frame(path, err) => {
+                write!(f, "Can't open frame '{}':\n{}", path.display(), err)
+            }
+            Error::EmptySequence(path) => {
+                write!(
+                    f,
+                    "The directory '{}' does not contain any frames",
+                    path.display()
+                )
+            }
+            Error::Svg(err) => write!(f, "Can't parse SVG image:\n{}", err),
+            Error::Font(err) => write!(f, "Can't load font:\n{}", err),
+            Error::InvalidColor(color) => write!(
+                f,
+                "Invalid color: '{}' should be in the RRGGBB or RRGGBBAA format",
+                color
+            ),
+            Error::InvalidColorDepth(depth) => write!(
+                f,
+                "Invalid color depth: '{}' should be in the rgbRGB format, e.g. rgb332",
+                depth
+            ),
+            Error::Palette(err) => write!(f, "Can't load palette:\n{}", err),
+            Error::InvalidCrop {
+                crop: (x, y, width, height),
+                image_size: (image_width, image_height),
+            } => write!(
+                f,
+                "The {}x{} crop at ({}, {}) does not fit within the {}x{} image",
+                width, height, x, y, image_width, image_height
+            ),
+            Error::InvalidPrefix(err) => write!(f, "Invalid prefix: {}", err),
+            Error::OutOfBounds {
+                screen_size: (screen_width, screen_height),
+                left,
+                top,
+                right,
+                bottom,
+            } => {
+                write!(
+                    f,
+                    "The image does not fit on the {}x{} screen:",
+                    screen_width, screen_height
+                )?;
+                if *left > 0 {
+                    write!(
+                        f,
+                        "\n- Its border extends {} pixels past the left edge",
+                        left
+                    )?;
+                }
+                if *top > 0 {
+                    write!(f, "\n- Its border extends {} pixels past the top edge", top)?;
+                }
+                if *right > 0 {
+                    write!(f, "\n- It extends {} pixels past the right edge", right)?;
+                }
+                if *bottom > 0 {
+                    write!(f, "\n- It extends {} pixels past the bottom edge", bottom)?;
+                }
+
+                Ok(())
+            }
+            Error::Pinger(err) => write!(f, "Can't create pinger:\n{}", err),
+            Error::Bind(err) => write!(f, "Can't bind the sockets:\n{}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<image::ImageError> for Error {
+    fn from(err: image::ImageError) -> Self {
+        Error::Image(err)
+    }
+}
@@ -0,0 +1,68 @@
+//! The drawing logic behind the `pingas` binary, usable as a library.
+//!
+//! The functions and types exported from the crate root are the ones intended
+//! for embedding pingas in another application. A typical program resizes its
+//! image with [`resize_image`] (or generates an [`image::RgbaImage`] directly)
+//! and hands it to [`Canvas::draw_image`]. Animations can be played with
+//! [`Canvas::draw_animation`]. Images can be reduced to a lower color depth
+//! with [`dither`] or [`reduce_color_depth`], or to a fixed [`Palette`] with
+//! [`quantize`], before drawing them. A [`Source`] holds either a still image or
+//! an animation, and functions like [`crop_source`] and [`map_frames`]
+//! transform every frame of it at once. [`decode_source`] turns the contents of
+//! an image file into a [`Source`], playing animated GIFs and PNGs as
+//! animations and rotating photos according to their EXIF orientation. If you only want to know which addresses
+//! would be pinged, [`image_addresses`] lists them for an entire image without
+//! sending anything, and [`build_address`] can be used on its own for single
+//! pixels.
+
+mod address;
+mod adjust;
+mod alpha;
+mod animation;
+mod backend;
+mod canvas;
+mod color;
+mod decode;
+mod dither;
+mod edges;
+mod error;
+mod limiter;
+mod marquee;
+mod order;
+mod palette;
+mod runs;
+mod source;
+mod stats;
+mod svg;
+mod text;
+mod transition;
+mod webp;
+
+pub use address::{build_address, image_addresses, PixelLayout, Prefix};
+pub use adjust::{adjust_colors, blur, invert_colors, sharpen, to_grayscale, Adjustments};
+pub use alpha::{apply_alpha_mode, chroma_key, scale_alpha, vignette, AlphaMode};
+pub use animation::{
+    decode_apng, decode_gif, frame_difference, load_sequence, play_count, Frame, CLEAR_COLOR,
+};
+pub use backend::{Backend, Binding, MAX_PAYLOAD_SIZE};
+pub use canvas::{
+    mirror_quad, resize_image, scale_image, Canvas, Extent, Ping, ResizeMode, DEFAULT_SCREEN_SIZE,
+};
+pub use color::parse_color;
+pub use decode::{apply_orientation, decode_image, decode_source, unsupported_format};
+pub use dither::{dither, reduce_color_depth, ColorDepth};
+pub use edges::outline;
+pub use error::Error;
+pub use marquee::ScrollDirection;
+pub use order::Order;
+pub use palette::{dither_to_palette, quantize, Palette};
+pub use runs::{color_runs, ColorRuns};
+pub use source::{
+    blink_frames, check_enlarged_size, crop_source, for_each_frame, map_frames, resize_source,
+    shrink_to_fit, source_size, visible_pixels, Source,
+};
+pub use stats::Stats;
+pub use svg::{is_svg, rasterize_svg, SVG_DPI};
+pub use text::TextRenderer;
+pub use transition::{Easing, Transition};
+pub use webp::decode_webp;
@@ -0,0 +1,325 @@
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::stats::Stats;
+
+/// How many seconds worth of packets can be sent in a single burst after the
+/// limiter has been idle for a while.
+const BURST_SECONDS: f64 = 0.1;
+
+/// The fraction of the target rate a ramp starts at, so the first packets
+/// don't have to wait forever.
+const RAMP_START: f64 = 0.01;
+
+/// How often the adaptive rate gets adjusted.
+const ADJUSTMENT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The adaptive rate gets cut when more than this fraction of the packets sent
+/// since the last adjustment failed or went unanswered.
+const ERROR_THRESHOLD: f64 = 0.01;
+
+/// The adaptive rate gets multiplied by this when there were too many errors.
+const DECREASE_FACTOR: f64 = 0.5;
+
+/// The adaptive rate increases by this fraction of the maximum rate when there
+/// weren't.
+const INCREASE_STEP: f64 = 0.05;
+
+/// The adaptive rate never drops below this fraction of the maximum rate.
+const MIN_ADAPTIVE_RATE: f64 = 0.01;
+
+/// A token bucket rate limiter shared between all workers. Tokens are refilled
+/// at a constant rate, and every packet sent costs one token.
+#[derive(Debug)]
+pub struct RateLimiter {
+    /// The number of tokens added per second. With an adaptive rate this is
+    /// the maximum rate.
+    rate: f64,
+    /// The maximum number of tokens that can be stored.
+    capacity: f64,
+    /// The time it takes to linearly increase the rate to `rate`, if set.
+    ramp: Option<Duration>,
+    /// Whether the rate should be adjusted based on the number of failed and
+    /// unanswered packets.
+    adaptive: bool,
+    /// The statistics used for adjusting the rate. The rate that's currently
+    /// allowed is also published here.
+    stats: Stats,
+    state: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// This can go negative when a worker takes out more tokens than there are
+    /// available. The next caller then has to wait for that debt to be paid off.
+    tokens: f64,
+    last_refill: Instant,
+    /// When the first packets were sent. The ramp starts from here.
+    first_acquire: Option<Instant>,
+    /// The rate before the ramp gets applied. This only changes when the rate
+    /// is adaptive.
+    target: f64,
+    last_adjustment: Instant,
+    /// The number of packets sent and the number of errors at the last
+    /// adjustment.
+    counts_at_adjustment: (u64, u64),
+}
+
+impl RateLimiter {
+    /// Create a limiter allowing `rate` packets per second. If `ramp` is set,
+    /// the allowed rate starts out at a small fraction of `rate` when the first
+    /// packets get sent and increases linearly until it reaches `rate` after
+    /// `ramp` has passed. If `adaptive` is set, `rate` is treated as the
+    /// maximum rate and the rate gets cut whenever `stats` report too many
+    /// errors, after which it slowly increases again.
+    pub fn new(rate: f64, ramp: Option<Duration>, adaptive: bool, stats: Stats) -> Self {
+        let capacity = (rate * BURST_SECONDS).max(1.0);
+        stats.set_rate_limit(rate);
+
+        RateLimiter {
+            rate,
+            capacity,
+            ramp,
+            adaptive,
+            stats,
+            state: Mutex::new(Bucket {
+                // There's no burst at the start of a ramp
+                tokens: if ramp.is_some() { 0.0 } else { capacity },
+                last_refill: Instant::now(),
+                first_acquire: None,
+                target: rate,
+                last_adjustment: Instant::now(),
+                counts_at_adjustment: (0, 0),
+            }),
+        }
+    }
+
+    /// The length of the ramp in seconds, if there is one.
+    fn ramp_seconds(&self) -> Option<f64> {
+        self.ramp
+            .map(|ramp| ramp.as_secs_f64())
+            .filter(|&ramp| ramp > 0.0)
+    }
+
+    /// The rate that's allowed `elapsed` seconds after the first packets were
+    /// sent when ramping up to `rate`.
+    fn rate_at(&self, rate: f64, elapsed: f64) -> f64 {
+        match self.ramp_seconds() {
+            Some(ramp) => rate * (elapsed / ramp).clamp(RAMP_START, 1.0),
+            None => rate,
+        }
+    }
+
+    /// The number of seconds it takes to earn `tokens` tokens when ramping up
+    /// to `rate`, starting `elapsed` seconds after the first packets were sent.
+    /// During the ramp the rate first stays at its starting value for a bit and
+    /// then increases linearly, so the time spent in each of those phases has
+    /// to be accounted for separately.
+    fn time_to_earn(&self, rate: f64, mut elapsed: f64, mut tokens: f64) -> f64 {
+        let ramp = match self.ramp_seconds() {
+            Some(ramp) => ramp,
+            None => return tokens / rate,
+        };
+
+        let mut waited = 0.0;
+        let flat_until = RAMP_START * ramp;
+        if elapsed < flat_until {
+            let start_rate = rate * RAMP_START;
+            let phase = (flat_until - elapsed).min(tokens / start_rate);
+            waited += phase;
+            tokens -= phase * start_rate;
+            elapsed += phase;
+        }
+
+        // With the rate being `rate * t / ramp`, the number of tokens earned
+        // between `t0` and `t1` is `rate / (2 * ramp) * (t1² - t0²)`
+        if elapsed < ramp && tokens > 0.0 {
+            let remaining = rate / (2.0 * ramp) * (ramp.powi(2) - elapsed.powi(2));
+            if tokens <= remaining {
+                let end = (elapsed.powi(2) + tokens * 2.0 * ramp / rate).sqrt();
+                return waited + end - elapsed;
+            }
+
+            waited += ramp - elapsed;
+            tokens -= remaining;
+        }
+
+        waited + tokens.max(0.0) / rate
+    }
+
+    /// Block until `packets` packets may be sent. Workers send entire rows at
+    /// once, so instead of waiting for `packets` tokens to become available we
+    /// take them out immediately and sleep off the resulting debt. This keeps
+    /// the average rate correct even when a single row contains more pixels
+    /// than the bucket can hold.
+    pub fn acquire(&self, packets: usize) {
+        let wait = self.reserve(packets);
+        if wait > Duration::from_secs(0) {
+            thread::sleep(wait);
+        }
+    }
+
+    /// Take out the tokens for `packets` packets like [`acquire()`](Self::acquire),
+    /// but return how long the caller has to wait before sending them instead
+    /// of sleeping. At low rates that wait can be long enough that it should be
+    /// possible to cut it short.
+    pub fn reserve(&self, packets: usize) -> Duration {
+        let mut bucket = self.state.lock().unwrap();
+        let now = Instant::now();
+        let first_acquire = *bucket.first_acquire.get_or_insert(now);
+        if self.adaptive {
+            self.adjust(&mut bucket, now);
+        }
+
+        // While ramping up the bucket also shrinks, so we don't get any
+        // bursts larger than what the current rate would allow
+        let since_start = now.duration_since(first_acquire).as_secs_f64();
+        let rate = self.rate_at(bucket.target, since_start);
+        self.stats.set_rate_limit(rate);
+        let capacity = (rate * BURST_SECONDS).max(1.0).min(self.capacity);
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(capacity);
+        bucket.last_refill = now;
+
+        bucket.tokens -= packets as f64;
+        if bucket.tokens < 0.0 {
+            Duration::from_secs_f64(self.time_to_earn(bucket.target, since_start, -bucket.tokens))
+        } else {
+            Duration::from_secs(0)
+        }
+    }
+
+    /// Adjust the adaptive rate if enough time has passed since the last
+    /// adjustment. This uses additive increase and multiplicative decrease like
+    /// TCP's congestion control: the rate gets halved as soon as too many
+    /// packets fail or go unanswered, and it slowly creeps back up while
+    /// everything's fine.
+    fn adjust(&self, bucket: &mut Bucket, now: Instant) {
+        if now.duration_since(bucket.last_adjustment) < ADJUSTMENT_INTERVAL {
+            return;
+        }
+
+        let counts = (
+            self.stats.packets_sent(),
+            // Sends that only succeeded after retrying still mean the queues
+            // are congested
+            self.stats.unanswered() + self.stats.failed_sends() + self.stats.retried_sends(),
+        );
+        let sent = counts.0 - bucket.counts_at_adjustment.0;
+        let errors = counts.1 - bucket.counts_at_adjustment.1;
+        // There's nothing to go by if nothing has been sent
+        if sent > 0 {
+            bucket.target = if errors as f64 > sent as f64 * ERROR_THRESHOLD {
+                (bucket.target * DECREASE_FACTOR).max(self.rate * MIN_ADAPTIVE_RATE)
+            } else {
+                (bucket.target + self.rate * INCREASE_STEP).min(self.rate)
+            };
+        }
+
+        bucket.last_adjustment = now;
+        bucket.counts_at_adjustment = counts;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The time it takes to earn `tokens` tokens by numerically integrating
+    /// [`RateLimiter::rate_at()`], to check the closed form solution against.
+    fn integrate(limiter: &RateLimiter, rate: f64, elapsed: f64, tokens: f64) -> f64 {
+        let step = 1e-5;
+        let (mut time, mut earned) = (elapsed, 0.0);
+        while earned < tokens {
+            earned += limiter.rate_at(rate, time + step / 2.0) * step;
+            time += step;
+        }
+
+        time - elapsed
+    }
+
+    #[test]
+    fn earns_tokens_during_ramp() {
+        let limiter = RateLimiter::new(
+            100.0,
+            Some(Duration::from_secs(10)),
+            false,
+            Stats::default(),
+        );
+
+        // The rate stays at 1 packet per second for the first 0.1 seconds, then
+        // increases linearly to 100 packets per second at 10 seconds
+        for &(elapsed, tokens) in &[
+            // Only the flat phase
+            (0.0, 0.05),
+            // From the flat phase into the linear phase
+            (0.0, 20.0),
+            // Only the linear phase
+            (2.0, 10.0),
+            // From the linear phase past the end of the ramp
+            (8.0, 300.0),
+            // After the ramp
+            (12.0, 50.0),
+        ] {
+            let expected = integrate(&limiter, 100.0, elapsed, tokens);
+            let actual = limiter.time_to_earn(100.0, elapsed, tokens);
+            assert!(
+                (expected - actual).abs() < 1e-3,
+                "earning {} tokens after {}s: expected {}s, got {}s",
+                tokens,
+                elapsed,
+                expected,
+                actual
+            );
+        }
+        assert_eq!(limiter.time_to_earn(100.0, 12.0, 50.0), 0.5);
+    }
+
+    #[test]
+    fn adapts_rate_to_errors() {
+        let stats = Stats::default();
+        let limiter = RateLimiter::new(1000.0, None, true, stats.clone());
+        let mut bucket = limiter.state.lock().unwrap();
+        let mut adjust = |sent: usize, unanswered: usize| {
+            stats.add_packets_sent(sent);
+            stats.add_unanswered(unanswered);
+            let now = bucket.last_adjustment + ADJUSTMENT_INTERVAL;
+            limiter.adjust(&mut bucket, now);
+            bucket.target
+        };
+
+        // Too many errors halve the rate, and no errors increase it by 5% of
+        // the maximum rate
+        assert_eq!(adjust(1000, 100), 500.0);
+        assert_eq!(adjust(1000, 0), 550.0);
+        // Exactly 1% is still fine
+        assert_eq!(adjust(1000, 10), 600.0);
+        // Nothing changes when nothing has been sent
+        assert_eq!(adjust(0, 0), 600.0);
+
+        // The rate never drops below 1% of the maximum, and never goes over the
+        // maximum
+        for _ in 0..10 {
+            adjust(1000, 1000);
+        }
+        assert_eq!(adjust(1000, 1000), 10.0);
+        for _ in 0..30 {
+            adjust(1000, 0);
+        }
+        assert_eq!(adjust(1000, 0), 1000.0);
+    }
+
+    #[test]
+    fn waits_between_adjustments() {
+        let stats = Stats::default();
+        let limiter = RateLimiter::new(1000.0, None, true, stats.clone());
+        let mut bucket = limiter.state.lock().unwrap();
+        stats.add_packets_sent(1000);
+        stats.add_unanswered(1000);
+
+        let now = bucket.last_adjustment + ADJUSTMENT_INTERVAL / 2;
+        limiter.adjust(&mut bucket, now);
+        assert_eq!(bucket.target, 1000.0);
+    }
+}
@@ -0,0 +1,75 @@
+//! Logging for the `pingas` binary. Everything gets logged to STDERR so STDOUT
+//! stays clean for `--dry-run` and `--output-json`. The log level can be
+//! changed with `-v` and `--quiet`, and the `RUST_LOG` environment variable can
+//! be used for more fine grained filters.
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::env;
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The number of pings fastping_rs and the raw backend failed to send. They
+/// log an error every time this happens, which usually means the send queues
+/// are full.
+pub static SEND_ERRORS: AtomicU64 = AtomicU64::new(0);
+
+/// Counts failed sends and passes everything else on to `env_logger`.
+struct Logger {
+    inner: env_logger::Logger,
+    /// Failed sends are only counted at the normal log levels since there can
+    /// be thousands of them per second.
+    show_send_errors: bool,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        is_send_error(metadata) || self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if is_send_error(record.metadata()) {
+            SEND_ERRORS.fetch_add(1, Ordering::Relaxed);
+            if !self.show_send_errors {
+                return;
+            }
+        }
+
+        self.inner.log(record);
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+fn is_send_error(metadata: &Metadata) -> bool {
+    metadata.level() == Level::Error
+        && (metadata.target().starts_with("fastping_rs")
+            || metadata.target().starts_with("pingas::backend"))
+}
+
+/// Install the logger, logging everything up to `level` unless `RUST_LOG`
+/// says otherwise. Informational messages are printed as is, and everything
+/// else is prefixed with its level.
+pub fn init(level: LevelFilter) {
+    let mut builder = env_logger::Builder::new();
+    builder
+        .filter_level(level)
+        .format(|buf, record| match record.level() {
+            Level::Info => writeln!(buf, "{}", record.args()),
+            level => writeln!(buf, "[{}] {}", level, record.args()),
+        });
+    if let Ok(filters) = env::var("RUST_LOG") {
+        builder.parse_filters(&filters);
+    }
+
+    let inner = builder.build();
+    // Failed sends still need to be counted when errors are filtered out
+    let max_level = inner.filter().max(LevelFilter::Error);
+    log::set_boxed_logger(Box::new(Logger {
+        inner,
+        show_send_errors: level >= LevelFilter::Debug,
+    }))
+    .map(|()| log::set_max_level(max_level))
+    .unwrap();
+}
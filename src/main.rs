@@ -1,169 +1,1883 @@
-use clap::{value_t, value_t_or_exit, App, Arg};
-use fastping_rs::Pinger;
-use image::{FilterType, GenericImageView, Rgba};
-use std::net::{IpAddr, Ipv6Addr};
+use chrono::{DateTime, Local};
+use clap::{value_t, values_t, ArgMatches, ErrorKind};
+use image::{DynamicImage, FilterType, GenericImageView, ImageError, Rgba, RgbaImage};
+use log::LevelFilter;
+use pingas::{
+    adjust_colors, apply_alpha_mode, blink_frames, blur, check_enlarged_size, chroma_key,
+    color_runs, crop_source, decode_source, dither, dither_to_palette, for_each_frame,
+    frame_difference, invert_colors, is_svg, load_sequence, map_frames, mirror_quad, outline,
+    parse_color, play_count, quantize, rasterize_svg, reduce_color_depth, resize_image,
+    resize_source, scale_alpha, scale_image, sharpen, shrink_to_fit, source_size, to_grayscale,
+    vignette, Adjustments, AlphaMode, Backend, Binding, Canvas, ColorDepth, Easing, Error, Order,
+    Palette, PixelLayout, Prefix, ResizeMode, ScrollDirection, Source, Stats, TextRenderer,
+    Transition, CLEAR_COLOR,
+};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::fs;
+use std::io::{self, BufWriter, Read, Write};
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
 use std::process::exit;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::thread;
+use std::time::{Duration, Instant};
+
+mod app_error;
+mod args;
+mod bench;
+mod camera;
+mod clock;
+mod config;
+mod logging;
+mod manifest;
+mod metrics;
+mod pattern;
+mod pause;
+mod placement;
+mod progress;
+mod serve;
+mod state;
+mod stream;
+mod watch;
+
+use app_error::AppError;
+use args::{parse_coordinate, parse_crop, parse_frame_size, parse_length, parse_scale, Defaults};
+use camera::Camera;
+use clock::{Clock, Face};
+use config::{filter_description, Config, EffectiveConfig, FILTERS};
+use manifest::Layer;
+use placement::{parse_offset, parse_position, Anchor, Length};
+use progress::Progress;
+use serve::Upload;
+use stream::{FrameStream, Framing};
+use watch::FileWatcher;
 
 fn main() {
-    let matches = App::new("pingas")
-        .version(clap::crate_version!())
-        .author(clap::crate_authors!())
-        .set_term_width(80)
-        .about("A Jinglepings pinger")
-        .arg(
-            Arg::with_name("repeat")
-                .short("r")
-                .help("The number of repetitions when pinging rows.")
-                .long_help(
-                    "The number of repetitions when pinging rows. \
-                     This might be useful when drawing small images \
-                     that don't quite saturate the packet queue.",
-                )
-                .takes_value(true)
-                .default_value("1"),
-        )
-        .arg(
-            Arg::with_name("filter")
-                .short("f")
-                .long("filter")
-                .help("Choose kind of filtering used when scaling the iamge.")
-                .possible_values(&["nearest", "linear", "cubic", "gaussian", "lanczos3"])
-                // Nearest neighbour is the default because the system does not
-                // actually blend semitransparent pixels. All other filtering
-                // algorithms will cause there to be a border of semitransparent
-                // pixels resulting in weird looking artifacts.
-                .default_value("nearest"),
-        )
-        .arg(
-            Arg::with_name("filename")
-                .help("A path to an image. Most bitmap format are supported.")
-                .required(true)
-                .takes_value(true),
-        )
-        .arg(
-            Arg::with_name("x")
-                .help("The x coordinate to draw at. (range [1..1920])")
-                .required(true)
-                .takes_value(true),
-        )
-        .arg(
-            Arg::with_name("y")
-                .help("The y coordinate to draw at. (range [1..1080])")
-                .required(true)
-                .takes_value(true),
-        )
-        .arg(
-            Arg::with_name("width")
-                .help("The width of the scaled bitmap.")
-                .required(true)
-                .takes_value(true),
+    if let Err(err) = try_main() {
+        exit_with_error(err);
+    }
+}
+
+fn try_main() -> Result<(), AppError> {
+    let args = state::args()?;
+    let config = Config::load(&args).map_err(AppError::Config)?;
+    let defaults = Defaults::new(&config);
+    let matches = args::app(&defaults).get_matches_from(args.clone());
+
+    logging::init(if matches.is_present("quiet") {
+        LevelFilter::Warn
+    } else {
+        match matches.occurrences_of("verbose") {
+            0 => LevelFilter::Info,
+            1 => LevelFilter::Debug,
+            _ => LevelFilter::Trace,
+        }
+    });
+
+    if matches.is_present("list_filters") {
+        for filter in FILTERS {
+            println!("{:<10} {}", filter, filter_description(filter));
+        }
+        return Ok(());
+    }
+    if let Some(format) = matches.value_of("print_config") {
+        print_config(&matches, &config, format)?;
+        return Ok(());
+    }
+
+    if let Some(path) = matches.value_of("state") {
+        state::save(Path::new(path), &args).map_err(AppError::WriteState)?;
+        log::debug!("Saved the arguments to '{}'", path);
+    }
+
+    let session = Session::new(&matches)?;
+    match matches.subcommand() {
+        ("bench", Some(bench_matches)) => run_bench(&matches, bench_matches, session),
+        ("replay", Some(replay_matches)) => run_replay(replay_matches, session),
+        ("serve", Some(serve_matches)) => run_serve(&matches, serve_matches, session),
+        _ => run_draw(&matches, session),
+    }
+}
+
+/// The canvas and the options that apply to everything pingas can draw.
+struct Session {
+    canvas: Canvas,
+    repetitions: usize,
+    start_at: Option<DateTime<Local>>,
+    duration: Option<Duration>,
+    metrics: Option<SocketAddr>,
+    screen_size: (u32, u32),
+    dry_run: bool,
+    full_frames: bool,
+}
+
+impl Session {
+    /// Set up the canvas from the command line arguments. This returns an error
+    /// for options that only work with the raw backend.
+    fn new(matches: &ArgMatches) -> Result<Self, AppError> {
+        let repetitions = value_t!(matches, "repeat", usize)?;
+        let rate = if matches.is_present("rate") {
+            Some(value_t!(matches, "rate", f64)?)
+        } else {
+            None
+        };
+        let prefixes = values_t!(matches, "prefix", Prefix)?;
+        let seed = if matches.is_present("seed") {
+            Some(value_t!(matches, "seed", u64)?)
+        } else {
+            None
+        };
+        let order = match matches
+            .value_of("dispatch")
+            .unwrap_or_else(|| matches.value_of("order").unwrap())
+        {
+            "rows" => Order::Rows,
+            "columns" => Order::Columns,
+            "random" => Order::Random { seed },
+            "center-out" => Order::CenterOut,
+            "spiral" => Order::Spiral,
+            _ => unreachable!(),
+        };
+        let pixel_layout = match matches.value_of("pixel_layout").unwrap() {
+            "bgra16" => PixelLayout::Bgra16,
+            "rgba16" => PixelLayout::Rgba16,
+            "argb16" => PixelLayout::Argb16,
+            "abgr16" => PixelLayout::Abgr16,
+            _ => unreachable!(),
+        };
+        let duration = matches
+            .value_of("duration")
+            .map(|duration| humantime::parse_duration(duration).unwrap());
+        let start_at = matches
+            .value_of("start_at")
+            .map(|start_at| clock::parse_target(start_at).unwrap());
+        let metrics = matches
+            .value_of("metrics_addr")
+            .map(|address| address.parse().unwrap());
+        let backend = match matches.value_of("backend").unwrap() {
+            "fastping" => Backend::Fastping,
+            "raw" => Backend::Raw,
+            _ => unreachable!(),
+        };
+        let threads = value_t!(matches, "threads", usize)?;
+        let passes = value_t!(matches, "count", usize)?;
+        let screen_width = value_t!(matches, "screen_width", u32)?;
+        let screen_height = value_t!(matches, "screen_height", u32)?;
+        let clip = matches.is_present("clip");
+        let dry_run = matches.is_present("dry_run");
+        let full_frames = matches.is_present("full_frames");
+        let mut canvas = Canvas::new()
+            .with_prefixes(prefixes)
+            .with_screen_size(screen_width, screen_height)
+            .with_clipping(clip)
+            .with_skip_transparent(!matches.is_present("no_skip_transparent"))
+            .with_order(order)
+            .with_pixel_layout(pixel_layout)
+            .with_threads(threads)
+            .with_backend(backend)
+            .with_full_frames(full_frames);
+        if matches.is_present("border") {
+            canvas = canvas.with_border(
+                value_t!(matches, "border", u32)?,
+                parse_color(matches.value_of("border_color").unwrap()).unwrap(),
+            );
+        }
+        if matches.value_of("transition") == Some("fade") {
+            let easing = match matches.value_of("easing").unwrap() {
+                "linear" => Easing::Linear,
+                "ease-in-out" => Easing::EaseInOut,
+                _ => unreachable!(),
+            };
+            canvas = canvas.with_transition(Transition {
+                duration: Duration::from_millis(value_t!(matches, "transition_ms", u64)?),
+                easing,
+            });
+        }
+        if let Some(mut tiles) = matches.values_of("tile") {
+            let mut tiles = || tiles.next().unwrap().parse().unwrap();
+            canvas = canvas.with_tiling(tiles(), tiles());
+        }
+        if matches.is_present("adaptive") {
+            canvas = canvas.with_adaptive_rate(true);
+        }
+        if let Some(ramp) = matches.value_of("ramp") {
+            canvas = canvas.with_ramp(humantime::parse_duration(ramp).unwrap());
+        }
+        if let Some(rate) = rate {
+            canvas = canvas.with_rate_limit(rate);
+        }
+        if matches.is_present("keepalive_rate") {
+            canvas = canvas.with_keepalive_rate(value_t!(matches, "keepalive_rate", f64)?);
+        }
+        if let Some(jitter) = matches.value_of("jitter") {
+            canvas = canvas.with_jitter(humantime::parse_duration(jitter).unwrap(), seed);
+        }
+        let binding = match (
+            matches.value_of("interface"),
+            matches.value_of("source_address"),
+        ) {
+            (Some(interface), _) => Some(Binding::Interface(interface.to_owned())),
+            (None, Some(address)) => Some(Binding::Address(address.parse().unwrap())),
+            (None, None) => None,
+        };
+        if let Some(binding) = binding {
+            if backend != Backend::Raw {
+                return Err(AppError::usage(
+                    "--interface and --source can only be used with --backend raw",
+                    ErrorKind::ArgumentConflict,
+                ));
+            }
+            canvas = canvas.with_binding(binding);
+        }
+        if passes > 0 {
+            canvas = canvas.with_passes(passes);
+        }
+        if matches.is_present("max_packets") {
+            canvas = canvas.with_max_packets(value_t!(matches, "max_packets", u64)?);
+        }
+        if matches.is_present("retry") {
+            if backend != Backend::Raw {
+                return Err(AppError::usage(
+                    "--retry can only be used with --backend raw",
+                    ErrorKind::ArgumentConflict,
+                ));
+            }
+            canvas = canvas.with_retries(value_t!(matches, "retry", usize)?);
+        }
+        if matches.is_present("payload_size") {
+            if backend != Backend::Raw {
+                return Err(AppError::usage(
+                    "--payload-size can only be used with --backend raw",
+                    ErrorKind::ArgumentConflict,
+                ));
+            }
+            canvas = canvas.with_payload_size(value_t!(matches, "payload_size", usize)?);
+        }
+
+        Ok(Session {
+            canvas,
+            repetitions,
+            start_at,
+            duration,
+            metrics,
+            screen_size: (screen_width, screen_height),
+            dry_run,
+            full_frames,
+        })
+    }
+
+    /// Draw through [`draw_with_stats()`], starting at `--start-at` and stopping
+    /// after `--duration`.
+    fn draw(&self, draw: impl FnOnce() -> Result<(), Error>) -> Result<(), AppError> {
+        draw_with_stats(
+            &self.canvas,
+            self.start_at,
+            self.duration,
+            self.metrics,
+            draw,
         )
-        .arg(
-            Arg::with_name("height")
-                .help(
-                    "The height of the scaled bitmap. If set, the bitmap will be resized to fit \
-                     within the specified width and height.",
+    }
+}
+
+/// Measure how many packets per second can be sent for the `bench` subcommand.
+fn run_bench(
+    matches: &ArgMatches,
+    bench_matches: &ArgMatches,
+    session: Session,
+) -> Result<(), AppError> {
+    if session.dry_run {
+        return Err(AppError::usage(
+            "--dry-run can't be used with the bench subcommand",
+            ErrorKind::ArgumentConflict,
+        ));
+    }
+
+    let size = value_t!(bench_matches, "size", u32)?;
+    let duration = humantime::parse_duration(bench_matches.value_of("duration").unwrap()).unwrap();
+    let image = bench::test_image(size);
+    let canvas = &session.canvas;
+    log::info!(
+        "Benchmarking the {} backend with {} threads for {}",
+        matches.value_of("backend").unwrap(),
+        value_t!(matches, "threads", usize)?,
+        humantime::format_duration(duration)
+    );
+
+    let mut sampler = None;
+    draw_with_stats(
+        canvas,
+        session.start_at,
+        Some(duration),
+        session.metrics,
+        || {
+            sampler = Some(bench::Sampler::start(canvas.stats(), duration));
+            canvas.draw_image(&image, (0, 0), session.repetitions)
+        },
+    )?;
+    println!("{}", sampler.unwrap().finish());
+
+    Ok(())
+}
+
+/// Ping a list of addresses for the `replay` subcommand.
+fn run_replay(replay_matches: &ArgMatches, session: Session) -> Result<(), AppError> {
+    let path = replay_matches.value_of("file").unwrap();
+    let addresses = load_replay(path)?;
+    if addresses.is_empty() {
+        return Err(AppError::NoAddresses(path.to_owned()));
+    }
+
+    // Without any rows to go by the addresses are split up into groups the
+    // size of a row on the screen, keeping them in order
+    let groups: Vec<Vec<IpAddr>> = addresses
+        .chunks(session.screen_size.0 as usize)
+        .map(|group| group.to_vec())
+        .collect();
+    if session.dry_run {
+        let _ = print_addresses(&groups, session.repetitions);
+        return Ok(());
+    }
+
+    log::info!("Replaying {} addresses from '{}'", addresses.len(), path);
+    session.draw(|| session.canvas.draw_addresses(groups, session.repetitions))
+}
+
+/// Draw the images that get uploaded over HTTP for the `serve` subcommand.
+fn run_serve(
+    matches: &ArgMatches,
+    serve_matches: &ArgMatches,
+    session: Session,
+) -> Result<(), AppError> {
+    let apply_filters = image_filters(matches)?;
+    let address: SocketAddr = serve_matches.value_of("address").unwrap().parse().unwrap();
+    let uploads = serve::serve(address).map_err(AppError::Serve)?;
+    let canvas = &session.canvas;
+
+    log::info!("Waiting for images on http://{}/", address);
+    session.draw(|| {
+        canvas.draw_updates(&RgbaImage::new(0, 0), (0, 0), session.repetitions, || {
+            let upload = uploads.try_recv().ok()?;
+            match prepare_upload(
+                &upload,
+                matches,
+                canvas,
+                session.screen_size,
+                &apply_filters,
+            ) {
+                Ok((image, (x, y))) => {
+                    let (width, height) = image.dimensions();
+                    let message =
+                        format!("Printing the {}x{} image at ({}, {})", width, height, x, y);
+                    log::info!("{}", message);
+                    upload.reply(Ok(message));
+
+                    Some((image, (x, y)))
+                }
+                Err(err) => {
+                    log::warn!("Rejected an upload: {}", err);
+                    upload.reply(Err(err));
+
+                    None
+                }
+            }
+        })
+    })
+}
+
+/// Draw an image, an animation, text, a clock, or anything else that ends up
+/// on the screen, either at a single position or at every `--positions`.
+fn run_draw(matches: &ArgMatches, session: Session) -> Result<(), AppError> {
+    let canvas = &session.canvas;
+    let repetitions = session.repetitions;
+    let screen_size = session.screen_size;
+    let (screen_width, screen_height) = screen_size;
+    let apply_filters = image_filters(matches)?;
+    let mut loop_count = parse_loop_count(matches)?;
+    let anchor = matches
+        .value_of("anchor")
+        .map(|anchor| anchor.parse::<Anchor>().unwrap());
+    let at = matches
+        .value_of("at")
+        .map(|position| {
+            parse_position(position, screen_size).map_err(|err| {
+                AppError::usage(
+                    &format!("Invalid value for '--at <at>': {}", err),
+                    ErrorKind::ValueValidation,
                 )
-                .takes_value(true),
-        )
-        .get_matches();
-
-    let repetitions = value_t_or_exit!(matches, "repeat", usize);
-    let filename = matches.value_of("filename").unwrap();
-    let origin_x = value_t_or_exit!(matches, "x", u16);
-    let origin_y = value_t_or_exit!(matches, "y", u16);
-    let width = value_t_or_exit!(matches, "width", u32);
-    let height = value_t!(matches, "height", u32);
-    let filter_type = match matches.value_of("filter").unwrap() {
-        "nearest" => FilterType::Nearest,
-        "linear" => FilterType::Triangle,
-        "cubic" => FilterType::CatmullRom,
-        "gaussian" => FilterType::Gaussian,
-        "lanczos3" => FilterType::Lanczos3,
-        _ => unreachable!(),
+            })
+        })
+        .transpose()?;
+    // With --positions there's no single position to draw at
+    let placed = anchor.is_some() || at.is_some() || matches.is_present("positions");
+    // The countdown is a clock that shows the time left instead of the time
+    let clock = match matches.subcommand() {
+        (name @ "clock", Some(clock_matches)) | (name @ "countdown", Some(clock_matches)) => {
+            Some(load_clock(name, clock_matches)?)
+        }
+        _ => None,
+    };
+    let mut camera = matches
+        .value_of("camera")
+        .map(|device| Camera::open(&camera_path(device)))
+        .transpose()
+        .map_err(AppError::Camera)?;
+    let stream = matches
+        .value_of("stream")
+        .map(|path| {
+            let framing = match matches.value_of("raw_frames") {
+                Some(size) => {
+                    let (width, height) = parse_frame_size(size).unwrap();
+                    Framing::Raw { width, height }
+                }
+                None => Framing::LengthPrefixed,
+            };
+            FrameStream::open(path, framing)
+        })
+        .transpose()
+        .map_err(AppError::Stream)?;
+    // Frames from a camera or a stream go through the same resizing and
+    // cropping as images do, starting with the first frame
+    let first_frame = match (&mut camera, &stream) {
+        (Some(camera), _) => Some(camera.capture().map_err(Error::from)?),
+        (None, Some(stream)) => Some(stream.wait_for_frame().ok_or_else(|| {
+            AppError::Stream(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "The stream ended before the first frame",
+            ))
+        })?),
+        (None, None) => None,
+    };
+    let (name, mut source, origin) = match (matches.subcommand(), &clock) {
+        (("fill", Some(fill_matches)), _) => fill_source(fill_matches, placed, screen_size)?,
+        (("test-pattern", Some(pattern_matches)), _) => {
+            pattern_source(pattern_matches, placed, screen_size)?
+        }
+        ((name, Some(clock_matches)), Some(clock)) => {
+            clock_source(name, clock_matches, clock, placed, screen_size)?
+        }
+        _ if matches.is_present("manifest") => manifest_source(matches, placed)?,
+        _ => {
+            let (source, plays) = image_source(matches, placed, screen_size, first_frame)?;
+            loop_count = plays;
+            source
+        }
     };
 
-    let image = {
-        let image = image::open(filename).unwrap_or_else(|err| {
-            eprintln!("Can't open file:\n{}", err);
-            exit(1);
-        });
+    for_each_frame(&mut source, &apply_filters);
 
-        // Either fit the image in the specified area if height is set or
-        // calculate the new height based on the given width
-        let height = height.unwrap_or_else(|_| {
-            ((width as f32) / (image.width() as f32) * (image.height() as f32)) as u32
+    // Blinking is played as a two frame animation, so it goes through the same
+    // scheduling and rate limiting as any other animation
+    if matches.is_present("blink") {
+        let frequency = value_t!(matches, "blink", f64)?;
+        source = match source {
+            Source::Still(image) if clock.is_none() => blink_frames(
+                image.to_rgba(),
+                frequency,
+                background_color(matches).unwrap_or(CLEAR_COLOR),
+            ),
+            _ => {
+                return Err(AppError::usage(
+                    "--blink only works with still images",
+                    ErrorKind::ArgumentConflict,
+                ))
+            }
+        };
+    }
+
+    if matches.is_present("stats_runs") {
+        report_runs(match &source {
+            Source::Still(image) => image.as_rgba8().unwrap(),
+            Source::Animation(frames) => &frames[0].image,
         });
-        image.resize(width, height, filter_type).to_rgba()
-    };
+    }
+
+    if let Some(path) = matches.value_of("preview") {
+        let image = match &source {
+            Source::Still(image) => image.as_rgba8().unwrap(),
+            Source::Animation(frames) => &frames[0].image,
+        };
+        image.save(path).map_err(AppError::WritePreview)?;
+
+        return Ok(());
+    }
 
     // These are the dimensions of the resized image, they can be slightly
-    // different from the ones specified.
-    let image_height = image.height();
-    let image_width = image.width();
+    // different from the ones specified. When tiling this covers all tiles.
+    let (image_width, image_height) = canvas.tiled_size(source_size(&source));
+    if let Some(path) = matches.value_of("positions") {
+        let image = match &source {
+            Source::Still(image) if clock.is_none() => image.to_rgba(),
+            _ => {
+                return Err(AppError::usage(
+                    "--positions only works with still images",
+                    ErrorKind::ArgumentConflict,
+                ))
+            }
+        };
+        return draw_at_positions(path, name, &image, &session);
+    }
+
+    let (origin_x, origin_y) = match (origin.or(at), anchor) {
+        (Some(origin), _) => origin,
+        (None, Some(anchor)) => {
+            let offset = matches
+                .value_of("offset")
+                .map(|offset| parse_offset(offset, screen_size).unwrap())
+                .unwrap_or((0, 0));
+            anchor
+                .origin(
+                    offset,
+                    (image_width, image_height),
+                    (screen_width, screen_height),
+                )
+                .map_err(AppError::Placement)?
+        }
+        (None, None) => unreachable!(),
+    };
+
+    // Anchoring, clipping, borders, and transparent edges all change where the
+    // image ends up, so this is reported before drawing. Drawing nothing at all
+    // is almost always a mistake, unless the image can still change while
+    // it's being drawn.
+    let extent = match &source {
+        Source::Still(image) => canvas.extent(image.as_rgba8().unwrap(), (origin_x, origin_y))?,
+        Source::Animation(frames) => canvas.extent(&frames[0].image, (origin_x, origin_y))?,
+    };
+    let live =
+        clock.is_some() || camera.is_some() || stream.is_some() || matches.is_present("watch");
+    if extent.is_none() && !live {
+        let later_frames = match &source {
+            Source::Still(_) => &[][..],
+            Source::Animation(frames) => &frames[1..],
+        };
+        let mut visible = false;
+        for frame in later_frames {
+            visible |= canvas.extent(&frame.image, (origin_x, origin_y))?.is_some();
+        }
+        if !visible {
+            return Err(AppError::NothingToDraw(name.to_owned()));
+        }
+    }
+
+    if let Some(path) = matches.value_of("output_json") {
+        let frames = planned_frames(&source, session.full_frames);
+        write_json(path, canvas, &frames, (origin_x, origin_y), repetitions)?;
+
+        return Ok(());
+    }
+
+    if session.dry_run {
+        for image in planned_frames(&source, session.full_frames) {
+            let rows = canvas.addresses(&image, (origin_x, origin_y))?;
+
+            // Writing will fail when piping to something like `head`, in which
+            // case we can just stop
+            if print_addresses(&rows, repetitions).is_err() {
+                break;
+            }
+        }
+
+        return Ok(());
+    }
+
+    // The watcher is set up before drawing starts so it can't miss any changes
+    let watcher = if matches.is_present("watch") {
+        match (&source, matches.value_of("filename")) {
+            (Source::Still(_), Some(filename)) if filename != "-" => {
+                Some(FileWatcher::new(Path::new(filename)).map_err(AppError::Watch)?)
+            }
+            _ => {
+                return Err(AppError::usage(
+                    "--watch only works with still images loaded from a file",
+                    ErrorKind::ArgumentConflict,
+                ))
+            }
+        }
+    } else {
+        None
+    };
+    match extent {
+        Some(extent) => log::info!(
+            "Printing '{}' to ({}, {}) @ {}x{} pixels, covering ({}, {}) to ({}, {}) with {} visible pixels",
+            name,
+            origin_x,
+            origin_y,
+            image_width,
+            image_height,
+            extent.top_left.0,
+            extent.top_left.1,
+            extent.bottom_right.0,
+            extent.bottom_right.1,
+            extent.pixels
+        ),
+        None => log::warn!(
+            "Printing '{}' to ({}, {}) @ {}x{} pixels, but none of its pixels are visible",
+            name,
+            origin_x,
+            origin_y,
+            image_width,
+            image_height
+        ),
+    }
+    let scroll = matches.value_of("scroll").map(|direction| match direction {
+        "left" => ScrollDirection::Left,
+        "right" => ScrollDirection::Right,
+        _ => unreachable!(),
+    });
+    let fps = value_t!(matches, "fps", f64)?;
+    let speed = value_t!(matches, "speed", f64)?;
+    session.draw(|| {
+        match (&source, scroll, &clock, camera.as_mut(), &stream) {
+            (_, _, Some(clock), ..) => {
+                thread::sleep(clock::until_next_second());
+                canvas.draw_live(
+                    (origin_x, origin_y),
+                    Duration::from_secs(1),
+                    repetitions,
+                    || {
+                        let mut image = clock.render();
+                        apply_filters(&mut image);
+                        image
+                    },
+                )
+            }
+            (Source::Still(image), _, None, Some(camera), _) => {
+                let mut frame = image.as_rgba8().unwrap().clone();
+                let mut captured = false;
+                canvas.draw_live(
+                    (origin_x, origin_y),
+                    Duration::from_secs_f64(1.0 / fps),
+                    repetitions,
+                    || {
+                        // The first frame has already been captured, and if
+                        // capturing fails we'll keep showing the last frame
+                        if captured {
+                            let frame_source = camera
+                                .capture()
+                                .map_err(|err| Error::from(err).into())
+                                .and_then(|frame| {
+                                    image_source(matches, placed, screen_size, Some(frame))
+                                });
+                            match frame_source {
+                                Ok(((_, Source::Still(image), _), _)) => {
+                                    frame = image.to_rgba();
+                                    apply_filters(&mut frame);
+                                }
+                                Ok(_) => unreachable!(),
+                                Err(err) => log::warn!("Can't capture a frame:\n{}", err),
+                            }
+                        }
+                        captured = true;
+
+                        frame.clone()
+                    },
+                )
+            }
+            (Source::Still(image), _, None, None, Some(stream)) => canvas.draw_stream(
+                image.as_rgba8().unwrap(),
+                (origin_x, origin_y),
+                repetitions,
+                || match image_source(matches, placed, screen_size, Some(stream.latest()?)) {
+                    Ok(((_, Source::Still(image), _), _)) => {
+                        let mut image = image.to_rgba();
+                        apply_filters(&mut image);
+                        Some(image)
+                    }
+                    Ok(_) => unreachable!(),
+                    Err(err) => {
+                        log::warn!("Can't draw a frame:\n{}", err);
+                        None
+                    }
+                },
+            ),
+            (Source::Still(image), Some(direction), None, None, None) => canvas.draw_marquee(
+                image.as_rgba8().unwrap(),
+                (origin_x, origin_y),
+                screen_width.saturating_sub(u32::from(origin_x)),
+                direction,
+                speed,
+                repetitions,
+            ),
+            (Source::Still(image), None, None, None, None) => match &watcher {
+                Some(watcher) => canvas.draw_updates(
+                    image.as_rgba8().unwrap(),
+                    (origin_x, origin_y),
+                    repetitions,
+                    || {
+                        if !watcher.changed() {
+                            return None;
+                        }
 
-    println!(
-        "Printing '{}' to ({}, {}) @ {}x{} pixels",
-        filename, origin_x, origin_y, image_width, image_height
+                        // The file may not have been written completely yet, in
+                        // which case we'll keep drawing the old version until the
+                        // next change
+                        match image_source(matches, placed, screen_size, None) {
+                            Ok(((_, Source::Still(image), _), _)) => {
+                                let mut image = image.to_rgba();
+                                apply_filters(&mut image);
+                                log::info!("Reloaded '{}'", name);
+                                Some((image, (origin_x, origin_y)))
+                            }
+                            Ok(_) => {
+                                log::warn!("'{}' is now an animation, it won't be reloaded", name);
+                                None
+                            }
+                            Err(err) => {
+                                log::warn!("Can't reload '{}':\n{}", name, err);
+                                None
+                            }
+                        }
+                    },
+                ),
+                None => {
+                    canvas.draw_image(image.as_rgba8().unwrap(), (origin_x, origin_y), repetitions)
+                }
+            },
+            (Source::Animation(frames), _, None, ..) => {
+                canvas.draw_animation(frames, (origin_x, origin_y), repetitions, loop_count)
+            }
+        }
+    })
+}
+
+/// Draw copies of `image` at every position in the `--positions` file at
+/// `path`.
+fn draw_at_positions(
+    path: &str,
+    name: &str,
+    image: &RgbaImage,
+    session: &Session,
+) -> Result<(), AppError> {
+    let canvas = &session.canvas;
+    let (image_width, image_height) = canvas.tiled_size(image.dimensions());
+    let origins = load_positions(
+        path,
+        canvas,
+        session.screen_size,
+        (image_width, image_height),
+    )?;
+    if origins.is_empty() {
+        return Err(AppError::NoPositions(path.to_owned()));
+    }
+    let mut visible = false;
+    for &origin in &origins {
+        visible |= canvas.extent(image, origin)?.is_some();
+    }
+    if !visible {
+        return Err(AppError::NothingToDraw(name.to_owned()));
+    }
+
+    if session.dry_run {
+        let _ = print_addresses(
+            &canvas.copy_addresses(image, &origins)?,
+            session.repetitions,
+        );
+        return Ok(());
+    }
+
+    log::info!(
+        "Printing '{}' to {} positions @ {}x{} pixels",
+        name,
+        origins.len(),
+        image_width,
+        image_height
     );
-    eprintln!(
+    session.draw(|| canvas.draw_copies(image, &origins, session.repetitions))
+}
+
+/// The filters that get applied to images after resizing them, so they match
+/// what actually gets sent. The clock, the file watcher, and the server produce
+/// new images while drawing, so this returns a function that can be applied to
+/// those images as well. Blurring or sharpening comes first, followed by the
+/// chroma key, the color adjustments, grayscale, inverting, the opacity, the
+/// alpha mode, the outline, and finally the palette or dithering.
+fn image_filters(matches: &ArgMatches) -> Result<impl Fn(&mut RgbaImage), AppError> {
+    let chroma_key_color = matches.value_of("chroma_key").map(|key| {
+        let tolerance = matches
+            .value_of("tolerance")
+            .map_or(0.0, |tolerance| tolerance.parse().unwrap());
+        (parse_color(key).unwrap(), tolerance)
+    });
+    let adjustments = Adjustments {
+        brightness: value_t!(matches, "brightness", i32)?,
+        contrast: value_t!(matches, "contrast", f32)?,
+        gamma: value_t!(matches, "gamma", f32)?,
+    };
+    let blur_sigma = if matches.is_present("blur") {
+        Some(value_t!(matches, "blur", f32)?)
+    } else {
+        None
+    };
+    let sharpen_image = matches.is_present("sharpen");
+    let grayscale = matches.is_present("grayscale");
+    let invert = matches.is_present("invert");
+    let opacity = if matches.is_present("opacity") {
+        Some(value_t!(matches, "opacity", f32)?)
+    } else {
+        None
+    };
+    let vignette_strength = if matches.is_present("vignette") {
+        Some(value_t!(matches, "vignette", f32)?)
+    } else {
+        None
+    };
+    let outline_threshold = if matches.is_present("outline") {
+        Some(value_t!(matches, "threshold", f32)?)
+    } else {
+        None
+    };
+    let background = background_color(matches);
+    let alpha_mode = match (matches.value_of("alpha_mode").unwrap(), background) {
+        ("premultiply", background) => {
+            AlphaMode::Premultiply(background.unwrap_or(Rgba([0, 0, 0, 255])))
+        }
+        (_, Some(background)) => AlphaMode::Flatten(background),
+        ("skip-zero", None) => AlphaMode::SkipZero,
+        ("threshold", None) => AlphaMode::Threshold(value_t!(matches, "alpha_threshold", u8)?),
+        _ => unreachable!(),
+    };
+    let dither_image = matches.value_of("dither") == Some("floyd-steinberg");
+    let palette = matches.value_of("palette").map(load_palette).transpose()?;
+    let reduced_depth = if matches.is_present("quantize") {
+        Some(value_t!(matches, "quantize", ColorDepth)?)
+    } else {
+        None
+    };
+    let depth = reduced_depth.unwrap_or(value_t!(matches, "color_depth", ColorDepth)?);
+    Ok(move |image: &mut RgbaImage| {
+        if let Some(sigma) = blur_sigma {
+            blur(image, sigma);
+        }
+        if sharpen_image {
+            sharpen(image);
+        }
+        if let Some((key, tolerance)) = chroma_key_color {
+            chroma_key(image, key, tolerance);
+        }
+        if adjustments != Adjustments::default() {
+            adjust_colors(image, adjustments);
+        }
+        if grayscale {
+            to_grayscale(image);
+        }
+        if invert {
+            invert_colors(image);
+        }
+        if let Some(opacity) = opacity {
+            scale_alpha(image, opacity);
+        }
+        if let Some(strength) = vignette_strength {
+            vignette(image, strength);
+        }
+        apply_alpha_mode(image, alpha_mode);
+        if let Some(threshold) = outline_threshold {
+            outline(image, threshold);
+        }
+        match (&palette, dither_image) {
+            (Some(palette), true) => dither_to_palette(image, palette),
+            (Some(palette), false) => quantize(image, palette),
+            (None, true) => dither(image, depth),
+            (None, false) => {
+                if let Some(depth) = reduced_depth {
+                    reduce_color_depth(image, depth);
+                }
+            }
+        }
+    })
+}
+
+/// The `--background` color, if one was given.
+fn background_color(matches: &ArgMatches) -> Option<Rgba<u8>> {
+    matches
+        .value_of("background")
+        .map(|color| parse_color(color).unwrap())
+}
+
+/// Install the signal handler and log statistics while `draw` is pinging. If
+/// `start_at` is set this first waits until that time, and if `duration` is set
+/// drawing stops after that long. If `metrics` is set, the statistics are also
+/// served as Prometheus metrics on that address.
+fn draw_with_stats(
+    canvas: &Canvas,
+    start_at: Option<DateTime<Local>>,
+    duration: Option<Duration>,
+    metrics: Option<SocketAddr>,
+    draw: impl FnOnce() -> Result<(), Error>,
+) -> Result<(), AppError> {
+    if let Some(address) = metrics {
+        metrics::serve(address, canvas.stats()).map_err(AppError::Metrics)?;
+        log::info!("Serving metrics on http://{}/metrics", address);
+    }
+
+    log::info!(
         "\nErrors will be printed below, this can happen when the queues are congested. \
          Try decreasing the rate if this keeps happening."
     );
 
-    // We will ping per row to avoid hammering the server
-    let handles: Vec<_> = image
-        .enumerate_rows()
-        .map(|(_, row)| {
-            let addresses: Vec<_> = row
-                // Skip any completely transparent pixels
-                .filter(|(_, _, &Rgba([_, _, _, alpha]))| alpha > 0)
-                .map(|(x, y, pixel)| {
-                    build_address(origin_x + x as u16, origin_y + y as u16, pixel).to_string()
+    let stop = canvas.stop_flag();
+    ctrlc::set_handler(move || stop.store(true, Ordering::Relaxed)).map_err(AppError::Signal)?;
+    pause::install(canvas.pause_flag()).map_err(AppError::PauseSignal)?;
+
+    if let Some(start_at) = start_at {
+        if !wait_until(start_at, &canvas.stop_flag()) {
+            return Ok(());
+        }
+    }
+
+    // This shares the stop flag with the Ctrl-C handler, so whichever comes
+    // first stops drawing
+    if let Some(duration) = duration {
+        let stop = canvas.stop_flag();
+        thread::spawn(move || {
+            thread::sleep(duration);
+            stop.store(true, Ordering::Relaxed);
+        });
+    }
+
+    let start = Instant::now();
+    let done = Arc::new(AtomicBool::new(false));
+    // The progress bar is purely informational, so it's left out with --quiet
+    let progress = if log::log_enabled!(log::Level::Info) {
+        Progress::start(canvas.stats())
+    } else {
+        None
+    };
+    let reporter = {
+        let stats = canvas.stats();
+        let pause = canvas.pause_flag();
+        let showing_progress = progress
+            .as_ref()
+            .map_or_else(Arc::default, Progress::active_flag);
+        let done = done.clone();
+        thread::spawn(move || report_stats(&stats, &pause, &showing_progress, start, &done))
+    };
+
+    let result = draw();
+    let elapsed = start.elapsed();
+    done.store(true, Ordering::Relaxed);
+    if let Some(progress) = progress {
+        progress.finish();
+    }
+    reporter.join().unwrap();
+    result?;
+
+    let packets_sent = canvas.stats().packets_sent();
+    log::info!(
+        "\nSent {} packets in {} ({:.0} pixels/s on average)",
+        packets_sent,
+        humantime::format_duration(Duration::from_secs(elapsed.as_secs())),
+        packets_sent as f64 / elapsed.as_secs_f64()
+    );
+
+    Ok(())
+}
+
+/// Sleep until `time` for `--start-at`, or until the stop flag gets set by the
+/// Ctrl-C handler. Returns `false` if drawing should be stopped.
+fn wait_until(time: DateTime<Local>, stop: &AtomicBool) -> bool {
+    let remaining = || (time - Local::now()).to_std().unwrap_or_default();
+    log::info!(
+        "Waiting {} until {} to start drawing",
+        humantime::format_duration(Duration::from_secs(remaining().as_secs())),
+        time.format("%Y-%m-%d %H:%M:%S")
+    );
+
+    loop {
+        if stop.load(Ordering::Relaxed) {
+            return false;
+        }
+
+        let remaining = remaining();
+        if remaining == Duration::from_secs(0) {
+            return true;
+        }
+        thread::sleep(remaining.min(Duration::from_millis(100)));
+    }
+}
+
+/// Open or render the image to draw from the default subcommand's arguments,
+/// along with its name and the coordinates to draw it at. The coordinates are
+/// omitted when they're `placed` through `--anchor`, `--at`, or `--positions`
+/// instead of through positional arguments. When drawing from `--camera` or
+/// `--stream`, the frame that came in is passed as `frame`. This also returns
+/// the number of times to play the source if it's an animation, which unless
+/// `--loop-count` is used comes from the file itself.
+fn image_source<'a>(
+    matches: &'a ArgMatches,
+    placed: bool,
+    screen_size: (u32, u32),
+    frame: Option<RgbaImage>,
+) -> Result<(NamedSource<'a>, Option<usize>), AppError> {
+    let text = matches.value_of("text");
+    let sequence = matches.value_of("sequence");
+
+    // clap fills in positional arguments from left to right, so when drawing
+    // text, a sequence, or frames from a camera or a stream instead of an image
+    // the coordinates end up in the slots meant for the filename and what comes
+    // after it
+    let mut positionals = ["filename", "x", "y", "width", "height"]
+        .iter()
+        .filter_map(|name| matches.value_of(name));
+    let filename = if text.is_some() || sequence.is_some() || frame.is_some() {
+        None
+    } else {
+        positionals.next()
+    };
+    let origin = if placed {
+        None
+    } else {
+        Some((
+            parse_coordinate("x", positionals.next(), screen_size.0)?,
+            parse_coordinate("y", positionals.next(), screen_size.1)?,
+        ))
+    };
+    let scale = matches
+        .value_of("scale")
+        .map(|scale| parse_scale(scale).unwrap());
+    let width: Option<u32> = match positionals.next() {
+        Some(_) if scale.is_some() => {
+            return Err(AppError::usage(
+                "--scale can't be combined with <width> and <height>",
+                ErrorKind::ArgumentConflict,
+            ))
+        }
+        None if text.is_some() || frame.is_some() || scale.is_some() => None,
+        value => Some(parse_length("width", value, screen_size.0)?),
+    };
+    let height: Option<u32> = positionals
+        .next()
+        .map(|value| parse_length("height", Some(value), screen_size.1))
+        .transpose()?;
+    if positionals.next().is_some() {
+        return Err(AppError::usage(
+            "Found more positional arguments than expected",
+            ErrorKind::UnknownArgument,
+        ));
+    }
+    let (resize_mode, filters) = resize_options(matches);
+
+    // SVG images are rasterized at the size they get drawn at so they don't get
+    // blurry from being scaled afterwards. Cropping and rotating change what
+    // that size would be, so then they're rasterized at their natural size and
+    // resized like any other image.
+    let data = filename.map(read_source).transpose()?;
+    let loop_count = match &data {
+        Some(data) if !matches.is_present("loop_count") => play_count(data),
+        _ => parse_loop_count(matches)?,
+    };
+    let presized = data.as_deref().is_some_and(is_svg)
+        && !matches.is_present("crop")
+        && !matches!(matches.value_of("rotate"), Some("90") | Some("270"));
+    let source = match (data, sequence, text, frame) {
+        (Some(data), ..) if is_svg(&data) => {
+            let (width, height) = if presized {
+                (width, height)
+            } else {
+                (None, None)
+            };
+            rasterize_svg(
+                &data,
+                width,
+                height,
+                resize_mode,
+                value_t!(matches, "svg_dpi", f32)?,
+            )
+            .map(|image| Source::Still(DynamicImage::ImageRgba8(image)))
+        }
+        (Some(data), ..) => decode_source(&data, !matches.is_present("no_auto_orient")),
+        (None, Some(sequence), ..) => {
+            let fps = value_t!(matches, "fps", f64)?;
+            load_sequence(Path::new(sequence), Duration::from_secs_f64(1.0 / fps))
+                .map(Source::Animation)
+        }
+        (None, None, Some(text), _) => render_text(
+            text,
+            matches.value_of("font").unwrap(),
+            value_t!(matches, "size", f32)?,
+            parse_color(matches.value_of("color").unwrap()).unwrap(),
+        ),
+        (None, None, None, Some(frame)) => Ok(Source::Still(DynamicImage::ImageRgba8(frame))),
+        (None, None, None, None) => unreachable!(),
+    }?;
+
+    let source = match matches.value_of("crop") {
+        Some(crop) => crop_source(source, parse_crop(crop).unwrap())?,
+        None => source,
+    };
+
+    // This has to happen before resizing so the height gets computed from the
+    // rotated image's aspect ratio
+    let source = match matches.value_of("rotate") {
+        Some("90") => map_frames(source, image::imageops::rotate90),
+        Some("180") => map_frames(source, image::imageops::rotate180),
+        Some("270") => map_frames(source, image::imageops::rotate270),
+        _ => source,
+    };
+    let source = if matches.is_present("flip_h") {
+        map_frames(source, image::imageops::flip_horizontal)
+    } else {
+        source
+    };
+    let source = if matches.is_present("flip_v") {
+        map_frames(source, image::imageops::flip_vertical)
+    } else {
+        source
+    };
+
+    // Text and frames are drawn at their natural size unless a width has been
+    // specified
+    let resize = |source: &Source, width: u32, height: Option<u32>| {
+        let filter_type = filters.for_resize(source_size(source), width, height, resize_mode);
+        resize_source(source, width, height, resize_mode, filter_type)
+    };
+    let max_pixels = if matches.is_present("max_pixels") {
+        Some(value_t!(matches, "max_pixels", u64)?)
+    } else {
+        None
+    };
+    let width = if presized { None } else { width };
+    let source = match (width, max_pixels) {
+        (None, None) => source,
+        (Some(width), None) => resize(&source, width, height),
+        (_, Some(max_pixels)) => shrink_to_fit(&source, width, height, max_pixels, resize),
+    };
+    let source = match scale {
+        Some(factor) => {
+            // This is checked up front since scaling up a large image by a
+            // large factor would take up a lot of memory. Where the image ends
+            // up can only be checked once it's been placed.
+            check_enlarged_size(&source, factor, screen_size)?;
+            map_frames(source, |image| scale_image(image, factor))
+        }
+        None => source,
+    };
+    let source = match matches.value_of("mirror") {
+        Some("quad") => {
+            check_enlarged_size(&source, 2, screen_size)?;
+            map_frames(source, mirror_quad)
+        }
+        _ => source,
+    };
+
+    let name = filename
+        .or(sequence)
+        .or(text)
+        .or_else(|| matches.value_of("camera"))
+        .or_else(|| matches.value_of("stream"));
+
+    Ok(((name.unwrap(), source, origin), loop_count))
+}
+
+/// The number of times to play animations according to `--loop-count`, or
+/// `None` if they should loop forever.
+fn parse_loop_count(matches: &ArgMatches) -> Result<Option<usize>, AppError> {
+    if !matches.is_present("loop_count") {
+        return Ok(None);
+    }
+
+    match value_t!(matches, "loop_count", usize)? {
+        0 => Ok(None),
+        n => Ok(Some(n)),
+    }
+}
+
+/// Load the images listed in the `--manifest` file and compose them into a
+/// single image, along with the coordinates to draw it at. Like in
+/// [`image_source()`], the coordinates are omitted when the scene gets
+/// `placed`.
+fn manifest_source<'a>(matches: &'a ArgMatches, placed: bool) -> Result<NamedSource<'a>, AppError> {
+    let path = matches.value_of("manifest").unwrap();
+    let error =
+        |err: String| AppError::Manifest(format!("Can't load the manifest '{}':\n{}", path, err));
+    let entries = manifest::load(Path::new(path)).map_err(error)?;
+
+    let (resize_mode, filters) = resize_options(matches);
+    let auto_orient = !matches.is_present("no_auto_orient");
+    let layers = entries
+        .into_iter()
+        .map(|entry| {
+            let image = fs::read(&entry.file)
+                .map_err(|err| err.to_string())
+                .and_then(|data| match decode_source(&data, auto_orient) {
+                    Ok(Source::Still(image)) => Ok(image),
+                    Ok(Source::Animation(_)) => Err(String::from("Animations can't be used")),
+                    Err(err) => Err(err.to_string()),
                 })
-                .collect();
+                .map_err(|err| {
+                    error(format!(
+                        "Line {}: Can't open '{}':\n{}",
+                        entry.line,
+                        entry.file.display(),
+                        err
+                    ))
+                })?;
+            let image = match entry.width {
+                Some(width) => {
+                    let filter = entry.filter.as_deref().map_or_else(
+                        || filters.for_resize(image.dimensions(), width, entry.height, resize_mode),
+                        filter_type,
+                    );
+                    resize_image(&image, width, entry.height, resize_mode, filter)
+                }
+                None => image.to_rgba(),
+            };
 
-            addresses
+            Ok(Layer {
+                image,
+                position: (entry.x, entry.y),
+                opacity: entry.opacity,
+                blend: entry.blend,
+            })
         })
-        // Skip any completely transparent rows
-        .filter(|addresses| addresses.len() > 0)
-        .flat_map(|addresses| {
-            // We can have multiple threads pinging the same row, this is useful
-            // for pinging small images faster
-            (0..repetitions).map(move |_| {
-                // TODO: Print the errors so we know when the network is congested
-                let (pinger, _) = Pinger::new(Some(1), Some(0)).unwrap();
-                for address in &addresses {
-                    pinger.add_ipaddr(address);
+        .collect::<Result<Vec<_>, AppError>>()?;
+
+    let (scene, (x, y)) = manifest::composite(&layers);
+    let origin = match (placed, u16::try_from(x), u16::try_from(y)) {
+        (true, _, _) => None,
+        (false, Ok(x), Ok(y)) => Some((x, y)),
+        _ => {
+            return Err(error(format!(
+                "The scene's position ({}, {}) is out of range",
+                x, y
+            )))
+        }
+    };
+
+    Ok((path, Source::Still(DynamicImage::ImageRgba8(scene)), origin))
+}
+
+/// The `--resize-mode` and `--filter` to resize images with.
+fn resize_options(matches: &ArgMatches) -> (ResizeMode, Filters) {
+    let resize_mode = match matches.value_of("resize_mode").unwrap() {
+        "fit" => ResizeMode::Fit,
+        "fill" => ResizeMode::Fill,
+        "stretch" => ResizeMode::Stretch,
+        _ => unreachable!(),
+    };
+    let filter = filter_type(matches.value_of("filter").unwrap());
+    let filters = Filters {
+        shrink: filter,
+        enlarge: matches
+            .value_of("upscale_filter")
+            .map_or(filter, filter_type),
+    };
+
+    (resize_mode, filters)
+}
+
+/// The scaling filters from `--filter` and `--upscale-filter`.
+#[derive(Debug, Clone, Copy)]
+struct Filters {
+    shrink: FilterType,
+    enlarge: FilterType,
+}
+
+impl Filters {
+    /// The filter for resizing an image of `image_size` pixels to `width` and
+    /// `height` with [`resize_image()`]. This depends on whether the image gets
+    /// larger or smaller, which depends on the resize mode when both a width
+    /// and a height are given.
+    fn for_resize(
+        self,
+        (image_width, image_height): (u32, u32),
+        width: u32,
+        height: Option<u32>,
+        mode: ResizeMode,
+    ) -> FilterType {
+        let grows_x = width > image_width;
+        let enlarged = match height {
+            None => grows_x,
+            Some(height) => {
+                let grows_y = height > image_height;
+                match mode {
+                    // Fitting scales the image by the smaller of the two
+                    // ratios, and filling scales it by the larger one
+                    ResizeMode::Fit => grows_x && grows_y,
+                    ResizeMode::Fill | ResizeMode::Stretch => grows_x || grows_y,
                 }
+            }
+        };
+
+        if enlarged {
+            self.enlarge
+        } else {
+            self.shrink
+        }
+    }
+}
 
-                thread::spawn(move || loop {
-                    pinger.ping_once();
+/// Print the effective values for the options that can be set in the config
+/// file in `format` for `--print-config`, along with where those defaults came
+/// from.
+fn print_config(matches: &ArgMatches, config: &Config, format: &str) -> Result<(), AppError> {
+    let effective = EffectiveConfig {
+        repeat: value_t!(matches, "repeat", usize)?,
+        filter: matches.value_of("filter").unwrap().to_owned(),
+        rate: if matches.is_present("rate") {
+            Some(value_t!(matches, "rate", f64)?)
+        } else {
+            None
+        },
+        prefix: matches
+            .values_of("prefix")
+            .unwrap()
+            .map(String::from)
+            .collect(),
+        screen_width: value_t!(matches, "screen_width", u32)?,
+        screen_height: value_t!(matches, "screen_height", u32)?,
+    };
+
+    match format {
+        "toml" => {
+            match &config.path {
+                Some(path) => println!("# Defaults loaded from '{}'", path.display()),
+                None => println!("# No config file was loaded"),
+            }
+            print!("{}", toml::to_string(&effective).unwrap());
+        }
+        "json" => println!("{}", serde_json::to_string_pretty(&effective).unwrap()),
+        _ => unreachable!(),
+    }
+
+    Ok(())
+}
+
+/// Map one of the names in [`FILTERS`] to the corresponding scaling filter.
+fn filter_type(name: &str) -> FilterType {
+    match name {
+        "nearest" => FilterType::Nearest,
+        "linear" => FilterType::Triangle,
+        "cubic" => FilterType::CatmullRom,
+        "gaussian" => FilterType::Gaussian,
+        "lanczos3" => FilterType::Lanczos3,
+        _ => unreachable!(),
+    }
+}
+
+/// Decode, resize, and filter an image uploaded to the `serve` subcommand, and
+/// figure out where to draw it. The position and size can be passed as `x`,
+/// `y`, `width`, and `height` parameters, which can also be percentages of the
+/// screen's size. Images are drawn at the top left corner of the screen at
+/// their original size by default.
+fn prepare_upload(
+    upload: &Upload,
+    matches: &ArgMatches,
+    canvas: &Canvas,
+    screen_size: (u32, u32),
+    apply_filters: impl Fn(&mut RgbaImage),
+) -> Result<(RgbaImage, (u16, u16)), String> {
+    fn param<T: TryFrom<i64>>(
+        upload: &Upload,
+        name: &str,
+        screen_size: u32,
+    ) -> Result<Option<T>, String> {
+        upload
+            .params
+            .get(name)
+            .map(|value| {
+                let pixels = value
+                    .parse::<Length>()
+                    .map_err(|err| format!("Invalid value for '{}': {}", name, err))?
+                    .to_pixels(screen_size);
+                T::try_from(pixels).map_err(|_| {
+                    format!("Invalid value for '{}': {} is out of range", name, pixels)
                 })
             })
-        })
-        .collect();
+            .transpose()
+    }
 
-    for handle in handles {
-        handle.join().unwrap();
+    let origin = (
+        param(upload, "x", screen_size.0)?.unwrap_or(0),
+        param(upload, "y", screen_size.1)?.unwrap_or(0),
+    );
+    let width: Option<u32> = param(upload, "width", screen_size.0)?;
+    let height: Option<u32> = param(upload, "height", screen_size.1)?;
+
+    let auto_orient = !matches.is_present("no_auto_orient");
+    let image = match decode_source(&upload.data, auto_orient).map_err(|err| err.to_string())? {
+        Source::Still(image) => image,
+        Source::Animation(_) => return Err(String::from("Animations can't be uploaded")),
+    };
+    let mut image = match width {
+        Some(width) => {
+            let (resize_mode, filters) = resize_options(matches);
+            let filter_type = filters.for_resize(image.dimensions(), width, height, resize_mode);
+            resize_image(&image, width, height, resize_mode, filter_type)
+        }
+        None => image.to_rgba(),
+    };
+    apply_filters(&mut image);
+
+    canvas
+        .check_bounds(canvas.tiled_size(image.dimensions()), origin)
+        .map_err(|err| err.to_string())?;
+
+    Ok((image, origin))
+}
+
+/// Create a solid rectangle for the `fill` subcommand, along with its name and
+/// the coordinates to draw it at. Like with [`image_source()`], the coordinates
+/// are omitted when the rectangle is `placed` through `--anchor` or `--at`.
+fn fill_source<'a>(
+    matches: &'a ArgMatches,
+    placed: bool,
+    screen_size: (u32, u32),
+) -> Result<NamedSource<'a>, AppError> {
+    let (origin, (width, height)) = parse_rectangle(matches, placed, screen_size)?;
+    let color = if matches.is_present("clear") {
+        Rgba([0, 0, 0, 255])
+    } else {
+        parse_color(matches.value_of("color").unwrap()).unwrap()
+    };
+
+    let image = RgbaImage::from_pixel(width, height, color);
+    Ok((
+        "fill",
+        Source::Still(DynamicImage::ImageRgba8(image)),
+        origin,
+    ))
+}
+
+/// The coordinates of a rectangle if they're known up front, and its size.
+type Rectangle = (Option<(u16, u16)>, (u32, u32));
+
+/// Parse the `x`, `y`, `width`, and `height` positional arguments of the `fill`
+/// and `test-pattern` subcommands. This returns an error if they're invalid.
+/// The coordinates are omitted when the rectangle is `placed`.
+fn parse_rectangle(
+    matches: &ArgMatches,
+    placed: bool,
+    screen_size: (u32, u32),
+) -> Result<Rectangle, AppError> {
+    let mut positionals = ["x", "y", "width", "height"]
+        .iter()
+        .filter_map(|name| matches.value_of(name));
+    let origin = if placed {
+        None
+    } else {
+        Some((
+            parse_coordinate("x", positionals.next(), screen_size.0)?,
+            parse_coordinate("y", positionals.next(), screen_size.1)?,
+        ))
+    };
+    let width: u32 = parse_length("width", positionals.next(), screen_size.0)?;
+    let height: u32 = parse_length("height", positionals.next(), screen_size.1)?;
+    if positionals.next().is_some() {
+        return Err(AppError::usage(
+            "Found more positional arguments than expected",
+            ErrorKind::UnknownArgument,
+        ));
     }
+
+    Ok((origin, (width, height)))
 }
 
-/// Build an IPv6 address for writing a pixel. `x` and `y` should correspond to
-/// some pixel on a 1920x1080 screen.
-#[allow(clippy::many_single_char_names)]
-fn build_address(x: u16, y: u16, pixel: &Rgba<u8>) -> IpAddr {
-    let &Rgba([r, g, b, a]) = pixel;
+/// Generate a test pattern for the `test-pattern` subcommand, along with its
+/// name and the coordinates to draw it at. The positional arguments work the
+/// same way as for [`fill_source()`].
+fn pattern_source<'a>(
+    matches: &'a ArgMatches,
+    placed: bool,
+    screen_size: (u32, u32),
+) -> Result<NamedSource<'a>, AppError> {
+    let (origin, (width, height)) = parse_rectangle(matches, placed, screen_size)?;
 
-    IpAddr::V6(Ipv6Addr::new(
-        0x2001,
-        0x610,
-        0x1908,
-        0xa000,
-        x,
-        y,
-        ((b as u16) << 8) | (g as u16),
-        ((r as u16) << 8) | (a as u16),
+    let image = match matches.value_of("pattern").unwrap() {
+        "checkerboard" => {
+            pattern::checkerboard(width, height, value_t!(matches, "cell_size", u32)?)
+        }
+        "gradient" => pattern::gradient(width, height),
+        "color-bars" => pattern::color_bars(width, height),
+        "solid" => RgbaImage::from_pixel(
+            width,
+            height,
+            parse_color(matches.value_of("color").unwrap()).unwrap(),
+        ),
+        _ => unreachable!(),
+    };
+    Ok((
+        "test-pattern",
+        Source::Still(DynamicImage::ImageRgba8(image)),
+        origin,
     ))
 }
+
+/// Load the font for the `clock` or `countdown` subcommand and set up the
+/// clock.
+fn load_clock(subcommand: &str, matches: &ArgMatches) -> Result<Clock, AppError> {
+    let font_data =
+        fs::read(matches.value_of("font").unwrap()).map_err(|err| Error::Font(err.to_string()))?;
+    let face = match subcommand {
+        "clock" => Face::Time(matches.value_of("format").unwrap().to_owned()),
+        "countdown" => Face::Countdown {
+            target: clock::parse_target(matches.value_of("target").unwrap()).unwrap(),
+            done_text: matches.value_of("done_text").map(String::from),
+            flash: matches.is_present("flash"),
+        },
+        _ => unreachable!(),
+    };
+
+    Ok(Clock::new(
+        TextRenderer::new(font_data)?,
+        face,
+        value_t!(matches, "size", f32)?,
+        parse_color(matches.value_of("color").unwrap()).unwrap(),
+    ))
+}
+
+/// Render the current time for the `clock` subcommand or the time left for the
+/// `countdown` subcommand, along with its name and the coordinates to draw it
+/// at. Like with [`image_source()`], the coordinates are omitted when the
+/// clock is `placed` through `--anchor` or `--at`.
+fn clock_source<'a>(
+    name: &'a str,
+    matches: &'a ArgMatches,
+    clock: &Clock,
+    placed: bool,
+    screen_size: (u32, u32),
+) -> Result<NamedSource<'a>, AppError> {
+    let origin = if placed {
+        None
+    } else {
+        Some((
+            parse_coordinate("x", matches.value_of("x"), screen_size.0)?,
+            parse_coordinate("y", matches.value_of("y"), screen_size.1)?,
+        ))
+    };
+
+    Ok((
+        name,
+        Source::Still(DynamicImage::ImageRgba8(clock.render())),
+        origin,
+    ))
+}
+
+/// Print every address exactly as often as it would have been pinged during a
+/// single pass over the image.
+fn print_addresses(rows: &[Vec<IpAddr>], repetitions: usize) -> io::Result<()> {
+    let stdout = io::stdout();
+    let mut stdout = BufWriter::new(stdout.lock());
+    for addresses in rows {
+        for _ in 0..repetitions {
+            for address in addresses {
+                writeln!(stdout, "{}", address)?;
+            }
+        }
+    }
+
+    stdout.flush()
+}
+
+/// The images that get drawn during the first pass over `source`. For
+/// animations these only contain the pixels that changed since the previous
+/// frame, unless `full_frames` is set.
+fn planned_frames(source: &Source, full_frames: bool) -> Vec<Cow<'_, RgbaImage>> {
+    match source {
+        Source::Still(image) => vec![Cow::Borrowed(image.as_rgba8().unwrap())],
+        Source::Animation(frames) => frames
+            .iter()
+            .enumerate()
+            .map(|(idx, frame)| {
+                if full_frames || idx == 0 {
+                    Cow::Borrowed(&frame.image)
+                } else {
+                    Cow::Owned(frame_difference(&frames[idx - 1].image, &frame.image))
+                }
+            })
+            .collect(),
+    }
+}
+
+/// A single ping as written by `--output-json`.
+#[derive(Serialize)]
+struct JsonPing {
+    frame: usize,
+    x: u16,
+    y: u16,
+    r: u8,
+    g: u8,
+    b: u8,
+    a: u8,
+    address: IpAddr,
+}
+
+/// Write the pings for every frame to `path` as JSON, or to STDOUT if `path` is
+/// `-`. The pings are listed in the same order as with `--dry-run`.
+fn write_json(
+    path: &str,
+    canvas: &Canvas,
+    frames: &[Cow<RgbaImage>],
+    origin: (u16, u16),
+    repetitions: usize,
+) -> Result<(), AppError> {
+    let mut pings = Vec::new();
+    for (frame, image) in frames.iter().enumerate() {
+        for group in canvas.pings(image, origin)? {
+            for _ in 0..repetitions {
+                pings.extend(group.iter().map(|ping| JsonPing {
+                    frame,
+                    x: ping.x,
+                    y: ping.y,
+                    r: ping.color[0],
+                    g: ping.color[1],
+                    b: ping.color[2],
+                    a: ping.color[3],
+                    address: ping.address,
+                }));
+            }
+        }
+    }
+
+    let write = || -> Result<(), Box<dyn std::error::Error>> {
+        let writer: Box<dyn Write> = if path == "-" {
+            Box::new(io::stdout())
+        } else {
+            Box::new(fs::File::create(path)?)
+        };
+        let mut writer = BufWriter::new(writer);
+        serde_json::to_writer_pretty(&mut writer, &pings)?;
+        writeln!(writer)?;
+        writer.flush()?;
+
+        Ok(())
+    };
+
+    write().map_err(|err| AppError::WriteJson(err.to_string()))
+}
+
+/// The only part of `--output-json`'s output needed to replay it.
+#[derive(Deserialize)]
+struct JsonAddress {
+    address: String,
+}
+
+/// Read the positions for `--positions` from `path`. Percentages are resolved
+/// against `screen_size`. Invalid positions and positions where an image of
+/// `image_size` pixels would not fit on `canvas` get printed to STDERR and are
+/// skipped.
+fn load_positions(
+    path: &str,
+    canvas: &Canvas,
+    screen_size: (u32, u32),
+    image_size: (u32, u32),
+) -> Result<Vec<(u16, u16)>, AppError> {
+    let contents = fs::read_to_string(path).map_err(AppError::ReadPositions)?;
+
+    Ok(contents
+        .lines()
+        .enumerate()
+        .map(|(idx, line)| (idx + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|(line_number, line)| {
+            let position = line
+                .replace(',', " ")
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(",");
+            let origin = match parse_position(&position, screen_size) {
+                Ok(origin) => origin,
+                Err(err) => {
+                    log::warn!("Skipping line {}: {}", line_number, err);
+                    return None;
+                }
+            };
+            match canvas.check_bounds(image_size, origin) {
+                Ok(()) => Some(origin),
+                Err(err) => {
+                    log::warn!("Skipping line {}:\n{}", line_number, err);
+                    None
+                }
+            }
+        })
+        .collect())
+}
+
+/// Read the addresses to replay from `path`, or from STDIN if `path` is `-`.
+/// This accepts both a list of addresses separated by newlines and the output
+/// of `--output-json`. Invalid addresses get printed to STDERR and are skipped.
+fn load_replay(path: &str) -> Result<Vec<IpAddr>, AppError> {
+    let contents = if path == "-" {
+        let mut buffer = String::new();
+        io::stdin().read_to_string(&mut buffer).map(|_| buffer)
+    } else {
+        fs::read_to_string(path)
+    }
+    .map_err(|err| AppError::ReadAddresses(err.to_string()))?;
+
+    let entries: Vec<(String, String)> = if contents.trim_start().starts_with('[') {
+        serde_json::from_str::<Vec<JsonAddress>>(&contents)
+            .map_err(|err| AppError::DecodeAddresses(err.to_string()))?
+            .into_iter()
+            .enumerate()
+            .map(|(idx, entry)| (format!("entry {}", idx + 1), entry.address))
+            .collect()
+    } else {
+        contents
+            .lines()
+            .enumerate()
+            .map(|(idx, line)| (format!("line {}", idx + 1), line.trim().to_owned()))
+            .filter(|(_, line)| !line.is_empty())
+            .collect()
+    };
+
+    Ok(entries
+        .into_iter()
+        .filter_map(|(location, address)| match address.parse::<Ipv6Addr>() {
+            Ok(address) => Some(IpAddr::V6(address)),
+            Err(_) => {
+                log::warn!(
+                    "Skipping '{}' on {}, it's not a valid IPv6 address",
+                    address,
+                    location
+                );
+                None
+            }
+        })
+        .collect())
+}
+
+/// Print an error along with a hint on how to fix it if we have one, and exit
+/// with the error's exit code.
+fn exit_with_error(err: AppError) -> ! {
+    eprintln!("{}", err);
+    if let AppError::Pingas(Error::OutOfBounds { .. }) = err {
+        eprintln!("\nUse --clip to only draw the part of the image that fits on the screen.");
+    }
+
+    exit(err.exit_code());
+}
+
+/// Log how many runs of identical colors the rows of `image` contain for
+/// `--stats-runs`.
+fn report_runs(image: &RgbaImage) {
+    let runs = color_runs(image);
+    for (row, num_runs) in runs.runs_per_row.iter().enumerate() {
+        log::debug!("Row {} contains {} runs", row, num_runs);
+    }
+
+    let total_runs = runs.total_runs();
+    log::info!(
+        "The image contains {} runs of identical colors in its {} rows, between {} and {} per \
+         row. Runs are {:.1} pixels long on average, and the longest run is {} pixels long.",
+        total_runs,
+        runs.runs_per_row.len(),
+        runs.runs_per_row.iter().min().unwrap_or(&0),
+        runs.runs_per_row.iter().max().unwrap_or(&0),
+        runs.pixels as f64 / total_runs.max(1) as f64,
+        runs.longest_run
+    );
+    if let Some((Rgba([r, g, b, a]), count)) = runs.most_common {
+        log::info!(
+            "The most common color is {:02x}{:02x}{:02x}{:02x}, which covers {:.1}% of the \
+             {} pixels that get sent",
+            r,
+            g,
+            b,
+            a,
+            count as f64 / runs.pixels as f64 * 100.0,
+            runs.pixels
+        );
+    }
+}
+
+/// Log a summary of the packets sent and the errors encountered during the
+/// last second, until `done` gets set. The packets sent during the
+/// last second doubles as the number of pixels drawn per second. This also
+/// reports when drawing gets paused or resumed through `pause`. Nothing gets
+/// logged while `showing_progress` is set, since that would garble the
+/// progress bar.
+fn report_stats(
+    stats: &Stats,
+    pause: &AtomicBool,
+    showing_progress: &AtomicBool,
+    start: Instant,
+    done: &AtomicBool,
+) {
+    let (mut last_sent, mut last_unanswered, mut last_errors) = (0, 0, 0);
+    let mut was_paused = false;
+    while !done.load(Ordering::Relaxed) {
+        thread::sleep(Duration::from_secs(1));
+
+        // The signal handlers can't log anything themselves
+        let paused = pause.load(Ordering::Relaxed);
+        match (was_paused, paused) {
+            (false, true) => log::info!("Paused drawing, send SIGUSR2 to resume"),
+            (true, false) => log::info!("Resumed drawing"),
+            _ => (),
+        }
+        was_paused = paused;
+
+        let sent = stats.packets_sent();
+        let unanswered = stats.unanswered();
+        let errors = logging::SEND_ERRORS.load(Ordering::Relaxed);
+        if showing_progress.load(Ordering::Relaxed) {
+            (last_sent, last_unanswered, last_errors) = (sent, unanswered, errors);
+            continue;
+        }
+
+        // The rate limit can change over time with --ramp and --adaptive
+        let limit = stats
+            .rate_limit()
+            .map(|rate| format!(", {:.0} pixels/s allowed", rate))
+            .unwrap_or_default();
+        log::info!(
+            "[{}] {} pixels/s, {} packets sent in total, {} failed to send, {} unanswered{}",
+            humantime::format_duration(Duration::from_secs(start.elapsed().as_secs())),
+            sent - last_sent,
+            sent,
+            errors - last_errors,
+            unanswered - last_unanswered,
+            limit
+        );
+
+        last_sent = sent;
+        last_unanswered = unanswered;
+        last_errors = errors;
+    }
+}
+
+/// A source's name, the source itself, and the coordinates to draw it at if
+/// they're known up front.
+type NamedSource<'a> = (&'a str, Source, Option<(u16, u16)>);
+
+fn load_palette(path: &str) -> Result<Palette, Error> {
+    fs::read_to_string(path)
+        .map_err(|err| Error::Palette(err.to_string()))?
+        .parse()
+}
+
+/// Render text to a tightly cropped image using the font at `font`.
+fn render_text(text: &str, font: &str, size: f32, color: Rgba<u8>) -> Result<Source, Error> {
+    let font_data = fs::read(font).map_err(|err| Error::Font(err.to_string()))?;
+    let renderer = TextRenderer::new(font_data)?;
+
+    Ok(Source::Still(DynamicImage::ImageRgba8(
+        renderer.render(text, size, color),
+    )))
+}
+
+/// The path to the video device for `--camera`, which can also be passed as
+/// just the device's number.
+fn camera_path(device: &str) -> PathBuf {
+    match device.parse::<u32>() {
+        Ok(number) => PathBuf::from(format!("/dev/video{}", number)),
+        Err(_) => PathBuf::from(device),
+    }
+}
+
+/// Read an image file. A filename of `-` reads the image from STDIN instead.
+fn read_source(filename: &str) -> Result<Vec<u8>, Error> {
+    if filename == "-" {
+        let mut data = Vec::new();
+        io::stdin()
+            .read_to_end(&mut data)
+            .map_err(ImageError::from)?;
+
+        Ok(data)
+    } else {
+        Ok(fs::read(filename).map_err(ImageError::from)?)
+    }
+}
@@ -0,0 +1,220 @@
+//! Composing a scene out of multiple images listed in a TOML manifest, passed
+//! through `--manifest`. Every image is listed in its own `[[image]]` table:
+//!
+//! ```toml
+//! [[image]]
+//! file = "background.png"
+//! width = 300
+//!
+//! [[image]]
+//! file = "logo.png"
+//! x = 20
+//! y = 20
+//! width = 100
+//! height = 50
+//! filter = "nearest"
+//! opacity = 0.8
+//! blend = "multiply"
+//! ```
+//!
+//! Images are drawn in the order they're listed in, so later images end up on
+//! top of earlier ones. Relative paths are resolved relative to the manifest.
+
+use image::{Rgba, RgbaImage};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::FILTERS;
+
+/// A single image in the manifest.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Entry {
+    pub file: PathBuf,
+    /// The position of the image's top left corner relative to the manifest's
+    /// origin.
+    #[serde(default)]
+    pub x: u32,
+    #[serde(default)]
+    pub y: u32,
+    /// The size to resize the image to, like the positional arguments. The
+    /// image is drawn at its original size if this is not set.
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// The scaling filter to use for this image instead of `--filter`.
+    pub filter: Option<String>,
+    /// How opaque the image is, between 0 and 1.
+    #[serde(default = "default_opacity")]
+    pub opacity: f32,
+    /// How the image gets combined with the images below it.
+    #[serde(default)]
+    pub blend: BlendMode,
+    /// The line the entry's `[[image]]` header is on, for error messages.
+    #[serde(skip)]
+    pub line: usize,
+}
+
+/// How the colors of an image get combined with the colors of the images below
+/// it. These work like the layer blend modes in image editors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlendMode {
+    /// Draw the image over the images below it.
+    #[default]
+    Normal,
+    /// Multiply the colors, which can only make them darker.
+    Multiply,
+    /// Multiply the inverted colors, which can only make them lighter.
+    Screen,
+    /// Multiply dark colors and screen light colors below the image, which
+    /// increases the contrast.
+    Overlay,
+}
+
+impl BlendMode {
+    /// Combine a color channel from the image below, `backdrop`, with the same
+    /// channel from the image on top, `source`. Both are between 0 and 1.
+    fn blend(self, backdrop: f32, source: f32) -> f32 {
+        match self {
+            BlendMode::Normal => source,
+            BlendMode::Multiply => backdrop * source,
+            BlendMode::Screen => backdrop + source - backdrop * source,
+            BlendMode::Overlay if backdrop <= 0.5 => 2.0 * backdrop * source,
+            BlendMode::Overlay => 1.0 - 2.0 * (1.0 - backdrop) * (1.0 - source),
+        }
+    }
+}
+
+/// An image ready to be put into the scene by [`composite()`].
+#[derive(Debug)]
+pub struct Layer {
+    pub image: RgbaImage,
+    /// The position of the image's top left corner.
+    pub position: (u32, u32),
+    pub opacity: f32,
+    pub blend: BlendMode,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    #[serde(default, rename = "image")]
+    images: Vec<Entry>,
+}
+
+fn default_opacity() -> f32 {
+    1.0
+}
+
+/// Read and validate the manifest at `path`. The paths in the returned entries
+/// have already been resolved.
+pub fn load(path: &Path) -> Result<Vec<Entry>, String> {
+    let contents = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let manifest: Manifest = toml::from_str(&contents).map_err(|err| err.to_string())?;
+    if manifest.images.is_empty() {
+        return Err(String::from(
+            "The manifest does not contain any [[image]] tables",
+        ));
+    }
+
+    // The TOML parser doesn't tell us where tables start, but every entry gets
+    // its own header
+    let headers = contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| line.trim_start().starts_with("[[image]]"))
+        .map(|(idx, _)| idx + 1);
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut entries = manifest.images;
+    for (entry, line) in entries.iter_mut().zip(headers) {
+        entry.line = line;
+        entry.file = base_dir.join(&entry.file);
+
+        let invalid = |reason: String| Err(format!("Line {}: {}", line, reason));
+        if let Some(filter) = &entry.filter {
+            if !FILTERS.contains(&filter.as_str()) {
+                return invalid(format!(
+                    "'{}' is not a valid filter, expected one of: {}",
+                    filter,
+                    FILTERS.join(", ")
+                ));
+            }
+        }
+        if entry.width == Some(0) || entry.height == Some(0) {
+            return invalid(String::from(
+                "The image's dimensions should be positive integers",
+            ));
+        }
+        if entry.height.is_some() && entry.width.is_none() {
+            return invalid(String::from("A height can only be used with a width"));
+        }
+        if !(0.0..=1.0).contains(&entry.opacity) {
+            return invalid(String::from("The opacity should be between 0 and 1"));
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Draw `layers` on top of each other, in order. The result is just large
+/// enough to contain every layer, so this also returns the position of its top
+/// left corner.
+pub fn composite(layers: &[Layer]) -> (RgbaImage, (u32, u32)) {
+    let left = layers
+        .iter()
+        .map(|layer| layer.position.0)
+        .min()
+        .unwrap_or(0);
+    let top = layers
+        .iter()
+        .map(|layer| layer.position.1)
+        .min()
+        .unwrap_or(0);
+    let right = layers
+        .iter()
+        .map(|layer| layer.position.0 + layer.image.width())
+        .max()
+        .unwrap_or(0);
+    let bottom = layers
+        .iter()
+        .map(|layer| layer.position.1 + layer.image.height())
+        .max()
+        .unwrap_or(0);
+
+    let mut scene = RgbaImage::new(right - left, bottom - top);
+    for layer in layers {
+        let (x, y) = (layer.position.0 - left, layer.position.1 - top);
+        for (image_x, image_y, pixel) in layer.image.enumerate_pixels() {
+            let backdrop = scene.get_pixel_mut(x + image_x, y + image_y);
+            *backdrop = blend_pixel(*backdrop, *pixel, layer.opacity, layer.blend);
+        }
+    }
+
+    (scene, (left, top))
+}
+
+/// Draw `source` with its alpha multiplied by `opacity` over `backdrop`. Where
+/// the backdrop is transparent the source's own color is used, so blending
+/// only affects the parts that overlap.
+fn blend_pixel(backdrop: Rgba<u8>, source: Rgba<u8>, opacity: f32, mode: BlendMode) -> Rgba<u8> {
+    let source_alpha = f32::from(source[3]) / 255.0 * opacity;
+    let backdrop_alpha = f32::from(backdrop[3]) / 255.0;
+    let alpha = source_alpha + backdrop_alpha * (1.0 - source_alpha);
+    if alpha == 0.0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+
+    let mut result = Rgba([0, 0, 0, (alpha * 255.0).round() as u8]);
+    for channel in 0..3 {
+        let source = f32::from(source[channel]) / 255.0;
+        let backdrop = f32::from(backdrop[channel]) / 255.0;
+        let blended =
+            (1.0 - backdrop_alpha) * source + backdrop_alpha * mode.blend(backdrop, source);
+        let color =
+            (source_alpha * blended + backdrop_alpha * backdrop * (1.0 - source_alpha)) / alpha;
+        result[channel] = (color * 255.0).round() as u8;
+    }
+
+    result
+}
@@ -0,0 +1,39 @@
+use image::RgbaImage;
+
+/// The direction a marquee scrolls in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScrollDirection {
+    /// Enter from the right edge and leave through the left edge.
+    #[default]
+    Left,
+    /// Enter from the left edge and leave through the right edge.
+    Right,
+}
+
+impl ScrollDirection {
+    /// The x coordinate of the image's left edge relative to a region `width`
+    /// pixels wide after scrolling `shift` pixels. The image starts just
+    /// outside of the region, and `shift` should wrap around after
+    /// `width + image_width` pixels when the image has fully left the region.
+    pub(crate) fn position(self, shift: u32, width: u32, image_width: u32) -> i64 {
+        match self {
+            ScrollDirection::Left => i64::from(width) - i64::from(shift),
+            ScrollDirection::Right => i64::from(shift) - i64::from(image_width),
+        }
+    }
+}
+
+/// Copy the part of `image` visible in a region `width` pixels wide when the
+/// image's left edge is at `position` within that region.
+pub(crate) fn window(image: &RgbaImage, width: u32, position: i64) -> RgbaImage {
+    let mut window = RgbaImage::new(width, image.height());
+    let left = (-position).clamp(0, i64::from(image.width())) as u32;
+    let right = (i64::from(width) - position).clamp(0, i64::from(image.width())) as u32;
+    for y in 0..image.height() {
+        for x in left..right {
+            window.put_pixel((position + i64::from(x)) as u32, y, *image.get_pixel(x, y));
+        }
+    }
+
+    window
+}
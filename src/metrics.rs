@@ -0,0 +1,118 @@
+//! A minimal HTTP server exposing the drawing statistics as Prometheus metrics
+//! for `--metrics-addr`.
+
+use pingas::Stats;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+
+use crate::logging::SEND_ERRORS;
+
+/// Slow or idle clients get disconnected after this long so they can't block
+/// the server.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Start serving metrics on `address` from a background thread. Every request
+/// gets the same response regardless of its path. This returns an error if
+/// the address can't be bound.
+pub fn serve(address: SocketAddr, stats: Stats) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            // A single failed connection shouldn't bring down the server
+            let result = stream.and_then(|stream| respond(stream, &stats));
+            if let Err(err) = result {
+                log::debug!("Failed to serve metrics: {}", err);
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn respond(stream: TcpStream, stats: &Stats) -> io::Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+
+    // We don't care about the request, but it has to be read before responding
+    // or some clients will see the connection as reset
+    let mut reader = BufReader::new(&stream);
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 && line != "\r\n" && line != "\n" {
+        line.clear();
+    }
+
+    let body = render(stats);
+    write!(
+        &stream,
+        "HTTP/1.1 200 OK\r\n\
+         Content-Type: text/plain; version=0.0.4\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\
+         \r\n\
+         {}",
+        body.len(),
+        body
+    )?;
+
+    (&stream).flush()
+}
+
+/// Format the metrics in Prometheus's text exposition format.
+fn render(stats: &Stats) -> String {
+    let mut body = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, value: f64| {
+        let _ = write!(
+            body,
+            "# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {value}\n",
+            name = name,
+            kind = kind,
+            help = help,
+            value = value
+        );
+    };
+
+    metric(
+        "pingas_packets_sent_total",
+        "counter",
+        "The number of ping packets sent.",
+        stats.packets_sent() as f64,
+    );
+    metric(
+        "pingas_send_errors_total",
+        "counter",
+        "The number of ping packets that could not be sent.",
+        SEND_ERRORS.load(Ordering::Relaxed) as f64,
+    );
+    metric(
+        "pingas_retried_sends_total",
+        "counter",
+        "The number of ping packets that were sent again after failing to send.",
+        stats.retried_sends() as f64,
+    );
+    metric(
+        "pingas_unanswered_total",
+        "counter",
+        "The number of pings that did not get a reply in time.",
+        stats.unanswered() as f64,
+    );
+    metric(
+        "pingas_frames_shown_total",
+        "counter",
+        "The number of images and animation frames drawn.",
+        stats.frames_shown() as f64,
+    );
+    if let Some(rate) = stats.rate_limit() {
+        metric(
+            "pingas_rate_limit",
+            "gauge",
+            "The number of packets per second that are currently allowed.",
+            rate,
+        );
+    }
+
+    body
+}
@@ -0,0 +1,151 @@
+use image::Rgba;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+
+/// A pixel's coordinates within the image, along with its color.
+pub(crate) type Pixel = (u32, u32, Rgba<u8>);
+
+/// The order in which an image's pixels get sent. Every worker pings its own
+/// group of pixels, so this decides which pixels end up in the same group and
+/// thus how the image appears on a busy screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Order {
+    /// Ping every row on its own, so on a congested screen the image slowly
+    /// fills from top to bottom.
+    #[default]
+    Rows,
+    /// Ping every column on its own, so the image fills in from left to right
+    /// instead. This spreads the load differently, which can help with tall and
+    /// narrow images. Neighbouring columns share a group when there are more
+    /// columns than rows.
+    Columns,
+    /// Spread the pixels randomly over the workers so the image fades in
+    /// uniformly. Using the same seed always results in the same order, and a
+    /// random seed is used if it's not set.
+    Random { seed: Option<u64> },
+    /// Send the pixels closest to the center of the image first, so the
+    /// subject of the image appears before its edges.
+    CenterOut,
+    /// Like [`Order::CenterOut`], but pixels at the same distance from the
+    /// center are sent in a clockwise spiral.
+    Spiral,
+}
+
+impl Order {
+    /// Rearrange the rows of pixels of a `width` by `height` image into groups
+    /// in this order. There will be at most as many groups as there are rows,
+    /// so changing the order doesn't change the number of workers needed. The
+    /// workers are started in the order of the groups.
+    pub(crate) fn arrange(
+        self,
+        rows: Vec<Vec<Pixel>>,
+        (width, height): (u32, u32),
+    ) -> Vec<Vec<Pixel>> {
+        if self == Order::Rows {
+            return rows;
+        }
+
+        let mut pixels: Vec<Pixel> = rows.iter().flatten().copied().collect();
+        match self {
+            Order::Rows => (),
+            Order::Columns => {
+                // The pixels are sorted by row, so a stable sort keeps every
+                // column sorted from top to bottom
+                pixels.sort_by_key(|&(x, _, _)| x);
+                let mut columns: Vec<Vec<Pixel>> = Vec::new();
+                for pixel in pixels {
+                    match columns.last_mut() {
+                        Some(column) if column[0].0 == pixel.0 => column.push(pixel),
+                        _ => columns.push(vec![pixel]),
+                    }
+                }
+                if columns.len() <= rows.len() {
+                    return columns;
+                }
+
+                let (num_groups, num_columns) = (rows.len(), columns.len());
+                let mut groups = vec![Vec::new(); num_groups];
+                for (idx, column) in columns.into_iter().enumerate() {
+                    groups[idx * num_groups / num_columns].extend(column);
+                }
+
+                return groups;
+            }
+            Order::Random { seed } => {
+                let mut rng = match seed {
+                    Some(seed) => StdRng::seed_from_u64(seed),
+                    None => StdRng::from_entropy(),
+                };
+                pixels.shuffle(&mut rng);
+            }
+            Order::CenterOut => {
+                pixels.sort_by(|(x1, y1, _), (x2, y2, _)| {
+                    let (dx1, dy1) = from_center(*x1, *y1, width, height);
+                    let (dx2, dy2) = from_center(*x2, *y2, width, height);
+                    dx1.hypot(dy1).partial_cmp(&dx2.hypot(dy2)).unwrap()
+                });
+            }
+            Order::Spiral => {
+                // The spiral consists of rectangular rings one pixel apart,
+                // and every ring is walked clockwise starting from the left
+                let rings = width.max(height) as f32 / 2.0;
+                pixels.sort_by_cached_key(|&(x, y, _)| {
+                    let (dx, dy) = from_center(x, y, width, height);
+                    let ring = (dx.abs().max(dy.abs()) * rings).round() as u32;
+                    let angle = (dy.atan2(dx) + std::f32::consts::PI) * 1000.0;
+
+                    (ring, angle as u32)
+                });
+            }
+        }
+
+        if pixels.is_empty() {
+            return Vec::new();
+        }
+        // This rounds up so we never end up with more groups than rows
+        let group_size = pixels.len().div_ceil(rows.len());
+        pixels
+            .chunks(group_size)
+            .map(|group| group.to_vec())
+            .collect()
+    }
+}
+
+/// The offset of a pixel from the center of the image. Both coordinates are
+/// normalized to the range [-1, 1] so images that aren't square are treated as
+/// if they were stretched to a square, and the center stays the subject.
+fn from_center(x: u32, y: u32, width: u32, height: u32) -> (f32, f32) {
+    let dx = (x as f32 + 0.5) / (width as f32) * 2.0 - 1.0;
+    let dy = (y as f32 + 0.5) / (height as f32) * 2.0 - 1.0;
+
+    (dx, dy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups_columns() {
+        let color = Rgba([255, 255, 255, 255]);
+        let rows: Vec<Vec<Pixel>> = (0..2)
+            .map(|y| (0..4).map(|x| (x, y, color)).collect())
+            .collect();
+
+        // Four columns have to fit in two groups, so every group gets two
+        // neighbouring columns
+        let groups = Order::Columns.arrange(rows, (4, 2));
+        let coordinates: Vec<Vec<(u32, u32)>> = groups
+            .iter()
+            .map(|group| group.iter().map(|&(x, y, _)| (x, y)).collect())
+            .collect();
+        assert_eq!(
+            coordinates,
+            vec![
+                vec![(0, 0), (0, 1), (1, 0), (1, 1)],
+                vec![(2, 0), (2, 1), (3, 0), (3, 1)],
+            ]
+        );
+    }
+}
@@ -0,0 +1,177 @@
+use image::{Rgb, RgbaImage};
+use std::str::FromStr;
+
+use crate::color::parse_color;
+use crate::dither::floyd_steinberg;
+use crate::error::Error;
+
+/// A fixed set of colors images can be reduced to with [`quantize()`] or
+/// [`dither_to_palette()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Palette {
+    colors: Vec<Rgb<u8>>,
+}
+
+impl Palette {
+    /// Create a palette from a list of colors. This returns an error if the
+    /// list is empty.
+    pub fn new(colors: Vec<Rgb<u8>>) -> Result<Self, Error> {
+        if colors.is_empty() {
+            Err(Error::Palette(String::from(
+                "The palette does not contain any colors",
+            )))
+        } else {
+            Ok(Palette { colors })
+        }
+    }
+
+    pub fn colors(&self) -> &[Rgb<u8>] {
+        &self.colors
+    }
+
+    /// Find the palette entry closest to `color`, using the Euclidean distance
+    /// between the red, green, and blue values.
+    pub fn nearest(&self, color: [f32; 3]) -> Rgb<u8> {
+        let distance = |entry: &Rgb<u8>| {
+            (0..3)
+                .map(|idx| (f32::from(entry[idx]) - color[idx]).powi(2))
+                .sum::<f32>()
+        };
+
+        *self
+            .colors
+            .iter()
+            .min_by(|a, b| distance(a).partial_cmp(&distance(b)).unwrap())
+            .unwrap()
+    }
+}
+
+impl FromStr for Palette {
+    type Err = Error;
+
+    /// Parse either a GIMP palette (`.gpl`) or a list of hexadecimal `RRGGBB`
+    /// colors with one color per line. Empty lines are ignored in both
+    /// formats.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s
+            .lines()
+            .enumerate()
+            .map(|(idx, line)| (idx + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty())
+            .peekable();
+        let is_gimp_palette = lines.peek().map(|(_, line)| *line) == Some("GIMP Palette");
+
+        let mut colors = Vec::new();
+        if is_gimp_palette {
+            // The header can contain a name and a number of columns, and lines
+            // starting with a `#` are comments. Colors are stored as three
+            // decimal numbers optionally followed by a name.
+            for (number, line) in lines.skip(1) {
+                if line.starts_with('#')
+                    || line.starts_with("Name:")
+                    || line.starts_with("Columns:")
+                {
+                    continue;
+                }
+
+                let channels: Vec<u8> = line
+                    .split_whitespace()
+                    .take(3)
+                    .filter_map(|channel| channel.parse().ok())
+                    .collect();
+                match channels[..] {
+                    [red, green, blue] => colors.push(Rgb([red, green, blue])),
+                    _ => return Err(invalid_line(number, line)),
+                }
+            }
+        } else {
+            for (number, line) in lines {
+                match parse_color(line) {
+                    Ok(color) if line.trim_start_matches('#').len() == 6 => {
+                        colors.push(Rgb([color[0], color[1], color[2]]))
+                    }
+                    _ => return Err(invalid_line(number, line)),
+                }
+            }
+        }
+
+        Palette::new(colors)
+    }
+}
+
+fn invalid_line(number: usize, line: &str) -> Error {
+    Error::Palette(format!("Line {}: '{}' is not a valid color", number, line))
+}
+
+/// Replace every pixel in `image` with the nearest color from `palette`. The
+/// alpha channel is left untouched.
+pub fn quantize(image: &mut RgbaImage, palette: &Palette) {
+    for pixel in image.pixels_mut() {
+        let nearest = palette.nearest([0, 1, 2].map(|channel| f32::from(pixel[channel])));
+        pixel[0] = nearest[0];
+        pixel[1] = nearest[1];
+        pixel[2] = nearest[2];
+    }
+}
+
+/// Like [`quantize()`], but with Floyd–Steinberg dithering so gradients can
+/// still be approximated with a small palette.
+pub fn dither_to_palette(image: &mut RgbaImage, palette: &Palette) {
+    floyd_steinberg(image, |old| palette.nearest(old).0.map(f32::from));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The message of a palette error.
+    fn palette_error(s: &str) -> String {
+        match s.parse::<Palette>() {
+            Err(Error::Palette(message)) => message,
+            result => panic!("expected a palette error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn parses_gimp_palettes() {
+        let palette: Palette = "GIMP Palette\n\
+                                Name: Test\n\
+                                Columns: 2\n\
+                                # A comment\n\
+                                \n\
+                                255   0   0\tRed\n\
+                                  0 128 255 Sky blue\n"
+            .parse()
+            .unwrap();
+        assert_eq!(palette.colors(), &[Rgb([255, 0, 0]), Rgb([0, 128, 255])]);
+    }
+
+    #[test]
+    fn parses_hex_lists() {
+        let palette: Palette = "ff0000\n\n#00ff80\n".parse().unwrap();
+        assert_eq!(palette.colors(), &[Rgb([255, 0, 0]), Rgb([0, 255, 128])]);
+    }
+
+    #[test]
+    fn rejects_invalid_palettes() {
+        // Line numbers include empty lines and the header
+        assert_eq!(
+            palette_error("ff0000\n\nnope\n"),
+            "Line 3: 'nope' is not a valid color"
+        );
+        assert_eq!(
+            palette_error("GIMP Palette\n255 0\n"),
+            "Line 2: '255 0' is not a valid color"
+        );
+        // Only RRGGBB colors are allowed, the alpha channel is left untouched
+        assert_eq!(
+            palette_error("ff000080"),
+            "Line 1: 'ff000080' is not a valid color"
+        );
+        assert_eq!(
+            palette_error("GIMP Palette\n# Nothing here\n"),
+            "The palette does not contain any colors"
+        );
+        assert_eq!(palette_error(""), "The palette does not contain any colors");
+    }
+}
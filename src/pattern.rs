@@ -0,0 +1,53 @@
+//! Test patterns for the `test-pattern` subcommand, for checking the position,
+//! the colors, and the prefix before drawing real images.
+
+use image::{Rgba, RgbaImage};
+
+/// The patterns that can be passed to the `test-pattern` subcommand.
+pub const PATTERNS: &[&str] = &["checkerboard", "gradient", "color-bars", "solid"];
+
+/// The colors of the color bars, from left to right. These are the same as the
+/// top part of the SMPTE color bars at full intensity.
+const BARS: [Rgba<u8>; 8] = [
+    Rgba([255, 255, 255, 255]),
+    Rgba([255, 255, 0, 255]),
+    Rgba([0, 255, 255, 255]),
+    Rgba([0, 255, 0, 255]),
+    Rgba([255, 0, 255, 255]),
+    Rgba([255, 0, 0, 255]),
+    Rgba([0, 0, 255, 255]),
+    Rgba([0, 0, 0, 255]),
+];
+
+/// Alternating white and black squares of `cell_size` pixels, starting with a
+/// white square in the top left corner.
+pub fn checkerboard(width: u32, height: u32, cell_size: u32) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |x, y| {
+        if (x / cell_size + y / cell_size).is_multiple_of(2) {
+            Rgba([255, 255, 255, 255])
+        } else {
+            Rgba([0, 0, 0, 255])
+        }
+    })
+}
+
+/// Red increases from left to right and green increases from top to bottom, so
+/// every corner has a different color and swapped channels are easy to spot.
+pub fn gradient(width: u32, height: u32) -> RgbaImage {
+    let ramp = |position: u32, length: u32| match length {
+        1 => 255,
+        _ => (position * 255 / (length - 1)) as u8,
+    };
+
+    RgbaImage::from_fn(width, height, |x, y| {
+        Rgba([ramp(x, width), ramp(y, height), 0, 255])
+    })
+}
+
+/// Eight vertical bars in white, the secondary colors, the primary colors, and
+/// black.
+pub fn color_bars(width: u32, height: u32) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |x, _| {
+        BARS[(x as usize * BARS.len()) / width as usize]
+    })
+}
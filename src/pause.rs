@@ -0,0 +1,37 @@
+//! Pausing and resuming drawing with `SIGUSR1` and `SIGUSR2`, so the screen can
+//! be handed to someone else for a while without stopping pingas.
+
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+
+/// The canvas's pause flag. Signal handlers can't capture anything, so the flag
+/// has to be reachable from a static.
+static PAUSE_FLAG: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+/// Set `pause` when receiving `SIGUSR1` and clear it again when receiving
+/// `SIGUSR2`. This can only be installed once.
+pub fn install(pause: Arc<AtomicBool>) -> io::Result<()> {
+    if PAUSE_FLAG.set(pause).is_err() {
+        return Err(io::Error::other(
+            "The signal handlers are already installed",
+        ));
+    }
+
+    for &signal in &[libc::SIGUSR1, libc::SIGUSR2] {
+        let handler = handle_signal as extern "C" fn(libc::c_int);
+        // The handler only stores to an atomic, which is fine to do from a
+        // signal handler
+        if unsafe { libc::signal(signal, handler as libc::sighandler_t) } == libc::SIG_ERR {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+extern "C" fn handle_signal(signal: libc::c_int) {
+    if let Some(pause) = PAUSE_FLAG.get() {
+        pause.store(signal == libc::SIGUSR1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,186 @@
+//! Computing where to draw an image from `--at`, `--anchor`, and `--offset`, as
+//! an alternative to passing the coordinates of the top left corner. Positions
+//! and sizes can also be given as percentages of the screen's size.
+
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+/// The values that can be passed to `--anchor`.
+pub const ANCHORS: &[&str] = &[
+    "top-left",
+    "top",
+    "top-right",
+    "left",
+    "center",
+    "right",
+    "bottom-left",
+    "bottom",
+    "bottom-right",
+];
+
+/// The point on the screen an image gets aligned to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Anchor {
+    horizontal: Alignment,
+    vertical: Alignment,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Alignment {
+    Start,
+    Center,
+    End,
+}
+
+impl FromStr for Anchor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (vertical, horizontal) = match s.find('-') {
+            Some(idx) => (&s[..idx], &s[idx + 1..]),
+            None if s == "top" || s == "bottom" => (s, "center"),
+            None => ("center", s),
+        };
+
+        let vertical = match vertical {
+            "top" => Alignment::Start,
+            "center" => Alignment::Center,
+            "bottom" => Alignment::End,
+            _ => return Err(format!("'{}' is not a valid anchor", s)),
+        };
+        let horizontal = match horizontal {
+            "left" => Alignment::Start,
+            "center" => Alignment::Center,
+            "right" => Alignment::End,
+            _ => return Err(format!("'{}' is not a valid anchor", s)),
+        };
+
+        Ok(Anchor {
+            horizontal,
+            vertical,
+        })
+    }
+}
+
+impl Anchor {
+    /// Compute the top left corner of an image of `image_size` pixels so it's
+    /// aligned to this anchor on a screen of `screen_size` pixels, after moving
+    /// it by `offset`. This returns an error if the image would end up past
+    /// the top or left edge of the screen.
+    pub fn origin(
+        self,
+        offset: (i64, i64),
+        image_size: (u32, u32),
+        screen_size: (u32, u32),
+    ) -> Result<(u16, u16), String> {
+        let x = self.horizontal.position(image_size.0, screen_size.0) + offset.0;
+        let y = self.vertical.position(image_size.1, screen_size.1) + offset.1;
+        if x < 0 || y < 0 {
+            return Err(format!(
+                "The image would be placed at ({}, {}), which is outside of the screen",
+                x, y
+            ));
+        }
+
+        Ok((clamp_coordinate(x), clamp_coordinate(y)))
+    }
+}
+
+impl Alignment {
+    fn position(self, image_size: u32, screen_size: u32) -> i64 {
+        let space = i64::from(screen_size) - i64::from(image_size);
+        match self {
+            Alignment::Start => 0,
+            Alignment::Center => space / 2,
+            Alignment::End => space,
+        }
+    }
+}
+
+/// Addresses can only hold 16 bit coordinates. Anything past that falls
+/// outside of the screen anyway, which the canvas will report.
+fn clamp_coordinate(coordinate: i64) -> u16 {
+    coordinate.min(i64::from(u16::MAX)) as u16
+}
+
+/// A distance on the screen, either as a number of pixels or as a percentage
+/// of the screen's width or height.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Length {
+    Pixels(i64),
+    Percent(f64),
+}
+
+impl Length {
+    /// Convert this length to pixels, resolving percentages against
+    /// `screen_size`.
+    pub fn to_pixels(self, screen_size: u32) -> i64 {
+        match self {
+            Length::Pixels(pixels) => pixels,
+            Length::Percent(percent) => (percent / 100.0 * f64::from(screen_size)).round() as i64,
+        }
+    }
+}
+
+impl FromStr for Length {
+    type Err = String;
+
+    /// Parse either a whole number of pixels like `300` or a percentage like
+    /// `50%`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let length = match s.strip_suffix('%') {
+            Some(percent) => percent
+                .parse::<f64>()
+                .ok()
+                .filter(|percent| percent.is_finite())
+                .map(Length::Percent),
+            None => s.parse().ok().map(Length::Pixels),
+        };
+
+        length.ok_or_else(|| format!("'{}' should be a number of pixels or a percentage", s))
+    }
+}
+
+/// Parse a pair of lengths in the `x,y` format.
+pub fn parse_lengths(lengths: &str) -> Result<(Length, Length), String> {
+    match lengths.split_once(',') {
+        Some((x, y)) => Ok((x.trim().parse()?, y.trim().parse()?)),
+        None => Err(format!("'{}' should be in the x,y format", lengths)),
+    }
+}
+
+/// Parse a position on the screen in the `x,y` format. Percentages are
+/// resolved against `screen_size`, and both coordinates have to lie on the
+/// screen.
+pub fn parse_position(position: &str, screen_size: (u32, u32)) -> Result<(u16, u16), String> {
+    let (x, y) = parse_lengths(position)?;
+
+    Ok((
+        to_coordinate("x", x, screen_size.0)?,
+        to_coordinate("y", y, screen_size.1)?,
+    ))
+}
+
+/// Convert `length` to a `name` coordinate on a screen that's `screen_size`
+/// pixels wide or high. Coordinates start at 0, so the last valid coordinate
+/// is one less than the screen's size.
+pub fn to_coordinate(name: &str, length: Length, screen_size: u32) -> Result<u16, String> {
+    let pixels = length.to_pixels(screen_size);
+    match u16::try_from(pixels) {
+        Ok(coordinate) if u32::from(coordinate) < screen_size => Ok(coordinate),
+        _ => Err(format!(
+            "{} is not a valid {} coordinate, expected a value between 0 and {}",
+            pixels,
+            name,
+            i64::from(screen_size) - 1
+        )),
+    }
+}
+
+/// Parse an offset in the `x,y` format. Both values can be negative, and
+/// percentages are resolved against `screen_size`.
+pub fn parse_offset(offset: &str, screen_size: (u32, u32)) -> Result<(i64, i64), String> {
+    let (x, y) = parse_lengths(offset)?;
+
+    Ok((x.to_pixels(screen_size.0), y.to_pixels(screen_size.1)))
+}
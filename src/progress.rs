@@ -0,0 +1,104 @@
+//! A progress bar for the start of drawing. Computing the addresses for a large
+//! image and sending them all once can take a while, and nothing shows up on
+//! the screen until then.
+
+use pingas::Stats;
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// How often the progress bar gets redrawn.
+const REFRESH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The number of characters between the progress bar's brackets.
+const BAR_WIDTH: usize = 30;
+
+/// Draws a progress bar on STDERR in the background until the first pass has
+/// been sent.
+pub struct Progress {
+    /// Set while the progress bar is being drawn, so nothing else should be
+    /// printed to STDERR.
+    active: Arc<AtomicBool>,
+    /// Set to stop drawing the progress bar early.
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+impl Progress {
+    /// Start drawing the progress bar based on `stats`. This returns `None`
+    /// when STDERR isn't a terminal, since the progress bar would only clutter
+    /// up logs.
+    pub fn start(stats: Stats) -> Option<Self> {
+        if !io::stderr().is_terminal() {
+            return None;
+        }
+
+        let active = Arc::new(AtomicBool::new(true));
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let active = active.clone();
+            let stop = stop.clone();
+            thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) && draw(&stats) {
+                    thread::sleep(REFRESH_INTERVAL);
+                }
+
+                eprint!("\r{:width$}\r", "", width = BAR_WIDTH + 40);
+                active.store(false, Ordering::Relaxed);
+            })
+        };
+
+        Some(Progress {
+            active,
+            stop,
+            handle,
+        })
+    }
+
+    /// A flag that's set for as long as the progress bar is being drawn.
+    pub fn active_flag(&self) -> Arc<AtomicBool> {
+        self.active.clone()
+    }
+
+    /// Stop drawing the progress bar and clear it, if that hasn't happened
+    /// already.
+    pub fn finish(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.handle.join().unwrap();
+    }
+}
+
+/// Draw the progress bar for the current stage, returning `false` once the
+/// first pass has been sent.
+fn draw(stats: &Stats) -> bool {
+    let (requested, computed) = (stats.addresses_requested(), stats.addresses_computed());
+    let packets_per_pass = stats.packets_per_pass();
+    let (label, done, total) = if packets_per_pass == 0 {
+        ("Computing addresses", computed, requested)
+    } else {
+        let sent = stats.packets_sent();
+        if sent >= packets_per_pass {
+            return false;
+        }
+
+        ("Sending first pass ", sent, packets_per_pass)
+    };
+
+    let fraction = match total {
+        0 => 0.0,
+        total => done as f64 / total as f64,
+    };
+    let filled = (fraction * BAR_WIDTH as f64) as usize;
+    eprint!(
+        "\r{} [{}{}] {:>3.0}%",
+        label,
+        "#".repeat(filled),
+        " ".repeat(BAR_WIDTH - filled),
+        fraction * 100.0
+    );
+    let _ = io::stderr().flush();
+
+    true
+}
@@ -0,0 +1,100 @@
+use image::{Rgba, RgbaImage};
+use std::collections::HashMap;
+
+/// How many horizontal runs of identical colors an image contains, as computed
+/// by [`color_runs()`]. Every pixel still needs its own packet, but an image
+/// with few long runs is mostly solid color, which may be better left out or
+/// drawn once as a background.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColorRuns {
+    /// The number of runs in every row of the image.
+    pub runs_per_row: Vec<usize>,
+    /// The number of pixels that would be sent, which excludes completely
+    /// transparent pixels.
+    pub pixels: usize,
+    /// The length of the longest run.
+    pub longest_run: usize,
+    /// The color most pixels have, along with the number of pixels with that
+    /// color.
+    pub most_common: Option<(Rgba<u8>, usize)>,
+}
+
+impl ColorRuns {
+    /// The total number of runs in the image.
+    pub fn total_runs(&self) -> usize {
+        self.runs_per_row.iter().sum()
+    }
+}
+
+/// Count the runs of horizontally adjacent pixels with the same color in every
+/// row of `image`. Completely transparent pixels never get sent, so they don't
+/// count as part of any run and they split up the runs around them.
+pub fn color_runs(image: &RgbaImage) -> ColorRuns {
+    let mut runs_per_row = Vec::with_capacity(image.height() as usize);
+    let mut pixels = 0;
+    let mut longest_run = 0;
+    let mut color_counts: HashMap<Rgba<u8>, usize> = HashMap::new();
+    for row in image.rows() {
+        let mut runs = 0;
+        let mut current: Option<(Rgba<u8>, usize)> = None;
+        for &pixel in row {
+            current = match current {
+                _ if pixel[3] == 0 => None,
+                Some((color, length)) if color == pixel => Some((color, length + 1)),
+                _ => {
+                    runs += 1;
+                    Some((pixel, 1))
+                }
+            };
+            if let Some((color, length)) = current {
+                pixels += 1;
+                longest_run = longest_run.max(length);
+                *color_counts.entry(color).or_default() += 1;
+            }
+        }
+
+        runs_per_row.push(runs);
+    }
+
+    // Ties are broken by the color itself so the result doesn't depend on the
+    // hash map's order
+    let most_common = color_counts
+        .into_iter()
+        .max_by_key(|&(color, count)| (count, color.0));
+
+    ColorRuns {
+        runs_per_row,
+        pixels,
+        longest_run,
+        most_common,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_runs() {
+        let red = Rgba([255, 0, 0, 255]);
+        let blue = Rgba([0, 0, 255, 255]);
+        let transparent = Rgba([0, 0, 0, 0]);
+        let image = RgbaImage::from_fn(5, 3, |x, y| match (x, y) {
+            // Red red blue blue blue
+            (0..=1, 0) => red,
+            (_, 0) => blue,
+            // A transparent pixel splits the red pixels in two runs
+            (2, 1) => transparent,
+            (_, 1) => red,
+            // Completely transparent rows don't have any runs
+            _ => transparent,
+        });
+
+        let runs = color_runs(&image);
+        assert_eq!(runs.runs_per_row, vec![2, 2, 0]);
+        assert_eq!(runs.total_runs(), 4);
+        assert_eq!(runs.pixels, 9);
+        assert_eq!(runs.longest_run, 3);
+        assert_eq!(runs.most_common, Some((red, 6)));
+    }
+}
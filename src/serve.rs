@@ -0,0 +1,218 @@
+//! A minimal HTTP server for the `serve` subcommand. Images get uploaded with
+//! a `POST` request containing the image data as its body, and the position
+//! and size are passed through the query string, e.g.
+//! `POST /?x=10&y=50%&width=200`.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+/// Uploads larger than this are rejected before they're read.
+const MAX_UPLOAD_SIZE: usize = 64 * 1024 * 1024;
+
+/// Requests whose request line and headers together are larger than this are
+/// rejected, since nothing pingas needs comes anywhere close.
+const MAX_HEADER_SIZE: u64 = 16 * 1024;
+
+/// Slow or idle clients get disconnected after this long so they can't block
+/// the server.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// An image uploaded to the server. The client waits for a response until
+/// [`reply()`](Upload::reply) gets called.
+pub struct Upload {
+    pub data: Vec<u8>,
+    /// The parameters from the query string.
+    pub params: HashMap<String, String>,
+    reply: Sender<Result<String, String>>,
+}
+
+impl Upload {
+    /// Respond to the upload with either a success message or an error.
+    pub fn reply(self, result: Result<String, String>) {
+        // The client may have disconnected in the meantime
+        let _ = self.reply.send(result);
+    }
+}
+
+/// Start listening for uploads on `address` from a background thread. Uploads
+/// are handled one at a time, in the order they arrive. This returns an error
+/// if the address can't be bound.
+pub fn serve(address: SocketAddr) -> io::Result<Receiver<Upload>> {
+    let listener = TcpListener::bind(address)?;
+    let (sender, uploads) = channel();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            // A single failed connection shouldn't bring down the server
+            let result = stream.and_then(|stream| handle(stream, &sender));
+            if let Err(err) = result {
+                log::debug!("Failed to handle upload: {}", err);
+            }
+        }
+    });
+
+    Ok(uploads)
+}
+
+fn handle(stream: TcpStream, uploads: &Sender<Upload>) -> io::Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+
+    let mut reader = BufReader::new(&stream);
+    // Otherwise a client could send a never ending header line and we would
+    // keep reading it into memory
+    let mut headers = (&mut reader).take(MAX_HEADER_SIZE);
+    let mut request_line = String::new();
+    headers.read_line(&mut request_line)?;
+    let mut content_length = None;
+    loop {
+        let mut line = String::new();
+        if headers.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    if headers.limit() == 0 {
+        return respond(
+            &stream,
+            "431 Request Header Fields Too Large",
+            "The request headers are too large",
+        );
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = (parts.next(), parts.next().unwrap_or("/"));
+    if method != Some("POST") {
+        return respond(
+            &stream,
+            "405 Method Not Allowed",
+            "Upload an image with a POST request",
+        );
+    }
+    let content_length = match content_length {
+        Some(length) if length <= MAX_UPLOAD_SIZE => length,
+        Some(_) => return respond(&stream, "413 Payload Too Large", "The image is too large"),
+        None => return respond(&stream, "411 Length Required", "Missing Content-Length"),
+    };
+
+    let mut data = vec![0; content_length];
+    reader.read_exact(&mut data)?;
+
+    let (reply, result) = channel();
+    let upload = Upload {
+        data,
+        params: parse_query(target),
+        reply,
+    };
+    if uploads.send(upload).is_err() {
+        return respond(
+            &stream,
+            "503 Service Unavailable",
+            "pingas is shutting down",
+        );
+    }
+
+    match result.recv() {
+        Ok(Ok(message)) => respond(&stream, "200 OK", &message),
+        Ok(Err(err)) => respond(&stream, "400 Bad Request", &err),
+        Err(_) => respond(
+            &stream,
+            "503 Service Unavailable",
+            "pingas is shutting down",
+        ),
+    }
+}
+
+fn respond(mut stream: &TcpStream, status: &str, message: &str) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\n\
+         Content-Type: text/plain\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\
+         \r\n\
+         {}\n",
+        status,
+        message.len() + 1,
+        message
+    )?;
+
+    stream.flush()
+}
+
+/// Parse the query string of a request target like `/?x=10&y=20`.
+fn parse_query(target: &str) -> HashMap<String, String> {
+    let query = target.split_once('?').map_or("", |(_, query)| query);
+    query
+        .split('&')
+        .filter_map(|param| param.split_once('='))
+        .map(|(name, value)| (percent_decode(name), percent_decode(value)))
+        .collect()
+}
+
+/// Decode `%XX` escapes so percentages like `50%25` work. Invalid escapes are
+/// left as they are.
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        let escaped = bytes
+            .get(idx + 1..idx + 3)
+            .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[idx], escaped) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                idx += 3;
+            }
+            (b'+', _) => {
+                decoded.push(b' ');
+                idx += 1;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                idx += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_query_strings() {
+        let params = parse_query("/?x=10&y=50%25&name=a+b%2Fc&flag&=empty");
+        assert_eq!(params.len(), 4);
+        assert_eq!(params["x"], "10");
+        assert_eq!(params["y"], "50%");
+        assert_eq!(params["name"], "a b/c");
+        assert_eq!(params[""], "empty");
+
+        assert!(parse_query("/").is_empty());
+        assert!(parse_query("/upload?").is_empty());
+    }
+
+    #[test]
+    fn leaves_invalid_escapes_alone() {
+        assert_eq!(percent_decode("50%"), "50%");
+        assert_eq!(percent_decode("%4"), "%4");
+        assert_eq!(percent_decode("%zz%41"), "%zzA");
+        assert_eq!(percent_decode("%+f"), "% f");
+        assert_eq!(percent_decode("%e2%9c%93"), "\u{2713}");
+    }
+}
@@ -0,0 +1,318 @@
+//! Still images and animations that get transformed frame by frame before
+//! they're drawn.
+
+use image::{DynamicImage, FilterType, GenericImageView, Rgba, RgbaImage};
+use std::time::Duration;
+
+use crate::animation::Frame;
+use crate::canvas::{resize_image, ResizeMode};
+use crate::error::Error;
+
+/// An image to draw. GIFs and PNGs with more than one frame are played as an
+/// animation, so every transformation has to be applied to all of its frames.
+#[derive(Clone)]
+pub enum Source {
+    Still(DynamicImage),
+    Animation(Vec<Frame>),
+}
+
+/// The dimensions of a still image or of the first frame of an animation.
+pub fn source_size(source: &Source) -> (u32, u32) {
+    match source {
+        Source::Still(image) => image.dimensions(),
+        Source::Animation(frames) => frames[0].image.dimensions(),
+    }
+}
+
+/// The number of pixels in a still image or in the largest frame of an
+/// animation that aren't completely transparent.
+pub fn visible_pixels(source: &Source) -> u64 {
+    let count = |image: &RgbaImage| image.pixels().filter(|pixel| pixel[3] > 0).count() as u64;
+    match source {
+        Source::Still(image) => match image.as_rgba8() {
+            Some(image) => count(image),
+            None => count(&image.to_rgba()),
+        },
+        Source::Animation(frames) => frames
+            .iter()
+            .map(|frame| count(&frame.image))
+            .max()
+            .unwrap_or(0),
+    }
+}
+
+/// Modify every frame of an image in place. Still images that aren't stored as
+/// RGBA get converted first.
+pub fn for_each_frame(source: &mut Source, mut f: impl FnMut(&mut RgbaImage)) {
+    match source {
+        Source::Still(image) => {
+            if image.as_rgba8().is_none() {
+                *image = DynamicImage::ImageRgba8(image.to_rgba());
+            }
+            f(image.as_mut_rgba8().unwrap())
+        }
+        Source::Animation(frames) => {
+            for frame in frames {
+                f(&mut frame.image);
+            }
+        }
+    }
+}
+
+/// Apply a transformation to every frame of an image.
+pub fn map_frames(source: Source, f: impl Fn(&RgbaImage) -> RgbaImage) -> Source {
+    match source {
+        Source::Still(image) => Source::Still(DynamicImage::ImageRgba8(f(&image.to_rgba()))),
+        Source::Animation(frames) => Source::Animation(
+            frames
+                .into_iter()
+                .map(|frame| Frame {
+                    image: f(&frame.image),
+                    delay: frame.delay,
+                })
+                .collect(),
+        ),
+    }
+}
+
+/// Resize every frame of an image with [`resize_image()`].
+pub fn resize_source(
+    source: &Source,
+    width: u32,
+    height: Option<u32>,
+    resize_mode: ResizeMode,
+    filter_type: FilterType,
+) -> Source {
+    match source {
+        Source::Still(image) => Source::Still(DynamicImage::ImageRgba8(resize_image(
+            image,
+            width,
+            height,
+            resize_mode,
+            filter_type,
+        ))),
+        Source::Animation(frames) => Source::Animation(
+            frames
+                .iter()
+                .map(|frame| Frame {
+                    image: resize_image(
+                        &DynamicImage::ImageRgba8(frame.image.clone()),
+                        width,
+                        height,
+                        resize_mode,
+                        filter_type,
+                    ),
+                    delay: frame.delay,
+                })
+                .collect(),
+        ),
+    }
+}
+
+/// Crop every frame of an image to the `(x, y, width, height)` rectangle. This
+/// returns an error if the rectangle does not lie within the image.
+pub fn crop_source(source: Source, crop: (u32, u32, u32, u32)) -> Result<Source, Error> {
+    let image_size = source_size(&source);
+    let (x, y, width, height) = crop;
+    if u64::from(x) + u64::from(width) > u64::from(image_size.0)
+        || u64::from(y) + u64::from(height) > u64::from(image_size.1)
+    {
+        return Err(Error::InvalidCrop { crop, image_size });
+    }
+
+    Ok(match source {
+        Source::Still(mut image) => Source::Still(image.crop(x, y, width, height)),
+        Source::Animation(frames) => Source::Animation(
+            frames
+                .into_iter()
+                .map(|mut frame| Frame {
+                    image: image::imageops::crop(&mut frame.image, x, y, width, height).to_image(),
+                    delay: frame.delay,
+                })
+                .collect(),
+        ),
+    })
+}
+
+/// Check whether `source` would still fit on the screen after enlarging it by
+/// `factor`, returning the same error as when an image doesn't fit if it
+/// doesn't. This can be checked before actually enlarging the image, which
+/// would take up a lot of memory for large images and factors.
+pub fn check_enlarged_size(
+    source: &Source,
+    factor: u32,
+    screen_size: (u32, u32),
+) -> Result<(), Error> {
+    let (width, height) = source_size(source);
+    let (width, height) = (
+        u64::from(width) * u64::from(factor),
+        u64::from(height) * u64::from(factor),
+    );
+    let (screen_width, screen_height) = (u64::from(screen_size.0), u64::from(screen_size.1));
+    if width > screen_width || height > screen_height {
+        Err(Error::OutOfBounds {
+            screen_size,
+            left: 0,
+            top: 0,
+            right: width.saturating_sub(screen_width).min(u64::from(u32::MAX)) as u32,
+            bottom: height
+                .saturating_sub(screen_height)
+                .min(u64::from(u32::MAX)) as u32,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+/// Resize `source` to `width` and `height` using `resize`, and then keep
+/// shrinking it until it contains at most `max_pixels` pixels that aren't
+/// completely transparent. Without a width the image starts out at its natural
+/// size. Shrinking always starts from the original image so the image doesn't
+/// get any blurrier than it has to.
+pub fn shrink_to_fit(
+    source: &Source,
+    width: Option<u32>,
+    height: Option<u32>,
+    max_pixels: u64,
+    resize: impl Fn(&Source, u32, Option<u32>) -> Source,
+) -> Source {
+    let (mut target_width, mut target_height) = match width {
+        Some(width) => (width, height),
+        None => (source_size(source).0, None),
+    };
+    let mut resized = resize(source, target_width, target_height);
+    let mut shrunk = false;
+    loop {
+        let pixels = visible_pixels(&resized);
+        if pixels <= max_pixels {
+            break;
+        }
+        if target_width <= 1 {
+            log::warn!(
+                "The image can't be shrunk any further to stay within {} visible pixels",
+                max_pixels
+            );
+            break;
+        }
+
+        // The number of pixels scales with the square of the width, but
+        // rounding can leave the image slightly too large so this may take a
+        // couple of attempts
+        let scale = (max_pixels as f64 / pixels as f64).sqrt();
+        let new_width = ((f64::from(target_width) * scale) as u32).clamp(1, target_width - 1);
+        target_height = target_height.map(|height| {
+            ((u64::from(height) * u64::from(new_width) / u64::from(target_width)) as u32).max(1)
+        });
+        target_width = new_width;
+        resized = resize(source, target_width, target_height);
+        shrunk = true;
+    }
+
+    if shrunk {
+        let (width, height) = source_size(&resized);
+        log::warn!(
+            "Shrunk the image to {}x{} pixels to stay within {} visible pixels",
+            width,
+            height,
+            max_pixels
+        );
+    }
+
+    resized
+}
+
+/// Create an animation that alternates between `image` and the same shape filled
+/// with `background`, switching `frequency` times per second in each
+/// direction.
+pub fn blink_frames(image: RgbaImage, frequency: f64, background: Rgba<u8>) -> Source {
+    let mut cleared = image.clone();
+    for pixel in cleared.pixels_mut().filter(|pixel| pixel[3] > 0) {
+        *pixel = background;
+    }
+
+    let delay = Duration::from_secs_f64(1.0 / (frequency * 2.0));
+    Source::Animation(vec![
+        Frame { image, delay },
+        Frame {
+            image: cleared,
+            delay,
+        },
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn animation(width: u32, height: u32) -> Source {
+        Source::Animation(
+            (0..2)
+                .map(|index| Frame {
+                    image: RgbaImage::from_fn(width, height, |x, y| {
+                        Rgba([x as u8, y as u8, index, 255])
+                    }),
+                    delay: Duration::from_millis(100),
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn crops_every_frame() {
+        let cropped = crop_source(animation(4, 4), (1, 2, 2, 2)).unwrap();
+        match cropped {
+            Source::Animation(frames) => {
+                for (index, frame) in frames.iter().enumerate() {
+                    assert_eq!(frame.image.dimensions(), (2, 2));
+                    assert_eq!(*frame.image.get_pixel(0, 0), Rgba([1, 2, index as u8, 255]));
+                }
+            }
+            Source::Still(_) => panic!("cropping turned the animation into a still image"),
+        }
+
+        assert!(matches!(
+            crop_source(animation(4, 4), (3, 0, 2, 1)),
+            Err(Error::InvalidCrop {
+                crop: (3, 0, 2, 1),
+                image_size: (4, 4),
+            })
+        ));
+    }
+
+    #[test]
+    fn checks_enlarged_sizes() {
+        let source = animation(10, 5);
+        assert!(check_enlarged_size(&source, 4, (40, 20)).is_ok());
+        assert!(matches!(
+            check_enlarged_size(&source, 5, (40, 20)),
+            Err(Error::OutOfBounds {
+                right: 10,
+                bottom: 5,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn shrinks_to_the_pixel_budget() {
+        let source = Source::Still(DynamicImage::ImageRgba8(RgbaImage::from_pixel(
+            100,
+            50,
+            Rgba([255; 4]),
+        )));
+        let resize = |source: &Source, width, height| {
+            resize_source(source, width, height, ResizeMode::Fit, FilterType::Nearest)
+        };
+
+        let shrunk = shrink_to_fit(&source, None, None, 1000, resize);
+        assert!(visible_pixels(&shrunk) <= 1000);
+        let (width, height) = source_size(&shrunk);
+        assert!(
+            width > 30,
+            "shrunk further than needed to {}x{}",
+            width,
+            height
+        );
+        assert_eq!(height, width / 2);
+    }
+}
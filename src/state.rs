@@ -0,0 +1,94 @@
+//! Saving the arguments pingas was started with to a `--state` file, so the same
+//! drawing can be picked up again after a restart with `--resume`.
+
+use crate::app_error::AppError;
+use clap::ErrorKind;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Everything needed to start drawing the same thing again.
+#[derive(Debug, Serialize, Deserialize)]
+struct State {
+    /// The directory pingas was started from, since the arguments can contain
+    /// relative paths.
+    directory: PathBuf,
+    /// The arguments pingas was started with, without the program's name.
+    args: Vec<String>,
+}
+
+/// The command line arguments. When pingas is started with `--resume`, these
+/// are the arguments stored in that state file instead, and the working
+/// directory is changed to the one the state was saved from.
+pub fn args() -> Result<Vec<OsString>, AppError> {
+    let args: Vec<OsString> = env::args_os().collect();
+    let (path, num_args) = match resume_path(&args[1..]) {
+        Some(resume) => resume,
+        None => return Ok(args),
+    };
+    if args.len() - 1 != num_args {
+        return Err(AppError::usage(
+            "--resume can't be combined with any other arguments",
+            ErrorKind::ArgumentConflict,
+        ));
+    }
+
+    let error = |err: String| {
+        AppError::ReadState(format!("Can't resume from '{}':\n{}", path.display(), err))
+    };
+    let contents = fs::read_to_string(&path).map_err(|err| error(err.to_string()))?;
+    let state: State = serde_json::from_str(&contents).map_err(|err| error(err.to_string()))?;
+    env::set_current_dir(&state.directory).map_err(|err| {
+        error(format!(
+            "Can't change to the directory '{}': {}",
+            state.directory.display(),
+            err
+        ))
+    })?;
+
+    Ok(args
+        .into_iter()
+        .take(1)
+        .chain(state.args.into_iter().map(OsString::from))
+        .collect())
+}
+
+/// Save `args`, as returned by [`args()`], to `path` along with the current
+/// working directory.
+pub fn save(path: &Path, args: &[OsString]) -> Result<(), String> {
+    let error = |err: String| format!("Can't save the state to '{}':\n{}", path.display(), err);
+    let state = State {
+        directory: env::current_dir().map_err(|err| error(err.to_string()))?,
+        args: args[1..]
+            .iter()
+            .map(|arg| {
+                arg.clone()
+                    .into_string()
+                    .map_err(|arg| error(format!("{:?} is not valid UTF-8", arg)))
+            })
+            .collect::<Result<_, _>>()?,
+    };
+    let json = serde_json::to_string_pretty(&state).map_err(|err| error(err.to_string()))?;
+
+    fs::write(path, json + "\n").map_err(|err| error(err.to_string()))
+}
+
+/// The path passed to `--resume` along with the number of arguments that took
+/// up, if it was passed. Like with `--config` this has to be found before clap
+/// parses the arguments.
+fn resume_path(args: &[OsString]) -> Option<(PathBuf, usize)> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--resume" {
+            return args.next().map(|path| (PathBuf::from(path), 2));
+        } else if let Some(path) = arg.to_str().and_then(|arg| arg.strip_prefix("--resume=")) {
+            return Some((PathBuf::from(path), 1));
+        } else if arg == "--" {
+            break;
+        }
+    }
+
+    None
+}
@@ -0,0 +1,144 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Counters shared between a [`Canvas`](crate::Canvas) and its workers. This is
+/// cheap to clone, so it can be handed to another thread to monitor progress
+/// while drawing.
+#[derive(Debug, Clone, Default)]
+pub struct Stats {
+    counters: Arc<Counters>,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    packets_sent: AtomicU64,
+    unanswered: AtomicU64,
+    failed_sends: AtomicU64,
+    retried_sends: AtomicU64,
+    frames_shown: AtomicU64,
+    packets_per_pass: AtomicU64,
+    addresses_requested: AtomicU64,
+    addresses_computed: AtomicU64,
+    /// The bits of the `f64` rate limit, or 0 if there isn't one.
+    rate_limit: AtomicU64,
+}
+
+impl Stats {
+    /// The total number of ping packets sent so far. Packets that could not be
+    /// delivered are counted as well.
+    pub fn packets_sent(&self) -> u64 {
+        self.counters.packets_sent.load(Ordering::Relaxed)
+    }
+
+    /// The total number of pings that did not get a reply in time. Not every
+    /// screen replies to pings, so this is only meaningful for screens that
+    /// do.
+    pub fn unanswered(&self) -> u64 {
+        self.counters.unanswered.load(Ordering::Relaxed)
+    }
+
+    /// The total number of pings the raw backend failed to send, usually
+    /// because the send queues were full. fastping_rs only logs these errors,
+    /// so they aren't counted when using that backend. When retrying, this
+    /// only counts the pings that still could not be sent after the last
+    /// retry.
+    pub fn failed_sends(&self) -> u64 {
+        self.counters.failed_sends.load(Ordering::Relaxed)
+    }
+
+    /// The total number of pings the raw backend sent again after failing to
+    /// send them, see [`Canvas::with_retries()`](crate::Canvas::with_retries).
+    /// A ping that had to be retried twice is counted twice.
+    pub fn retried_sends(&self) -> u64 {
+        self.counters.retried_sends.load(Ordering::Relaxed)
+    }
+
+    /// The total number of images the workers have started drawing. This
+    /// includes every frame of an animation and every update of a live image.
+    pub fn frames_shown(&self) -> u64 {
+        self.counters.frames_shown.load(Ordering::Relaxed)
+    }
+
+    /// The number of packets it takes for every worker to ping all of its
+    /// addresses once, for the image that's currently being drawn. This is 0
+    /// until drawing starts.
+    pub fn packets_per_pass(&self) -> u64 {
+        self.counters.packets_per_pass.load(Ordering::Relaxed)
+    }
+
+    /// The total number of addresses the canvas has started computing,
+    /// including the ones that haven't been computed yet. Computing the
+    /// addresses for a large image can take a while, and together with
+    /// [`addresses_computed()`](Self::addresses_computed) this shows how far
+    /// along that is.
+    pub fn addresses_requested(&self) -> u64 {
+        self.counters.addresses_requested.load(Ordering::Relaxed)
+    }
+
+    /// The total number of addresses the canvas has finished computing.
+    pub fn addresses_computed(&self) -> u64 {
+        self.counters.addresses_computed.load(Ordering::Relaxed)
+    }
+
+    /// The number of packets per second that are currently allowed to be sent,
+    /// if the rate is limited. This changes over time when ramping up or when
+    /// using an adaptive rate.
+    pub fn rate_limit(&self) -> Option<f64> {
+        match self.counters.rate_limit.load(Ordering::Relaxed) {
+            0 => None,
+            bits => Some(f64::from_bits(bits)),
+        }
+    }
+
+    pub(crate) fn add_packets_sent(&self, packets: usize) {
+        self.counters
+            .packets_sent
+            .fetch_add(packets as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_unanswered(&self, packets: usize) {
+        self.counters
+            .unanswered
+            .fetch_add(packets as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_failed_sends(&self, packets: usize) {
+        self.counters
+            .failed_sends
+            .fetch_add(packets as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_retried_sends(&self, packets: usize) {
+        self.counters
+            .retried_sends
+            .fetch_add(packets as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_frame_shown(&self) {
+        self.counters.frames_shown.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_packets_per_pass(&self, packets: usize) {
+        self.counters
+            .packets_per_pass
+            .store(packets as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_addresses_requested(&self, addresses: usize) {
+        self.counters
+            .addresses_requested
+            .fetch_add(addresses as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_addresses_computed(&self, addresses: usize) {
+        self.counters
+            .addresses_computed
+            .fetch_add(addresses as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_rate_limit(&self, rate: f64) {
+        self.counters
+            .rate_limit
+            .store(rate.to_bits(), Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,261 @@
+//! Reading a stream of frames for `--stream`. By default every frame starts
+//! with its length in bytes as a 32-bit big endian integer, followed by an
+//! image in any of the supported formats. With `--raw-frames` the stream
+//! instead consists of uncompressed RGBA frames of a fixed size, with nothing
+//! in between them. That's what `ffmpeg -f rawvideo -pix_fmt rgba` outputs.
+
+use image::{ImageError, RgbaImage};
+use pingas::decode_image;
+use std::fs::File;
+use std::io::{self, ErrorKind, Read};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+
+/// Frames larger than this are rejected, since a length prefix this large
+/// means the stream is out of sync or something else entirely got piped in.
+/// `--raw-frames` sizes that would exceed this are rejected up front.
+pub const MAX_FRAME_SIZE: usize = 256 * 1024 * 1024;
+
+/// How the frames in a stream are separated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Framing {
+    /// Every frame is an encoded image prefixed by its length.
+    LengthPrefixed,
+    /// Every frame is exactly `width * height * 4` bytes of RGBA pixels.
+    Raw { width: u32, height: u32 },
+}
+
+/// Frames that are being read and decoded in the background.
+pub struct FrameStream {
+    frames: Receiver<RgbaImage>,
+}
+
+impl FrameStream {
+    /// Start reading frames from the file or named pipe at `path`, or from
+    /// STDIN if `path` is `-`. Frames that can't be decoded get skipped. The
+    /// stream ends when the other side closes it.
+    pub fn open(path: &str, framing: Framing) -> io::Result<Self> {
+        let mut input: Box<dyn Read + Send> = if path == "-" {
+            Box::new(io::stdin())
+        } else {
+            Box::new(File::open(path)?)
+        };
+
+        let (sender, frames) = channel();
+        thread::spawn(move || loop {
+            match read_frame(&mut input, framing) {
+                Ok(Some(frame)) => {
+                    if sender.send(frame).is_err() {
+                        break;
+                    }
+                }
+                Ok(None) => {
+                    log::info!("The stream has ended, the last frame will stay on screen");
+                    break;
+                }
+                Err(ImageError::IoError(err)) => {
+                    log::warn!("Can't read from the stream:\n{}", err);
+                    break;
+                }
+                Err(err) => log::warn!("Skipping a frame that can't be decoded:\n{}", err),
+            }
+        });
+
+        Ok(FrameStream { frames })
+    }
+
+    /// Wait for the next frame. Returns `None` if the stream has ended.
+    pub fn wait_for_frame(&self) -> Option<RgbaImage> {
+        self.frames.recv().ok()
+    }
+
+    /// The most recent frame that has arrived since the last call, if any.
+    /// Frames that arrived before that are dropped since drawing them would
+    /// only add latency. This does not block.
+    pub fn latest(&self) -> Option<RgbaImage> {
+        self.frames.try_iter().last()
+    }
+}
+
+/// Read and decode a single frame. Returns `None` when the stream ends before
+/// the next frame starts. Reads that only return part of a frame are simply
+/// continued, but the stream ending in the middle of a frame is an error.
+fn read_frame(input: &mut impl Read, framing: Framing) -> Result<Option<RgbaImage>, ImageError> {
+    // `--raw-frames` sizes are checked against `MAX_FRAME_SIZE` when parsing
+    // the command line, so this mostly catches out of sync length prefixes
+    let length = match framing {
+        Framing::LengthPrefixed => {
+            let mut prefix = [0; 4];
+            if !read_exact_or_end(input, &mut prefix)? {
+                return Ok(None);
+            }
+
+            u32::from_be_bytes(prefix) as usize
+        }
+        Framing::Raw { width, height } => width as usize * height as usize * 4,
+    };
+
+    if length > MAX_FRAME_SIZE {
+        return Err(ImageError::from(io::Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Got a frame of {} bytes, the stream is probably corrupt",
+                length
+            ),
+        )));
+    }
+
+    let mut data = vec![0; length];
+    match (read_exact_or_end(input, &mut data)?, framing) {
+        (true, _) => (),
+        // The length prefix has already been read, so the frame can't be missing
+        (false, Framing::LengthPrefixed) => return Err(ImageError::from(end_of_stream())),
+        (false, Framing::Raw { .. }) => return Ok(None),
+    }
+
+    match framing {
+        Framing::LengthPrefixed => {
+            let format = image::guess_format(&data)?;
+            Ok(Some(decode_image(&data, format)?.to_rgba()))
+        }
+        Framing::Raw { width, height } => Ok(RgbaImage::from_raw(width, height, data)),
+    }
+}
+
+/// Fill `buffer` completely. Returns `false` if the stream ended before
+/// anything could be read, and an error if it ended halfway through.
+fn read_exact_or_end(input: &mut impl Read, buffer: &mut [u8]) -> io::Result<bool> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match input.read(&mut buffer[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(end_of_stream()),
+            Ok(read) => filled += read,
+            Err(err) if err.kind() == ErrorKind::Interrupted => (),
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(true)
+}
+
+fn end_of_stream() -> io::Error {
+    io::Error::new(
+        ErrorKind::UnexpectedEof,
+        "The stream ended in the middle of a frame",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::png::PNGEncoder;
+    use image::{ColorType, Rgba};
+
+    /// A reader that returns at most `chunk_size` bytes per read, like a pipe
+    /// that's being written to in small pieces.
+    struct ShortReads<'a> {
+        data: &'a [u8],
+        chunk_size: usize,
+    }
+
+    impl Read for ShortReads<'_> {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            let read = self.data.len().min(self.chunk_size).min(buffer.len());
+            buffer[..read].copy_from_slice(&self.data[..read]);
+            self.data = &self.data[read..];
+            Ok(read)
+        }
+    }
+
+    fn short_reads(data: &[u8]) -> ShortReads<'_> {
+        ShortReads {
+            data,
+            chunk_size: 3,
+        }
+    }
+
+    /// A 2x1 PNG image prefixed by its length.
+    fn prefixed_frame() -> Vec<u8> {
+        let mut png = Vec::new();
+        PNGEncoder::new(&mut png)
+            .encode(&[255, 0, 0, 255, 0, 0, 255, 128], 2, 1, ColorType::RGBA(8))
+            .unwrap();
+
+        let mut data = (png.len() as u32).to_be_bytes().to_vec();
+        data.extend(png);
+        data
+    }
+
+    fn is_unexpected_eof(result: Result<Option<RgbaImage>, ImageError>) -> bool {
+        matches!(result, Err(ImageError::IoError(err)) if err.kind() == ErrorKind::UnexpectedEof)
+    }
+
+    #[test]
+    fn reads_length_prefixed_frames() {
+        let data = [prefixed_frame(), prefixed_frame()].concat();
+        let mut input = short_reads(&data);
+        for _ in 0..2 {
+            let frame = read_frame(&mut input, Framing::LengthPrefixed)
+                .unwrap()
+                .unwrap();
+            assert_eq!(frame.dimensions(), (2, 1));
+            assert_eq!(*frame.get_pixel(1, 0), Rgba([0, 0, 255, 128]));
+        }
+
+        // The stream ending between two frames is not an error
+        assert!(read_frame(&mut input, Framing::LengthPrefixed)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn rejects_truncated_frames() {
+        let data = prefixed_frame();
+        for end in &[2, 4, data.len() - 1] {
+            let mut input = short_reads(&data[..*end]);
+            assert!(
+                is_unexpected_eof(read_frame(&mut input, Framing::LengthPrefixed)),
+                "the stream ended after {} bytes",
+                end
+            );
+        }
+
+        let mut input = short_reads(&[0; 10]);
+        let framing = Framing::Raw {
+            width: 2,
+            height: 2,
+        };
+        assert!(is_unexpected_eof(read_frame(&mut input, framing)));
+    }
+
+    #[test]
+    fn rejects_oversized_frames() {
+        let data = (MAX_FRAME_SIZE as u32 + 1).to_be_bytes();
+        let mut input = short_reads(&data);
+        assert!(matches!(
+            read_frame(&mut input, Framing::LengthPrefixed),
+            Err(ImageError::IoError(err)) if err.kind() == ErrorKind::InvalidData
+        ));
+    }
+
+    #[test]
+    fn reads_raw_frames() {
+        let data: Vec<u8> = (0..32).collect();
+        let mut input = short_reads(&data);
+        let framing = Framing::Raw {
+            width: 2,
+            height: 2,
+        };
+        for first in &[0, 16] {
+            let frame = read_frame(&mut input, framing).unwrap().unwrap();
+            assert_eq!(frame.dimensions(), (2, 2));
+            assert_eq!(
+                *frame.get_pixel(1, 1),
+                Rgba([first + 12, first + 13, first + 14, first + 15])
+            );
+        }
+
+        assert!(read_frame(&mut input, framing).unwrap().is_none());
+    }
+}
@@ -0,0 +1,163 @@
+//! Rasterizing SVG images. Vector images can be rendered at any size, so they
+//! get rasterized at the size they'll be drawn at instead of being scaled
+//! afterwards.
+
+use image::RgbaImage;
+
+use crate::canvas::ResizeMode;
+use crate::error::Error;
+
+/// The resolution SVG units are defined at. Passing this to [`rasterize_svg()`]
+/// renders an SVG image at its natural size.
+pub const SVG_DPI: f32 = 96.0;
+
+/// Check whether `data` looks like an SVG image. SVG images are XML documents,
+/// so this looks for an `<svg` tag near the start of the document.
+pub fn is_svg(data: &[u8]) -> bool {
+    let start = String::from_utf8_lossy(&data[..data.len().min(4096)]);
+    start
+        .trim_start_matches('\u{feff}')
+        .trim_start()
+        .starts_with('<')
+        && start.contains("<svg")
+}
+
+/// Rasterize an SVG image. Without a `width` the image is rendered at its
+/// natural size at `dpi` dots per inch. With a `width` the image is rendered at
+/// the exact size [`resize_image()`](crate::resize_image) would resize it to
+/// with the same options, so it never has to be scaled as a bitmap.
+#[cfg(feature = "svg")]
+pub fn rasterize_svg(
+    data: &[u8],
+    width: Option<u32>,
+    height: Option<u32>,
+    mode: ResizeMode,
+    dpi: f32,
+) -> Result<RgbaImage, Error> {
+    use resvg::{tiny_skia, usvg};
+
+    let tree = usvg::Tree::from_data(data, &usvg::Options::default())
+        .map_err(|err| Error::Svg(err.to_string()))?;
+    let (svg_width, svg_height) = (tree.size().width(), tree.size().height());
+    let uniform = |scale: f32| (scale, scale);
+    let (scale_x, scale_y) = match (width, height, mode) {
+        (None, ..) => uniform(dpi / SVG_DPI),
+        (Some(width), None, _) => uniform(width as f32 / svg_width),
+        (Some(width), Some(height), ResizeMode::Fit) => {
+            uniform((width as f32 / svg_width).min(height as f32 / svg_height))
+        }
+        (Some(width), Some(height), ResizeMode::Fill) => {
+            uniform((width as f32 / svg_width).max(height as f32 / svg_height))
+        }
+        (Some(width), Some(height), ResizeMode::Stretch) => {
+            (width as f32 / svg_width, height as f32 / svg_height)
+        }
+    };
+
+    // When filling the box the center of the scaled image gets cropped to the
+    // size of the box
+    let scaled_size = (svg_width * scale_x, svg_height * scale_y);
+    let (pixmap_width, pixmap_height) = match (width, height, mode) {
+        (Some(width), Some(height), ResizeMode::Fill) => (width, height),
+        _ => (
+            (scaled_size.0.round() as u32).max(1),
+            (scaled_size.1.round() as u32).max(1),
+        ),
+    };
+    let mut pixmap = tiny_skia::Pixmap::new(pixmap_width, pixmap_height).ok_or_else(|| {
+        Error::Svg(format!(
+            "Can't rasterize the image at {}x{} pixels",
+            pixmap_width, pixmap_height
+        ))
+    })?;
+    let transform = tiny_skia::Transform::from_scale(scale_x, scale_y).post_translate(
+        (pixmap_width as f32 - scaled_size.0) / 2.0,
+        (pixmap_height as f32 - scaled_size.1) / 2.0,
+    );
+    resvg::render(&tree, transform, &mut pixmap.as_mut());
+
+    // tiny-skia stores its pixels with premultiplied alpha
+    let pixels = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.red(), color.green(), color.blue(), color.alpha()]
+        })
+        .collect();
+
+    Ok(RgbaImage::from_raw(pixmap_width, pixmap_height, pixels).unwrap())
+}
+
+/// Rasterizing SVG images requires the `svg` feature, so without it SVG images
+/// are rejected with an error saying so.
+#[cfg(not(feature = "svg"))]
+pub fn rasterize_svg(
+    _data: &[u8],
+    _width: Option<u32>,
+    _height: Option<u32>,
+    _mode: ResizeMode,
+    _dpi: f32,
+) -> Result<RgbaImage, Error> {
+    Err(Error::MissingFeature {
+        format: "SVG",
+        feature: "svg",
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SQUARE: &[u8] = br##"<?xml version="1.0" encoding="UTF-8"?>
+<svg xmlns="http://www.w3.org/2000/svg" width="20" height="10">
+  <rect width="20" height="10" fill="#ff0000"/>
+</svg>"##;
+
+    #[test]
+    fn detects_svg_images() {
+        assert!(is_svg(SQUARE));
+        assert!(is_svg(b"\xef\xbb\xbf  <svg></svg>"));
+        assert!(!is_svg(b"\x89PNG\r\n\x1a\n"));
+        assert!(!is_svg(b"<html><body></body></html>"));
+        assert!(!is_svg(b""));
+    }
+
+    #[cfg(feature = "svg")]
+    #[test]
+    fn rasterizes_at_the_drawn_size() {
+        let natural = rasterize_svg(SQUARE, None, None, ResizeMode::Fit, SVG_DPI).unwrap();
+        assert_eq!(natural.dimensions(), (20, 10));
+        assert_eq!(*natural.get_pixel(10, 5), image::Rgba([255, 0, 0, 255]));
+
+        let sized = |width, height, mode| {
+            rasterize_svg(SQUARE, Some(width), height, mode, SVG_DPI)
+                .unwrap()
+                .dimensions()
+        };
+        assert_eq!(sized(40, None, ResizeMode::Fit), (40, 20));
+        assert_eq!(sized(40, Some(40), ResizeMode::Fit), (40, 20));
+        assert_eq!(sized(40, Some(40), ResizeMode::Fill), (40, 40));
+        assert_eq!(sized(40, Some(40), ResizeMode::Stretch), (40, 40));
+    }
+
+    #[cfg(feature = "svg")]
+    #[test]
+    fn rejects_malformed_svg_images() {
+        let result = rasterize_svg(b"<svg><rect", None, None, ResizeMode::Fit, SVG_DPI);
+        assert!(matches!(result, Err(Error::Svg(_))));
+    }
+
+    #[cfg(not(feature = "svg"))]
+    #[test]
+    fn rejects_svg_images_without_the_feature() {
+        let result = rasterize_svg(SQUARE, Some(40), None, ResizeMode::Fit, SVG_DPI);
+        assert!(matches!(
+            result,
+            Err(Error::MissingFeature {
+                format: "SVG",
+                feature: "svg"
+            })
+        ));
+    }
+}
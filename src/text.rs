@@ -0,0 +1,70 @@
+use image::{Rgba, RgbaImage};
+use rusttype::{point, Font, Scale};
+
+use crate::error::Error;
+
+/// Rasterizes text using a TrueType or OpenType font.
+pub struct TextRenderer {
+    font: Font<'static>,
+}
+
+impl TextRenderer {
+    /// Load a font from the contents of a `.ttf` or `.otf` file.
+    pub fn new(font_data: Vec<u8>) -> Result<Self, Error> {
+        let font = Font::from_bytes(font_data).map_err(|err| Error::Font(err.to_string()))?;
+
+        Ok(TextRenderer { font })
+    }
+
+    /// Render `text` with lines that are `size` pixels high. The returned image
+    /// is exactly large enough to fit the text, and everything except for the
+    /// text itself is transparent. Antialiased edges are drawn by scaling
+    /// `color`'s alpha channel.
+    pub fn render(&self, text: &str, size: f32, color: Rgba<u8>) -> RgbaImage {
+        let scale = Scale::uniform(size);
+        let v_metrics = self.font.v_metrics(scale);
+        let line_height = v_metrics.ascent - v_metrics.descent + v_metrics.line_gap;
+
+        let glyphs: Vec<_> = text
+            .lines()
+            .enumerate()
+            .flat_map(|(line, text)| {
+                let baseline = v_metrics.ascent + line as f32 * line_height;
+                self.font.layout(text, scale, point(0.0, baseline))
+            })
+            .collect();
+
+        let width = glyphs
+            .iter()
+            .filter_map(|glyph| glyph.pixel_bounding_box())
+            .map(|bounds| bounds.max.x.max(0) as u32)
+            .max()
+            .unwrap_or(0);
+        let num_lines = text.lines().count().max(1);
+        let height = (num_lines as f32 * line_height).ceil() as u32;
+
+        let Rgba([r, g, b, a]) = color;
+        let mut image = RgbaImage::new(width, height);
+        for glyph in &glyphs {
+            if let Some(bounds) = glyph.pixel_bounding_box() {
+                glyph.draw(|x, y, coverage| {
+                    let x = bounds.min.x + x as i32;
+                    let y = bounds.min.y + y as i32;
+                    if x < 0 || y < 0 || x as u32 >= width || y as u32 >= height {
+                        return;
+                    }
+
+                    // Glyphs can overlap slightly, so we'll keep the most
+                    // opaque value
+                    let alpha = (coverage * f32::from(a)).round() as u8;
+                    let pixel = image.get_pixel_mut(x as u32, y as u32);
+                    if alpha > pixel[3] {
+                        *pixel = Rgba([r, g, b, alpha]);
+                    }
+                });
+            }
+        }
+
+        image
+    }
+}
@@ -0,0 +1,91 @@
+use image::{Rgba, RgbaImage};
+use std::time::Duration;
+
+use crate::animation::{Frame, CLEAR_COLOR};
+
+/// How long every intermediate image of a fade is shown for. Shorter steps look
+/// smoother, but every step has to be sent to the screen.
+const FADE_STEP: Duration = Duration::from_millis(50);
+
+/// How the progress of a transition changes over time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Easing {
+    /// Blend at a constant speed.
+    #[default]
+    Linear,
+    /// Start and end slowly, and blend the fastest halfway through.
+    EaseInOut,
+}
+
+impl Easing {
+    /// Map the fraction of the transition's duration that has passed to the
+    /// fraction of the new image that should be visible. Both are between 0
+    /// and 1.
+    pub fn apply(self, progress: f64) -> f64 {
+        match self {
+            Easing::Linear => progress,
+            Easing::EaseInOut => progress * progress * (3.0 - 2.0 * progress),
+        }
+    }
+}
+
+/// A fade from one image to the next when switching between the frames of an
+/// animation or when an image gets replaced, as set with
+/// [`Canvas::with_transition()`](crate::Canvas::with_transition).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transition {
+    /// How long it takes for the old image to be completely replaced.
+    pub duration: Duration,
+    pub easing: Easing,
+}
+
+impl Transition {
+    /// The images shown between `from` and `to`, not including `from` and
+    /// `to` themselves. Both images are blended with [`CLEAR_COLOR`] where the
+    /// other image has a pixel and they don't, since that's what erasing those
+    /// pixels would draw. Pixels that are transparent in both images stay
+    /// transparent.
+    pub(crate) fn frames(&self, from: &RgbaImage, to: &RgbaImage) -> Vec<Frame> {
+        let steps = ((self.duration.as_secs_f64() / FADE_STEP.as_secs_f64()).round() as u32).max(1);
+        let delay = self.duration / steps;
+        let pixel_at = |image: &RgbaImage, x: u32, y: u32| {
+            if x < image.width() && y < image.height() && image.get_pixel(x, y)[3] > 0 {
+                Some(*image.get_pixel(x, y))
+            } else {
+                None
+            }
+        };
+
+        (1..steps)
+            .map(|step| {
+                let amount = self.easing.apply(f64::from(step) / f64::from(steps));
+                let image = RgbaImage::from_fn(
+                    from.width().max(to.width()),
+                    from.height().max(to.height()),
+                    |x, y| match (pixel_at(from, x, y), pixel_at(to, x, y)) {
+                        (None, None) => Rgba([0, 0, 0, 0]),
+                        (old, new) => blend(
+                            old.unwrap_or(CLEAR_COLOR),
+                            new.unwrap_or(CLEAR_COLOR),
+                            amount,
+                        ),
+                    },
+                );
+
+                Frame { image, delay }
+            })
+            .collect()
+    }
+}
+
+/// Linearly interpolate between two colors, including their alpha channels.
+fn blend(old: Rgba<u8>, new: Rgba<u8>, amount: f64) -> Rgba<u8> {
+    let mut result = old;
+    for channel in 0..4 {
+        let old = f64::from(old[channel]);
+        let new = f64::from(new[channel]);
+        result[channel] = (old + (new - old) * amount).round() as u8;
+    }
+
+    result
+}
@@ -0,0 +1,54 @@
+//! Watching the image for changes for `--watch`.
+
+use notify::{watcher, DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use std::time::Duration;
+
+/// Editors often write a file in several steps, so changes are only reported
+/// once the file hasn't been touched for this long.
+const DEBOUNCE_DELAY: Duration = Duration::from_millis(250);
+
+/// Reports when a single file has been modified.
+pub struct FileWatcher {
+    /// The watcher stops watching when it gets dropped, so it has to be kept
+    /// around.
+    _watcher: RecommendedWatcher,
+    events: Receiver<DebouncedEvent>,
+    /// The canonical path to the file being watched.
+    path: PathBuf,
+}
+
+impl FileWatcher {
+    /// Start watching `path`. Many editors save files by writing to a
+    /// temporary file and renaming that over the original, so this watches
+    /// the directory containing the file instead of the file itself.
+    pub fn new(path: &Path) -> Result<Self, notify::Error> {
+        let path = path.canonicalize().map_err(notify::Error::Io)?;
+        let (sender, events) = channel();
+        let mut watcher = watcher(sender, DEBOUNCE_DELAY)?;
+        watcher.watch(path.parent().unwrap(), RecursiveMode::NonRecursive)?;
+
+        Ok(FileWatcher {
+            _watcher: watcher,
+            events,
+            path,
+        })
+    }
+
+    /// Whether the file has been modified since the last time this was called.
+    /// This does not block.
+    pub fn changed(&self) -> bool {
+        // All pending events have to be drained, so this can't short circuit
+        self.events
+            .try_iter()
+            .filter(|event| match event {
+                DebouncedEvent::Create(path)
+                | DebouncedEvent::Write(path)
+                | DebouncedEvent::Rename(_, path) => *path == self.path,
+                _ => false,
+            })
+            .count()
+            > 0
+    }
+}
@@ -0,0 +1,95 @@
+//! Decoding WebP images through libwebp. `image` can only decode lossy WebP
+//! images without an alpha channel, so with the `webp` feature every kind of
+//! WebP image gets decoded with libwebp instead, including animations.
+
+use image::ImageError;
+
+use crate::animation::Frame;
+#[cfg(not(feature = "webp"))]
+use crate::error::Error;
+
+/// Decode a WebP image into its frames. Still images decode to a single frame.
+/// Every frame has the size of the entire image, just like with
+/// [`decode_gif()`](crate::decode_gif).
+#[cfg(feature = "webp")]
+pub fn decode_webp(data: &[u8]) -> Result<Vec<Frame>, ImageError> {
+    use image::{DynamicImage, RgbImage, RgbaImage};
+    use std::time::Duration;
+
+    let animation = libwebp::AnimDecoder::new(data)
+        .decode()
+        .map_err(ImageError::FormatError)?;
+
+    let mut frames = Vec::new();
+    let mut previous_timestamp = 0;
+    for frame in &animation {
+        let (width, height) = (frame.width(), frame.height());
+        let pixels = frame.get_image().to_vec();
+        // libwebp leaves out the alpha channel for images that don't use it
+        let image = if frame.get_layout().is_alpha() {
+            RgbaImage::from_raw(width, height, pixels)
+        } else {
+            RgbImage::from_raw(width, height, pixels)
+                .map(|image| DynamicImage::ImageRgb8(image).to_rgba())
+        }
+        .ok_or(ImageError::DimensionError)?;
+
+        // The timestamps mark the end of every frame
+        let timestamp = frame.get_time_ms();
+        let delay = Duration::from_millis((timestamp - previous_timestamp).max(0) as u64);
+        previous_timestamp = timestamp;
+        frames.push(Frame { image, delay });
+    }
+
+    if frames.is_empty() {
+        Err(ImageError::ImageEnd)
+    } else {
+        Ok(frames)
+    }
+}
+
+/// Without the `webp` feature only the WebP images `image` supports can be
+/// decoded, and those don't go through here.
+#[cfg(not(feature = "webp"))]
+pub fn decode_webp(_data: &[u8]) -> Result<Vec<Frame>, ImageError> {
+    let err = Error::MissingFeature {
+        format: "WebP",
+        feature: "webp",
+    };
+    Err(ImageError::UnsupportedError(err.to_string()))
+}
+
+#[cfg(all(test, feature = "webp"))]
+mod tests {
+    use super::*;
+    use image::Rgba;
+    use std::time::Duration;
+
+    #[test]
+    fn decodes_lossless_images_with_alpha() {
+        let pixels = [255, 0, 0, 255, 0, 0, 255, 128];
+        let data = libwebp::Encoder::from_rgba(&pixels, 2, 1).encode_lossless();
+
+        let frames = decode_webp(&data).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(*frames[0].image.get_pixel(0, 0), Rgba([255, 0, 0, 255]));
+        assert_eq!(*frames[0].image.get_pixel(1, 0), Rgba([0, 0, 255, 128]));
+    }
+
+    #[test]
+    fn decodes_animations() {
+        let red = [255, 0, 0, 255];
+        let blue = [0, 0, 255, 255];
+        let config = libwebp::WebPConfig::new().unwrap();
+        let mut encoder = libwebp::AnimEncoder::new(1, 1, &config);
+        encoder.add_frame(libwebp::AnimFrame::from_rgba(&red, 1, 1, 0));
+        encoder.add_frame(libwebp::AnimFrame::from_rgba(&blue, 1, 1, 100));
+        let data = encoder.encode();
+
+        let frames = decode_webp(&data).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(*frames[0].image.get_pixel(0, 0), Rgba(red));
+        assert_eq!(*frames[1].image.get_pixel(0, 0), Rgba(blue));
+        assert_eq!(frames[0].delay, Duration::from_millis(100));
+    }
+}